@@ -1,251 +1,243 @@
 //!一些处理数组的函数
 
-use crate::calc_exp::Eval;
+use crate::calc_exp::eval_const;
 use crate::ds_for_ir::GenerateIrInfo;
+use crate::error::{CompileError, Result};
+use crate::gen_ir::convert;
+use crate::gen_ir::ExpResult;
 use crate::symbol_table::ArrayInfoBase;
+use crate::symbol_table::ConstValue;
+use crate::symbol_table::ParamKind;
 use crate::symbol_table::SymbolInfo::Array;
 use crate::{ast::*, gen_ir::GenerateIR};
-use std::{fs::File, io::Write};
+use koopa::ir::Program;
+use std::rc::Rc;
+
+///计算数组的一个维度，维度必须是正的整数常量
+pub fn eval_dim(dim: &ConstExp, info: &mut GenerateIrInfo) -> Result<i32> {
+    match eval_const(dim, dim.span(), info, "数组维度")? {
+        ConstValue::Float(_) => Err(CompileError::semantic(
+            dim.span(),
+            "数组维度必须是整数".to_string(),
+        )),
+        ConstValue::Int(val) if val > 0 => Ok(val),
+        ConstValue::Int(val) => Err(CompileError::semantic(
+            dim.span(),
+            format!("数组维度必须为正数，实际为{}", val),
+        )),
+    }
+}
+
+///计算函数形参的种类，数组形参的第一维省略不计
+pub fn eval_param_kind(param: &FuncFParam, info: &mut GenerateIrInfo) -> Result<ParamKind> {
+    match (&param.dims, param.btype) {
+        (None, BType::Int) => Ok(ParamKind::Int),
+        (None, BType::Float) => Ok(ParamKind::Float),
+        (Some(dims), btype) => {
+            let mut real_dims: Vec<i32> = vec![];
+            for dim in dims {
+                real_dims.push(eval_dim(dim, info)?);
+            }
+            Ok(ParamKind::Array(btype, real_dims))
+        }
+    }
+}
 
 ///数组定义处理接口
 pub trait GenDefDim {
-    ///生成数组维度声明
-    fn gen_def_dim(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> Vec<i32>;
+    ///计算数组维度并插入符号表，alloc由调用者按全局或局部生成
+    fn gen_def_dim(&self, info: &mut GenerateIrInfo) -> Result<Vec<i32>>;
 }
 
 impl GenDefDim for ConstDef {
-    fn gen_def_dim(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> Vec<i32> {
+    fn gen_def_dim(&self, info: &mut GenerateIrInfo) -> Result<Vec<i32>> {
         //遍历dims，计算数组维度大小
         let mut real_dims: Vec<i32> = vec![];
         for dim in &self.dims {
-            real_dims.push(dim.eval(info).expect("数组维度中出现非常量表达式"));
+            real_dims.push(eval_dim(dim, info)?);
         }
+        //常量数组的初值在编译期就能确定，展平后一并存入符号表，便于折叠a[i][j]
+        let btype = info.decl_type;
+        let mut init: Vec<i32> = vec![];
+        self.const_init_val
+            .global_array_init(info, &real_dims, btype, self.span, &mut init)?;
         //插入符号表，标明是数组
         info.insert_symbol(
             self.ident.clone(),
             Array(ArrayInfoBase {
+                btype,
                 dims: real_dims.clone(), //borrow
+                const_init: Some(Rc::new(init)),
             }),
-        );
-        //生成维度声明
-        match info.is_global_symbol(&self.ident) {
-            true => write!(output, "global @{} = alloc ", info.get_name(&self.ident)).unwrap(),
-            false => write!(output, "  @{} = alloc ", info.get_name(&self.ident)).unwrap(),
-        }
-        let left = "[".to_string().repeat(real_dims.len());
-        write!(output, "{}i32", left).unwrap();
-        for dim in real_dims.iter().rev() {
-            write!(output, ", {}]", dim).unwrap();
-        }
-        real_dims
+            self.span,
+        )?;
+        Ok(real_dims)
     }
 }
 
 impl GenDefDim for VarDef {
-    fn gen_def_dim(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> Vec<i32> {
+    fn gen_def_dim(&self, info: &mut GenerateIrInfo) -> Result<Vec<i32>> {
         //遍历dims，计算数组维度大小
         let mut real_dims: Vec<i32> = vec![];
         for dim in &self.dims {
-            real_dims.push(dim.eval(info).expect("数组维度中出现非常量表达式"));
+            real_dims.push(eval_dim(dim, info)?);
         }
         //插入符号表，标明是数组
         info.insert_symbol(
             self.ident.clone(),
             Array(ArrayInfoBase {
+                btype: info.decl_type,
                 dims: real_dims.clone(), //borrow
+                const_init: None,
             }),
-        );
-        //生成维度声明
-        match info.is_global_symbol(&self.ident) {
-            true => write!(output, "global @{} = alloc ", info.get_name(&self.ident)).unwrap(),
-            false => write!(output, "  @{} = alloc ", info.get_name(&self.ident)).unwrap(),
-        }
-        let left = "[".to_string().repeat(real_dims.len());
-        write!(output, "{}i32", left).unwrap();
-        for dim in real_dims.iter().rev() {
-            write!(output, ", {}]", dim).unwrap();
+            self.span,
+        )?;
+        Ok(real_dims)
+    }
+}
+
+///初始化列表中从第filled个元素开始的嵌套列表对应的子数组维度
+///嵌套列表必须对齐到某一维的边界，在列表开头时对应去掉第一维的子数组，不能对应单个元素
+fn nested_dims(dims: &[i32], filled: usize, span: Span) -> Result<&[i32]> {
+    let mut align_dim = dims.len(); //对齐到哪个维度
+    let mut align_size: usize = 1; //对齐到的维度对应大小
+    for it in dims.iter().rev() {
+        align_size *= *it as usize;
+        if filled.is_multiple_of(align_size) {
+            align_dim -= 1;
+        } else {
+            break;
         }
-        real_dims
     }
+    //初始值，啥都没填充，对齐到第一维
+    let align_dim = align_dim.max(1);
+    if align_dim >= dims.len() {
+        return Err(CompileError::semantic(
+            span,
+            "初始化列表中的嵌套列表没有对齐到数组的某一维".to_string(),
+        ));
+    }
+    Ok(&dims[align_dim..])
 }
 
-///对全局数组的初始化
+///初始化列表对应的数组大小，给出的元素不能比它多
+fn array_size(dims: &[i32], filled: usize, span: Span) -> Result<usize> {
+    let size = dims.iter().product::<i32>() as usize;
+    if filled > size {
+        return Err(CompileError::semantic(
+            span,
+            format!("初始化列表中有{}个元素，超过了数组的大小{}", filled, size),
+        ));
+    }
+    Ok(size)
+}
+
+///对全局数组的初始化，result中是转换成btype后在IR中的表示
 pub trait GlobalArrayInit {
     fn global_array_init(
         &self,
-        output: &mut dyn Write,
         info: &mut GenerateIrInfo,
         dims: &[i32],
+        btype: BType,
+        span: Span,
         result: &mut Vec<i32>,
-    );
+    ) -> Result<()>;
 }
 
 impl GlobalArrayInit for ConstInitVal {
     fn global_array_init(
         &self,
-        output: &mut dyn Write,
         info: &mut GenerateIrInfo,
         dims: &[i32],
+        btype: BType,
+        span: Span,
         result: &mut Vec<i32>,
-    ) {
+    ) -> Result<()> {
         match self {
             ConstInitVal::ConstExp(exp) => {
-                let val = exp.eval(info).expect("数组初始化中出现非常量表达式");
-                result.push(val);
+                let val = eval_const(exp, exp.span(), info, "常量数组的初值")?;
+                result.push(val.convert(btype).to_word());
             }
             ConstInitVal::ConstInitValS(vals) => {
                 let pre_filled = result.len();
                 for val in vals {
-                    let now_filled = result.len();
-                    let mut align_dim = dims.len(); //对齐到哪个维度 TODO:check
-
-                    let mut align_size: usize = 1; //对齐到的维度对应大小
-
-                    for it in dims.iter().rev() {
-                        align_size *= *it as usize;
-                        if now_filled % align_size == 0 {
-                            align_dim -= 1;
-                        } else {
-                            break;
-                        }
-                    }
-
-                    if align_dim == 0 {
-                        //初始值，啥都没填充，对齐到第一维
-                        align_dim = 1;
-                    }
-
-                    val.global_array_init(
-                        output,
-                        info,
-                        &dims[align_dim..dims.len()], //切片的右面是开区间
-                        result,
-                    );
-                }
-                let fin_filled = result.len();
-                let required_size = dims.iter().fold(1, |acc, x| acc * x) as usize;
-                for _ in (fin_filled - pre_filled)..required_size {
-                    result.push(0);
+                    let sub_dims = match val {
+                        ConstInitVal::ConstExp(_) => &[][..],
+                        ConstInitVal::ConstInitValS(_) => nested_dims(dims, result.len(), span)?,
+                    };
+                    val.global_array_init(info, sub_dims, btype, span, result)?;
                 }
+                let required_size = array_size(dims, result.len() - pre_filled, span)?;
+                result.resize(pre_filled + required_size, 0);
             }
         }
+        Ok(())
     }
 }
 
 impl GlobalArrayInit for InitVal {
     fn global_array_init(
         &self,
-        output: &mut dyn Write,
         info: &mut GenerateIrInfo,
         dims: &[i32],
+        btype: BType,
+        span: Span,
         result: &mut Vec<i32>,
-    ) {
+    ) -> Result<()> {
         match self {
             InitVal::Exp(exp) => {
-                let val = exp.eval(info).expect("全局数组初始化中出现非常量表达式");
-                result.push(val);
+                let val = eval_const(exp, exp.span(), info, "全局数组的初值")?;
+                result.push(val.convert(btype).to_word());
             }
             InitVal::InitValS(vals) => {
                 let pre_filled = result.len();
                 for val in vals {
-                    let now_filled = result.len();
-                    let mut align_dim = dims.len(); //对齐到哪个维度 TODO:check
-
-                    let mut align_size: usize = 1; //对齐到的维度对应大小
-
-                    for it in dims.iter().rev() {
-                        align_size *= *it as usize;
-                        if now_filled % align_size == 0 {
-                            align_dim -= 1;
-                        } else {
-                            break;
-                        }
-                    }
-
-                    if align_dim == 0 {
-                        //初始值，啥都没填充，对齐到第一维
-                        align_dim = 1;
-                    }
-
-                    val.global_array_init(
-                        output,
-                        info,
-                        &dims[align_dim..dims.len()], //切片的右面是开区间
-                        result,
-                    );
-                }
-                let fin_filled = result.len();
-                let required_size = dims.iter().fold(1, |acc, x| acc * x) as usize;
-                for _ in (fin_filled - pre_filled)..required_size {
-                    result.push(0);
+                    let sub_dims = match val {
+                        InitVal::Exp(_) => &[][..],
+                        InitVal::InitValS(_) => nested_dims(dims, result.len(), span)?,
+                    };
+                    val.global_array_init(info, sub_dims, btype, span, result)?;
                 }
+                let required_size = array_size(dims, result.len() - pre_filled, span)?;
+                result.resize(pre_filled + required_size, 0);
             }
         }
+        Ok(())
     }
 }
 
-///对局部数组的初始化,返回展平的%id数组或0（id从1开始不可能为0）
-pub trait LocalArrayInit {
-    fn local_array_init(
-        &self,
-        output: &mut dyn Write,
-        info: &mut GenerateIrInfo,
-        dims: &[i32],
-        result: &mut Vec<i32>,
-    );
-}
+//CosntInitVal的局部初始化和GlobalArrayInit一样，因此不再实现
 
-//CosntInitVal的LocalArrayInit和GlobalArrayInit一样，因此不再实现
-
-///生成变量数组初值
+///生成变量数组初值，返回展平的表达式结果，没有给出的元素补0
 impl InitVal {
     pub fn local_array_init(
         &self,
-        output: &mut dyn Write,
+        program: &mut Program,
         info: &mut GenerateIrInfo,
         dims: &[i32],
-        result: &mut Vec<String>,
-    ) {
+        btype: BType,
+        span: Span,
+        result: &mut Vec<ExpResult>,
+    ) -> Result<()> {
         match self {
             InitVal::Exp(exp) => {
-                let val_id = exp.generate(output, info);
-                result.push(val_id.unwrap());
+                let val_id = exp.generate(program, info)?;
+                let val_id = convert(val_id, btype, program, info)?;
+                result.push(val_id);
             }
             InitVal::InitValS(vals) => {
                 let pre_filled = result.len();
                 for val in vals {
-                    let now_filled = result.len();
-                    let mut align_dim = dims.len(); //对齐到哪个维度 TODO:check
-
-                    let mut align_size: usize = 1; //对齐到的维度对应大小
-
-                    for it in dims.iter().rev() {
-                        align_size *= *it as usize;
-                        if now_filled % align_size == 0 {
-                            align_dim -= 1;
-                        } else {
-                            break;
-                        }
-                    }
-
-                    if align_dim == 0 {
-                        //初始值，啥都没填充，对齐到第一维
-                        align_dim = 1;
-                    }
-
-                    val.local_array_init(
-                        output,
-                        info,
-                        &dims[align_dim..dims.len()], //切片的右面是开区间
-                        result,
-                    );
-                }
-                let fin_filled = result.len();
-                let required_size = dims.iter().fold(1, |acc, x| acc * x) as usize;
-                for _ in (fin_filled - pre_filled)..required_size {
-                    result.push(0.to_string());
+                    let sub_dims = match val {
+                        InitVal::Exp(_) => &[][..],
+                        InitVal::InitValS(_) => nested_dims(dims, result.len(), span)?,
+                    };
+                    val.local_array_init(program, info, sub_dims, btype, span, result)?;
                 }
+                let required_size = array_size(dims, result.len() - pre_filled, span)?;
+                result.resize(pre_filled + required_size, ExpResult::Value(0));
             }
         }
+        Ok(())
     }
 }
@@ -1,5 +1,61 @@
 //! 基础的AST定义
 
+///源码位置，记录lalrpop给出的字节偏移区间[start, end)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+///解析浮点数字面量，十六进制形如0x1.8p3，十进制交给标准库处理
+///和C一样，超出float表示范围的字面量得到inf
+pub fn parse_float_literal(literal: &str) -> f32 {
+    let lower = literal.to_ascii_lowercase();
+    let Some(hex) = lower.strip_prefix("0x") else {
+        return literal.parse().unwrap_or(f32::INFINITY);
+    };
+    let (mantissa, exp) = hex.split_once('p').unwrap_or((hex, "0"));
+    let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    //先在f64中精确累加尾数，最后按指数缩放并舍入到f32
+    let mut value: f64 = 0.0;
+    for digit in int_part.chars().chain(frac_part.chars()) {
+        value = value * 16.0 + digit.to_digit(16).unwrap_or(0) as f64;
+    }
+    let exp = exp.parse::<i32>().unwrap_or(i32::MAX) - 4 * frac_part.len() as i32;
+    (value * 2f64.powi(exp)) as f32
+}
+
+///解析字符串字面量(含两侧引号)，返回转义后的字节，不含结尾的0
+pub fn parse_string_literal(literal: &str) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut iter = literal[1..literal.len() - 1].bytes();
+    while let Some(byte) = iter.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        bytes.push(match iter.next() {
+            Some(b'n') => b'\n',
+            Some(b't') => b'\t',
+            Some(b'r') => b'\r',
+            Some(b'0') => 0,
+            Some(b'a') => 0x07,
+            Some(b'b') => 0x08,
+            Some(b'f') => 0x0c,
+            Some(b'v') => 0x0b,
+            Some(other) => other, //\\ \" \' 以及其他未知转义都保留原字符
+            None => b'\\',
+        });
+    }
+    bytes
+}
+
 ///////////////////////////BaseAST////////////////////////////
 
 #[derive(Debug)]
@@ -17,12 +73,13 @@ pub enum CompItem {
 }
 
 #[derive(Debug)]
-///FuncDef     ::= FuncType IDENT "(" [FuncFParams] ")" Block;
+///FuncDef     ::= FuncType IDENT "(" [FuncFParams] ")" (Block | ";");
 pub struct FuncDef {
     pub func_type: FuncType,
     pub ident: String,                 //IDENT
     pub func_fparams: Vec<FuncFParam>, //FuncFParams ::= FuncFParam {"," FuncFParam};
-    pub block: Block,
+    pub block: Option<Block>,          //只有原型声明时没有函数体
+    pub span: Span,                    //函数头的位置
 }
 
 #[derive(Debug)]
@@ -31,20 +88,34 @@ pub struct FuncFParam {
     pub btype: BType,
     pub ident: String,
     pub dims: Option<Vec<ConstExp>>,
+    pub span: Span,
 }
 
-#[derive(Debug, Copy, Clone)]
-///FuncType    ::= "void" | "int";
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+///FuncType    ::= "void" | "int" | "float";
 /// 枚举类型，表示函数的返回值类型
 pub enum FuncType {
     Int,
+    Float,
     Void,
 }
 
+impl FuncType {
+    ///返回值的类型，void函数没有返回值
+    pub fn ret_btype(self) -> Option<BType> {
+        match self {
+            FuncType::Int => Some(BType::Int),
+            FuncType::Float => Some(BType::Float),
+            FuncType::Void => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 ///Block         ::= "{" {BlockItem} "}";
 pub struct Block {
     pub items: Vec<BlockItem>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
@@ -55,31 +126,78 @@ pub enum BlockItem {
 }
 
 #[derive(Debug)]
-/// Stmt ::= LVal "=" Exp ";"
-///       | [Exp] ";"
+/// Stmt ::= SimpleStmt ";"
+///       | ";"
 ///       | Block
 ///       | "return" [Exp] ";";
 ///       | "if" "(" Exp ")" Stmt ["else" Stmt]
 ///       | "while" "(" Exp ")" Stmt
+///       | "for" "(" (Decl | [SimpleStmts] ";") [Exp] ";" [SimpleStmts] ")" Stmt
+///       | "do" Stmt "while" "(" Exp ")" ";"
+///       | "switch" "(" Exp ")" "{" {SwitchArm} "}"
 ///       | "break" ";"
 ///       | "continue" ";"
-pub enum Stmt {
+/// SimpleStmts ::= SimpleStmt {"," SimpleStmt}
+/// SimpleStmt ::= LVal ("=" | "+=" | "-=" | "*=" | "/=" | "%=") Exp
+///              | LVal ("++" | "--") | ("++" | "--") LVal
+///              | Exp
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+///Stmt的具体种类
+pub enum StmtKind {
     Assign(LVal, Exp),
     Exp(Option<Exp>),
     Block(Block),
     If(Exp, Box<Stmt>, Option<Box<Stmt>>),
     RetExp(Option<Exp>),
     While(Exp, Box<Stmt>),
+    OpAssign(LVal, ArithOp, Exp), //复合赋值
+    Incr(LVal, ArithOp),          //自增自减只能作为语句，前置后置等价，op为Add或Sub
+    For(
+        Vec<BlockItem>, //初始化，可以是声明
+        Option<Exp>,    //条件，省略时恒为真
+        Vec<Stmt>,      //步进
+        Box<Stmt>,      //循环体
+    ),
+    DoWhile(Box<Stmt>, Exp),
+    Switch(Exp, Vec<SwitchArm>),
     Break,
     Continue,
 }
 
+#[derive(Debug)]
+///SwitchArm   ::= ("case" ConstExp | "default") ":" {BlockItem};
+///没有break时顺序执行到下一个分支，每个分支的声明只在该分支内可见
+pub struct SwitchArm {
+    pub label: CaseLabel,
+    pub items: Vec<BlockItem>,
+}
+
+#[derive(Debug)]
+pub enum CaseLabel {
+    Case(ConstExp),
+    Default(Span),
+}
+
 ///////////////////////////Exp////////////////////////////
 
 #[derive(Debug)]
-///Exp         ::= LOrExp;
+///Exp         ::= LOrExp | LOrExp "?" Exp ":" Exp;
 pub enum Exp {
-    LOrExp(Box<LOrExp>),
+    LOrExp(Box<LOrExp>, Span),
+    Cond(Box<LOrExp>, Box<Exp>, Box<Exp>, Span), //条件表达式，只对选中的分支求值
+}
+
+impl Exp {
+    pub fn span(&self) -> Span {
+        match self {
+            Exp::LOrExp(_, span) | Exp::Cond(_, _, _, span) => *span,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -89,7 +207,7 @@ pub enum Exp {
 pub enum UnaryExp {
     PrimaryExp(Box<PrimaryExp>),
     BinaryOp(UnaryOp, Box<UnaryExp>), //改名
-    Call(String, Vec<Exp>),           //FuncRParams ::= Exp {"," Exp};
+    Call(String, Vec<Exp>, Span),     //FuncRParams ::= Exp {"," Exp};
 }
 
 #[derive(Debug)]
@@ -97,15 +215,18 @@ pub enum UnaryExp {
 pub enum PrimaryExp {
     Bexp(Box<Exp>),
     LVal(LVal),
-    Number(i32),
+    Number(i64, Span), //字面量先按更宽的类型保存，求值时再截断成32位
+    FloatNumber(f32, Span),
+    Str(Vec<u8>, Span), //字符串字面量，只能作为putf的格式串
 }
 
 #[derive(Debug)]
-///UnaryOp     ::= "+" | "-" | "!";
+///UnaryOp     ::= "+" | "-" | "!" | "~";
 pub enum UnaryOp {
     Neg,
     Pos,
     Not,
+    BitNot,
 }
 
 #[derive(Debug)]
@@ -115,6 +236,35 @@ pub enum BinaryAddOp {
     Sub,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///复合赋值和自增自减中的算术运算
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+impl From<&BinaryAddOp> for ArithOp {
+    fn from(op: &BinaryAddOp) -> Self {
+        match op {
+            BinaryAddOp::Add => ArithOp::Add,
+            BinaryAddOp::Sub => ArithOp::Sub,
+        }
+    }
+}
+
+impl From<&BinaryMulOp> for ArithOp {
+    fn from(op: &BinaryMulOp) -> Self {
+        match op {
+            BinaryMulOp::Mul => ArithOp::Mul,
+            BinaryMulOp::Div => ArithOp::Div,
+            BinaryMulOp::Mod => ArithOp::Mod,
+        }
+    }
+}
+
 #[derive(Debug)]
 ///BinaryMulOp ::= "*" | "/" | "%" ;
 pub enum BinaryMulOp {
@@ -147,10 +297,24 @@ pub enum BinaryRelOp {
 }
 
 #[derive(Debug)]
-///RelExp      ::= AddExp | RelExp ("<" | ">" | "<=" | ">=") AddExp;
-pub enum RelExp {
+///BinaryShiftOp ::= "<<" | ">>" ;
+pub enum BinaryShiftOp {
+    Shl,
+    Sar, //int是有符号数，右移为算术右移
+}
+
+#[derive(Debug)]
+///ShiftExp    ::= AddExp | ShiftExp ("<<" | ">>") AddExp;
+pub enum ShiftExp {
     AddExp(Box<AddExp>),
-    BinaryExp(Box<RelExp>, BinaryRelOp, Box<AddExp>),
+    BinaryExp(Box<ShiftExp>, BinaryShiftOp, Box<AddExp>),
+}
+
+#[derive(Debug)]
+///RelExp      ::= ShiftExp | RelExp ("<" | ">" | "<=" | ">=") ShiftExp;
+pub enum RelExp {
+    ShiftExp(Box<ShiftExp>),
+    BinaryExp(Box<RelExp>, BinaryRelOp, Box<ShiftExp>),
 }
 
 #[derive(Debug)]
@@ -168,10 +332,31 @@ pub enum EqExp {
 }
 
 #[derive(Debug)]
-///LAndExp     ::= EqExp | LAndExp "&&" EqExp;
-pub enum LAndExp {
+///BitAndExp   ::= EqExp | BitAndExp "&" EqExp;
+pub enum BitAndExp {
     EqExp(Box<EqExp>),
-    BinaryExp(Box<LAndExp>, Box<EqExp>),
+    BinaryExp(Box<BitAndExp>, Box<EqExp>),
+}
+
+#[derive(Debug)]
+///BitXorExp   ::= BitAndExp | BitXorExp "^" BitAndExp;
+pub enum BitXorExp {
+    BitAndExp(Box<BitAndExp>),
+    BinaryExp(Box<BitXorExp>, Box<BitAndExp>),
+}
+
+#[derive(Debug)]
+///BitOrExp    ::= BitXorExp | BitOrExp "|" BitXorExp;
+pub enum BitOrExp {
+    BitXorExp(Box<BitXorExp>),
+    BinaryExp(Box<BitOrExp>, Box<BitXorExp>),
+}
+
+#[derive(Debug)]
+///LAndExp     ::= BitOrExp | LAndExp "&&" BitOrExp;
+pub enum LAndExp {
+    BitOrExp(Box<BitOrExp>),
+    BinaryExp(Box<LAndExp>, Box<BitOrExp>),
 }
 
 #[derive(Debug)]
@@ -194,10 +379,22 @@ pub enum ConstDecl {
     ConstDeclS(BType, Vec<ConstDef>),
 }
 
-#[derive(Debug)]
-///BType         ::= "int";
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+///BType         ::= "int" | "float";
 pub enum BType {
+    #[default]
     Int,
+    Float,
+}
+
+impl BType {
+    ///类型名，用于报错
+    pub fn name(self) -> &'static str {
+        match self {
+            BType::Int => "int",
+            BType::Float => "float",
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -206,6 +403,7 @@ pub struct ConstDef {
     pub ident: String,
     pub dims: Vec<ConstExp>,
     pub const_init_val: ConstInitVal,
+    pub span: Span,
 }
 
 #[derive(Debug)]
@@ -221,12 +419,21 @@ pub enum ConstExp {
     Exp(Exp),
 }
 
+impl ConstExp {
+    pub fn span(&self) -> Span {
+        match self {
+            ConstExp::Exp(exp) => exp.span(),
+        }
+    }
+}
+
 #[derive(Debug)]
 ///LVal          ::= IDENT {"[" Exp "]"};
 /// IDENT对应Ident
 pub struct LVal {
     pub ident: String,
     pub dims: Vec<Exp>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
@@ -242,6 +449,7 @@ pub struct VarDef {
     pub ident: String,
     pub dims: Vec<ConstExp>,
     pub init_val: Option<InitVal>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
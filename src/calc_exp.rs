@@ -1,25 +1,128 @@
-///!实现生成表达式静态求值
+//!实现生成表达式静态求值
 use crate::ast::*;
 use crate::ds_for_ir::GenerateIrInfo;
+use crate::error::{CompileError, Result};
+use crate::symbol_table::ArrayInfoBase;
+use crate::symbol_table::ConstValue;
 use crate::symbol_table::SymbolInfo;
 
+///把整数字面量截断成32位补码，超出32位无法表示时报错
+///2147483648截断成-2147483648，之后取负按补码回绕，正好得到INT_MIN
+pub fn literal_value(num: i64, span: Span) -> Result<i32> {
+    if num <= u32::MAX as i64 {
+        Ok(num as u32 as i32)
+    } else {
+        Err(CompileError::semantic(
+            span,
+            "整数字面量超出了32位整数的表示范围".to_string(),
+        ))
+    }
+}
+
+///按RISC-V的div语义折叠除法，INT_MIN / -1回绕成INT_MIN
+///除数为0时放弃折叠，留到运行时处理
+pub fn fold_div(v1: i32, v2: i32) -> Option<i32> {
+    match v2 {
+        0 => None,
+        _ => Some(v1.wrapping_div(v2)),
+    }
+}
+
+///按RISC-V的rem语义折叠取余，INT_MIN % -1得0
+///除数为0时放弃折叠，留到运行时处理
+pub fn fold_rem(v1: i32, v2: i32) -> Option<i32> {
+    match v2 {
+        0 => None,
+        _ => Some(v1.wrapping_rem(v2)),
+    }
+}
+
+///折叠二元算术运算，有一边是浮点数时两边都转换成浮点数计算
+///int_op返回None表示放弃折叠，float_op返回None表示该运算不能用于浮点数
+fn fold_arith(
+    v1: ConstValue,
+    v2: ConstValue,
+    int_op: impl Fn(i32, i32) -> Option<i32>,
+    float_op: impl Fn(f32, f32) -> Option<f32>,
+) -> Option<ConstValue> {
+    match (v1, v2) {
+        (ConstValue::Int(v1), ConstValue::Int(v2)) => int_op(v1, v2).map(ConstValue::Int),
+        _ => float_op(v1.as_float(), v2.as_float()).map(ConstValue::Float),
+    }
+}
+
+///折叠比较运算，结果为int
+fn fold_cmp(
+    v1: ConstValue,
+    v2: ConstValue,
+    int_op: impl Fn(&i32, &i32) -> bool,
+    float_op: impl Fn(&f32, &f32) -> bool,
+) -> ConstValue {
+    let result = match (v1, v2) {
+        (ConstValue::Int(v1), ConstValue::Int(v2)) => int_op(&v1, &v2),
+        _ => float_op(&v1.as_float(), &v2.as_float()),
+    };
+    ConstValue::Int(result as i32)
+}
+
+///折叠位运算，只对int有定义，float交给语义检查报错
+fn fold_bits(v1: ConstValue, v2: ConstValue, op: impl Fn(i32, i32) -> i32) -> Option<ConstValue> {
+    match (v1, v2) {
+        (ConstValue::Int(v1), ConstValue::Int(v2)) => Some(ConstValue::Int(op(v1, v2))),
+        _ => None,
+    }
+}
+
+///静态求值，无法在编译期求出时返回Ok(None)
 pub trait Eval {
-    fn eval(&self, info: &mut GenerateIrInfo) -> Option<i32>;
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>>;
+}
+
+///在必须是常量表达式的地方求值，无法求出时报错，what描述求值的对象
+pub fn eval_const<T: Eval>(
+    exp: &T,
+    span: Span,
+    info: &mut GenerateIrInfo,
+    what: &str,
+) -> Result<ConstValue> {
+    info.div_by_zero = false;
+    match exp.eval(info)? {
+        Some(val) => Ok(val),
+        None if info.div_by_zero => Err(CompileError::semantic(
+            span,
+            format!("{}中出现了除以0", what),
+        )),
+        None => Err(CompileError::semantic(
+            span,
+            format!("{}不是常量表达式", what),
+        )),
+    }
+}
+
+///计算case的值，必须是整数常量
+pub fn eval_case(label: &ConstExp, info: &mut GenerateIrInfo) -> Result<i32> {
+    match eval_const(label, label.span(), info, "case的值")? {
+        ConstValue::Int(val) => Ok(val),
+        ConstValue::Float(_) => Err(CompileError::semantic(
+            label.span(),
+            "case的值必须是整数".to_string(),
+        )),
+    }
 }
 
 ///为ConstInitVal实现Eval trait
 impl Eval for ConstInitVal {
-    fn eval(&self, info: &mut GenerateIrInfo) -> Option<i32> {
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>> {
         match self {
             ConstInitVal::ConstExp(const_exp) => const_exp.eval(info),
-            _ => None, //不支持对带有花括号的数组初值列表求值
+            _ => Ok(None), //不支持对带有花括号的数组初值列表求值
         }
     }
 }
 
 ///为ConstExp实现Eval trait
 impl Eval for ConstExp {
-    fn eval(&self, info: &mut GenerateIrInfo) -> Option<i32> {
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>> {
         match self {
             ConstExp::Exp(exp) => exp.eval(info),
         }
@@ -28,208 +131,454 @@ impl Eval for ConstExp {
 
 ///为Exp实现Eval trait
 impl Eval for Exp {
-    fn eval(&self, info: &mut GenerateIrInfo) -> Option<i32> {
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>> {
         match self {
-            Exp::LOrExp(exp) => exp.eval(info),
+            Exp::LOrExp(exp, _) => exp.eval(info),
+            //三个操作数都是常量时才折叠，结果的类型由两个分支共同决定
+            Exp::Cond(cond, then_exp, else_exp, _) => Ok(
+                match (cond.eval(info)?, then_exp.eval(info)?, else_exp.eval(info)?) {
+                    (Some(cond), Some(v1), Some(v2)) => {
+                        let btype = match (v1, v2) {
+                            (ConstValue::Int(_), ConstValue::Int(_)) => BType::Int,
+                            _ => BType::Float,
+                        };
+                        let val = if cond.is_true() { v1 } else { v2 };
+                        Some(val.convert(btype))
+                    }
+                    _ => None,
+                },
+            ),
         }
     }
 }
 
 ///为LOrExp实现Eval trait
 impl Eval for LOrExp {
-    fn eval(&self, info: &mut GenerateIrInfo) -> Option<i32> {
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>> {
         match self {
             LOrExp::LAndExp(exp) => exp.eval(info),
-            LOrExp::BinaryExp(exp1, exp2) => match (exp1.eval(info), exp2.eval(info)) {
+            LOrExp::BinaryExp(exp1, exp2) => Ok(match (exp1.eval(info)?, exp2.eval(info)?) {
                 (Some(v1), Some(v2)) => {
-                    if v1 != 0 || v2 != 0 {
-                        Some(1)
-                    } else {
-                        Some(0)
-                    }
+                    Some(ConstValue::Int((v1.is_true() || v2.is_true()) as i32))
                 }
                 _ => None,
-            },
+            }),
         }
     }
 }
 
 ///为LAndExp实现Eval trait
 impl Eval for LAndExp {
-    fn eval(&self, info: &mut GenerateIrInfo) -> Option<i32> {
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>> {
         match self {
-            LAndExp::EqExp(exp) => exp.eval(info),
-            LAndExp::BinaryExp(exp1, exp2) => match (exp1.eval(info), exp2.eval(info)) {
+            LAndExp::BitOrExp(exp) => exp.eval(info),
+            LAndExp::BinaryExp(exp1, exp2) => Ok(match (exp1.eval(info)?, exp2.eval(info)?) {
                 (Some(v1), Some(v2)) => {
-                    if v1 == 0 || v2 == 0 {
-                        Some(0)
-                    } else {
-                        Some(1)
-                    }
+                    Some(ConstValue::Int((v1.is_true() && v2.is_true()) as i32))
                 }
                 _ => None,
-            },
+            }),
+        }
+    }
+}
+
+///为BitOrExp实现Eval trait
+impl Eval for BitOrExp {
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>> {
+        match self {
+            BitOrExp::BitXorExp(exp) => exp.eval(info),
+            BitOrExp::BinaryExp(exp1, exp2) => Ok(match (exp1.eval(info)?, exp2.eval(info)?) {
+                (Some(v1), Some(v2)) => fold_bits(v1, v2, |a, b| a | b),
+                _ => None,
+            }),
+        }
+    }
+}
+
+///为BitXorExp实现Eval trait
+impl Eval for BitXorExp {
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>> {
+        match self {
+            BitXorExp::BitAndExp(exp) => exp.eval(info),
+            BitXorExp::BinaryExp(exp1, exp2) => Ok(match (exp1.eval(info)?, exp2.eval(info)?) {
+                (Some(v1), Some(v2)) => fold_bits(v1, v2, |a, b| a ^ b),
+                _ => None,
+            }),
+        }
+    }
+}
+
+///为BitAndExp实现Eval trait
+impl Eval for BitAndExp {
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>> {
+        match self {
+            BitAndExp::EqExp(exp) => exp.eval(info),
+            BitAndExp::BinaryExp(exp1, exp2) => Ok(match (exp1.eval(info)?, exp2.eval(info)?) {
+                (Some(v1), Some(v2)) => fold_bits(v1, v2, |a, b| a & b),
+                _ => None,
+            }),
         }
     }
 }
 
 ///为EqExp实现Eval trait
 impl Eval for EqExp {
-    fn eval(&self, info: &mut GenerateIrInfo) -> Option<i32> {
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>> {
         match self {
             EqExp::RelExp(exp) => exp.eval(info),
-            EqExp::BinaryExp(exp1, op, exp2) => match (exp1.eval(info), exp2.eval(info)) {
-                (Some(v1), Some(v2)) => match op {
-                    BinaryEqOp::Eq => {
-                        if v1 == v2 {
-                            Some(1)
-                        } else {
-                            Some(0)
-                        }
-                    }
-                    BinaryEqOp::Ne => {
-                        if v1 != v2 {
-                            Some(1)
-                        } else {
-                            Some(0)
-                        }
-                    }
-                },
+            EqExp::BinaryExp(exp1, op, exp2) => Ok(match (exp1.eval(info)?, exp2.eval(info)?) {
+                (Some(v1), Some(v2)) => Some(match op {
+                    BinaryEqOp::Eq => fold_cmp(v1, v2, i32::eq, f32::eq),
+                    BinaryEqOp::Ne => fold_cmp(v1, v2, i32::ne, f32::ne),
+                }),
                 _ => None,
-            },
+            }),
         }
     }
 }
 
 ///为RelExp实现Eval trait
 impl Eval for RelExp {
-    fn eval(&self, info: &mut GenerateIrInfo) -> Option<i32> {
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>> {
+        match self {
+            RelExp::ShiftExp(exp) => exp.eval(info),
+            RelExp::BinaryExp(exp1, op, exp2) => Ok(match (exp1.eval(info)?, exp2.eval(info)?) {
+                (Some(v1), Some(v2)) => Some(match op {
+                    BinaryRelOp::Lt => fold_cmp(v1, v2, i32::lt, f32::lt),
+                    BinaryRelOp::Gt => fold_cmp(v1, v2, i32::gt, f32::gt),
+                    BinaryRelOp::Le => fold_cmp(v1, v2, i32::le, f32::le),
+                    BinaryRelOp::Ge => fold_cmp(v1, v2, i32::ge, f32::ge),
+                }),
+                _ => None,
+            }),
+        }
+    }
+}
+
+///为ShiftExp实现Eval trait
+impl Eval for ShiftExp {
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>> {
         match self {
-            RelExp::AddExp(exp) => exp.eval(info),
-            RelExp::BinaryExp(exp1, op, exp2) => match (exp1.eval(info), exp2.eval(info)) {
+            ShiftExp::AddExp(exp) => exp.eval(info),
+            ShiftExp::BinaryExp(exp1, op, exp2) => Ok(match (exp1.eval(info)?, exp2.eval(info)?) {
+                //和RISC-V的sll/sra一样只取移位量的低5位
                 (Some(v1), Some(v2)) => match op {
-                    BinaryRelOp::Lt => {
-                        if v1 < v2 {
-                            Some(1)
-                        } else {
-                            Some(0)
-                        }
-                    }
-                    BinaryRelOp::Gt => {
-                        if v1 > v2 {
-                            Some(1)
-                        } else {
-                            Some(0)
-                        }
-                    }
-                    BinaryRelOp::Le => {
-                        if v1 <= v2 {
-                            Some(1)
-                        } else {
-                            Some(0)
-                        }
-                    }
-                    BinaryRelOp::Ge => {
-                        if v1 >= v2 {
-                            Some(1)
-                        } else {
-                            Some(0)
-                        }
-                    }
+                    BinaryShiftOp::Shl => fold_bits(v1, v2, |a, b| a.wrapping_shl(b as u32)),
+                    BinaryShiftOp::Sar => fold_bits(v1, v2, |a, b| a.wrapping_shr(b as u32)),
                 },
                 _ => None,
-            },
+            }),
         }
     }
 }
 
 ///为AddExp实现Eval trait
 impl Eval for AddExp {
-    fn eval(&self, info: &mut GenerateIrInfo) -> Option<i32> {
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>> {
         match self {
             AddExp::MulExp(exp) => exp.eval(info),
-            AddExp::BinaryExp(exp1, op, exp2) => match (exp1.eval(info), exp2.eval(info)) {
+            AddExp::BinaryExp(exp1, op, exp2) => Ok(match (exp1.eval(info)?, exp2.eval(info)?) {
                 (Some(v1), Some(v2)) => match op {
-                    BinaryAddOp::Add => Some(v1 + v2),
-                    BinaryAddOp::Sub => Some(v1 - v2),
+                    BinaryAddOp::Add => {
+                        fold_arith(v1, v2, |a, b| Some(a.wrapping_add(b)), |a, b| Some(a + b))
+                    }
+                    BinaryAddOp::Sub => {
+                        fold_arith(v1, v2, |a, b| Some(a.wrapping_sub(b)), |a, b| Some(a - b))
+                    }
                 },
                 _ => None,
-            },
+            }),
         }
     }
 }
 
 ///为MulExp实现Eval trait
 impl Eval for MulExp {
-    fn eval(&self, info: &mut GenerateIrInfo) -> Option<i32> {
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>> {
         match self {
             MulExp::UnaryExp(exp) => exp.eval(info),
-            MulExp::BinaryExp(exp1, op, exp2) => match (exp1.eval(info), exp2.eval(info)) {
-                (Some(v1), Some(v2)) => match op {
-                    BinaryMulOp::Mul => Some(v1 * v2),
-                    BinaryMulOp::Div => Some(v1 / v2),
-                    BinaryMulOp::Mod => Some(v1 % v2),
-                },
+            MulExp::BinaryExp(exp1, op, exp2) => Ok(match (exp1.eval(info)?, exp2.eval(info)?) {
+                (Some(v1), Some(v2)) => {
+                    //浮点数除以0按IEEE 754得到inf或nan，和运行时一致
+                    let result = match op {
+                        BinaryMulOp::Mul => {
+                            fold_arith(v1, v2, |a, b| Some(a.wrapping_mul(b)), |a, b| Some(a * b))
+                        }
+                        BinaryMulOp::Div => fold_arith(v1, v2, fold_div, |a, b| Some(a / b)),
+                        BinaryMulOp::Mod => fold_arith(v1, v2, fold_rem, |_, _| None),
+                    };
+                    if result.is_none() && v2 == ConstValue::Int(0) {
+                        //记录下来，常量表达式的上下文据此报错
+                        info.div_by_zero = true;
+                    }
+                    result
+                }
                 _ => None,
-            },
+            }),
         }
     }
 }
 
 ///为UnaryExp实现Eval trait
 impl Eval for UnaryExp {
-    fn eval(&self, info: &mut GenerateIrInfo) -> Option<i32> {
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>> {
         match self {
             UnaryExp::PrimaryExp(exp) => exp.eval(info),
-            UnaryExp::BinaryOp(op, exp) => match exp.eval(info) {
+            UnaryExp::BinaryOp(op, exp) => Ok(match exp.eval(info)? {
                 None => None,
-                Some(v) => match op {
-                    UnaryOp::Neg => Some(-v),
-                    UnaryOp::Pos => Some(v),
-                    UnaryOp::Not => {
-                        if v == 0 {
-                            Some(1)
-                        } else {
-                            Some(0)
-                        }
-                    }
+                Some(v) => match (op, v) {
+                    //-INT_MIN回绕成INT_MIN
+                    (UnaryOp::Neg, ConstValue::Int(v)) => Some(ConstValue::Int(v.wrapping_neg())),
+                    (UnaryOp::Neg, ConstValue::Float(v)) => Some(ConstValue::Float(-v)),
+                    (UnaryOp::Pos, v) => Some(v),
+                    (UnaryOp::Not, v) => Some(ConstValue::Int(!v.is_true() as i32)),
+                    (UnaryOp::BitNot, ConstValue::Int(v)) => Some(ConstValue::Int(!v)),
+                    (UnaryOp::BitNot, ConstValue::Float(_)) => None,
                 },
-            },
-            UnaryExp::Call(_, _) => None,
+            }),
+            UnaryExp::Call(..) => Ok(None),
         }
     }
 }
 
 ///为PrimaryExp实现Eval trait
 impl Eval for PrimaryExp {
-    fn eval(&self, info: &mut GenerateIrInfo) -> Option<i32> {
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>> {
         match self {
             PrimaryExp::Bexp(exp) => exp.eval(info),
-            PrimaryExp::Number(num) => Some(*num),
+            PrimaryExp::Number(num, span) => Ok(Some(ConstValue::Int(literal_value(*num, *span)?))),
+            PrimaryExp::FloatNumber(num, _) => Ok(Some(ConstValue::Float(*num))),
             PrimaryExp::LVal(lval) => lval.eval(info),
+            PrimaryExp::Str(..) => Ok(None),
         }
     }
 }
 
 ///为LVal实现Eval trait
-///注意到LVal只有为常量时才可调用Eval
-///可在更上一层调用中就得知LVal是否为常量
+///只有常量和下标全为常量的常量数组元素能够求值
 impl Eval for LVal {
-    fn eval(&self, info: &mut GenerateIrInfo) -> Option<i32> {
-        let val = info.search_symbol(&self.ident).expect("No Symbol Found!");
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>> {
+        let val = match info.search_symbol(&self.ident) {
+            Some(val) => val,
+            None => {
+                return Err(CompileError::semantic(
+                    self.span,
+                    format!("使用了未定义的符号`{}`", self.ident),
+                ))
+            }
+        };
         match val.content {
-            SymbolInfo::Const(v) => Some(v),
-            _ => None,
+            SymbolInfo::Const(v) => Ok(Some(v)),
+            //常量数组在下标都是常量且不越界时，直接从展平的初值中取
+            SymbolInfo::Array(ArrayInfoBase {
+                btype,
+                dims,
+                const_init: Some(init),
+            }) if dims.len() == self.dims.len() => {
+                let mut pos: usize = 0;
+                for (index, dim) in self.dims.iter().zip(&dims) {
+                    match index.eval(info)? {
+                        Some(ConstValue::Int(i)) if (0..*dim).contains(&i) => {
+                            pos = pos * (*dim as usize) + i as usize;
+                        }
+                        _ => return Ok(None),
+                    }
+                }
+                Ok(init
+                    .get(pos)
+                    .map(|word| ConstValue::from_word(*word, btype)))
+            }
+            _ => Ok(None),
         }
     }
 }
 
 ///为InitVal实现Eval trait
 impl Eval for InitVal {
-    fn eval(&self, info: &mut GenerateIrInfo) -> Option<i32> {
+    fn eval(&self, info: &mut GenerateIrInfo) -> Result<Option<ConstValue>> {
         match self {
             InitVal::Exp(exp) => exp.eval(info),
-            _ => None, //不支持对带有花括号的数组初值列表求值
+            _ => Ok(None), //不支持对带有花括号的数组初值列表求值
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///RV32M规范中div的语义：除以0得-1，INT_MIN / -1得INT_MIN
+    fn riscv_div(v1: i32, v2: i32) -> i32 {
+        if v2 == 0 {
+            -1
+        } else if v1 == i32::MIN && v2 == -1 {
+            i32::MIN
+        } else {
+            (v1 as i64 / v2 as i64) as i32
+        }
+    }
+
+    ///RV32M规范中rem的语义：除以0得被除数，INT_MIN % -1得0
+    fn riscv_rem(v1: i32, v2: i32) -> i32 {
+        if v2 == 0 {
+            v1
+        } else if v1 == i32::MIN && v2 == -1 {
+            0
+        } else {
+            (v1 as i64 % v2 as i64) as i32
+        }
+    }
+
+    const SAMPLES: [i32; 11] = [
+        i32::MIN,
+        i32::MIN + 1,
+        -7,
+        -2,
+        -1,
+        0,
+        1,
+        2,
+        7,
+        i32::MAX - 1,
+        i32::MAX,
+    ];
+
+    ///解析`const int x = exp;`
+    fn parse_const(exp: &str) -> CompUnit {
+        let source = format!("const int x = {};", exp);
+        let mut errors = vec![];
+        crate::sysy::CompUnitParser::new()
+            .parse(&mut errors, &source)
+            .unwrap()
+    }
+
+    ///取出`const int x = exp;`中的初值
+    fn const_init_val(ast: &CompUnit) -> &ConstInitVal {
+        match &ast.item[0] {
+            CompItem::Decl(Decl::ConstDecl(ConstDecl::ConstDeclS(_, defs))) => {
+                &defs[0].const_init_val
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn eval_value(exp: &str) -> Option<ConstValue> {
+        let ast = parse_const(exp);
+        const_init_val(&ast)
+            .eval(&mut GenerateIrInfo::new())
+            .unwrap()
+    }
+
+    fn eval_source(exp: &str) -> Option<i32> {
+        eval_value(exp).map(ConstValue::as_int)
+    }
+
+    #[test]
+    fn div_rem_match_riscv() {
+        for v1 in SAMPLES {
+            for v2 in SAMPLES {
+                if v2 == 0 {
+                    assert_eq!(fold_div(v1, v2), None);
+                    assert_eq!(fold_rem(v1, v2), None);
+                } else {
+                    assert_eq!(fold_div(v1, v2), Some(riscv_div(v1, v2)), "{} / {}", v1, v2);
+                    assert_eq!(fold_rem(v1, v2), Some(riscv_rem(v1, v2)), "{} % {}", v1, v2);
+                }
+            }
         }
     }
+
+    #[test]
+    fn arithmetic_wraps() {
+        assert_eq!(eval_source("2147483647 + 1"), Some(i32::MIN));
+        assert_eq!(eval_source("-2147483648 - 1"), Some(i32::MAX));
+        assert_eq!(eval_source("65536 * 65536"), Some(0));
+        assert_eq!(eval_source("-(-2147483648)"), Some(i32::MIN));
+        assert_eq!(eval_source("-2147483648 / -1"), Some(i32::MIN));
+        assert_eq!(eval_source("-2147483648 % -1"), Some(0));
+        assert_eq!(eval_source("-7 / 2"), Some(riscv_div(-7, 2)));
+        assert_eq!(eval_source("-7 % 2"), Some(riscv_rem(-7, 2)));
+    }
+
+    #[test]
+    fn division_by_zero_is_not_folded() {
+        assert_eq!(eval_source("1 / 0"), None);
+        assert_eq!(eval_source("1 % (2 - 2)"), None);
+
+        //必须是常量的地方报错
+        let ast = parse_const("1 / 0");
+        let mut info = GenerateIrInfo::new();
+        let err = eval_const(
+            const_init_val(&ast),
+            Span::default(),
+            &mut info,
+            "常量`x`的初值",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("除以0"));
+    }
+
+    #[test]
+    fn bitwise_folding() {
+        assert_eq!(eval_source("12 & 10"), Some(8));
+        assert_eq!(eval_source("12 | 10"), Some(14));
+        assert_eq!(eval_source("12 ^ 10"), Some(6));
+        assert_eq!(eval_source("~0"), Some(-1));
+        assert_eq!(eval_source("1 << 2 + 1"), Some(8));
+        assert_eq!(eval_source("12 & 10 == 10"), Some(0));
+        //即1 | (2 ^ (3 & 6))
+        assert_eq!(eval_source("1 | 2 ^ 3 & 6"), Some(1));
+        //右移是算术右移，移位量只取低5位，与RV32的sll/sra一致
+        assert_eq!(eval_source("-17 >> 2"), Some(-5));
+        assert_eq!(eval_source("1 << 33"), Some(2));
+        assert_eq!(eval_source("1 << 31"), Some(i32::MIN));
+        //浮点数不能参与位运算
+        assert_eq!(eval_value("1.5 & 1"), None);
+        assert_eq!(eval_value("~1.0"), None);
+    }
+
+    #[test]
+    fn conditional_folding() {
+        assert_eq!(eval_source("1 ? 2 : 3"), Some(2));
+        assert_eq!(eval_source("0 ? 2 : 1 ? 4 : 5"), Some(4));
+        assert_eq!(eval_source("1 < 2 ? 10 : 20"), Some(10));
+        //结果的类型由两个分支共同决定
+        assert_eq!(eval_value("1 ? 2 : 1.5"), Some(ConstValue::Float(2.0)));
+        assert_eq!(eval_value("0.0 ? 1 : 2"), Some(ConstValue::Int(2)));
+    }
+
+    #[test]
+    fn float_literals() {
+        assert_eq!(parse_float_literal("1.5"), 1.5);
+        assert_eq!(parse_float_literal(".25"), 0.25);
+        assert_eq!(parse_float_literal("3."), 3.0);
+        assert_eq!(parse_float_literal("1e3"), 1000.0);
+        assert_eq!(parse_float_literal("2.5E-1"), 0.25);
+        assert_eq!(parse_float_literal("0x1.8p1"), 3.0);
+        assert_eq!(parse_float_literal("0X.8P0"), 0.5);
+        assert_eq!(parse_float_literal("0x10p-4"), 1.0);
+        assert_eq!(parse_float_literal("0.1"), 0.1f32);
+    }
+
+    #[test]
+    fn float_folding() {
+        assert_eq!(eval_value("1.5 + 2"), Some(ConstValue::Float(3.5)));
+        assert_eq!(eval_value("7 / 2.0"), Some(ConstValue::Float(3.5)));
+        assert_eq!(eval_value("7 / 2"), Some(ConstValue::Int(3)));
+        assert_eq!(eval_value("-0x1p-1"), Some(ConstValue::Float(-0.5)));
+        assert_eq!(eval_value("1 < 1.5"), Some(ConstValue::Int(1)));
+        assert_eq!(eval_value("!0.0"), Some(ConstValue::Int(1)));
+        assert_eq!(eval_value("0.5 && 2"), Some(ConstValue::Int(1)));
+        assert_eq!(
+            eval_value("1.0 / 0"),
+            Some(ConstValue::Float(f32::INFINITY))
+        );
+        //浮点数不能取模
+        assert_eq!(eval_value("1.5 % 2"), None);
+        //转换成int时向0截断
+        assert_eq!(
+            ConstValue::Float(-2.75).convert(BType::Int),
+            ConstValue::Int(-2)
+        );
+    }
 }
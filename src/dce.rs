@@ -0,0 +1,261 @@
+//! 删除没有用的代码
+//!
+//! 从有副作用的指令(store、调用有副作用的函数、br/jump/ret)出发标记用到的值，
+//! 没有被标记的指令和基本块参数都删掉。只被store、从没被读过的局部alloc，对它的store也删掉
+use crate::ds_for_ir::{fwd_target, FloatIntrinsic};
+use crate::ds_for_opt::{insts, remove_insts, rewrite_edges};
+use koopa::ir::{BasicBlock, Function, FunctionData, Program, Value, ValueKind};
+use std::collections::{HashMap, HashSet};
+
+///没有副作用的函数：只store到自己的局部alloc，只调用没有副作用的函数
+///float内建函数在后端直接展开，也没有副作用；提前调用的名字跟随真正的函数
+pub fn pure_funcs(program: &Program) -> HashSet<Function> {
+    let names: HashMap<&str, Function> = program
+        .funcs()
+        .iter()
+        .map(|(&func, data)| (&data.name()[1..], func))
+        .collect();
+    let mut pure = HashSet::new();
+    let mut callees: HashMap<Function, Vec<Function>> = HashMap::new();
+    for (&func, data) in program.funcs() {
+        if data.layout().entry_bb().is_none() {
+            if FloatIntrinsic::from_name(&data.name()[1..]).is_some() {
+                pure.insert(func);
+            }
+            continue;
+        }
+        let local = local_pointers(data);
+        let mut has_side_effect = false;
+        for (_, inst) in insts(data) {
+            match data.dfg().value(inst).kind() {
+                ValueKind::Store(store) if !local.contains(&store.dest()) => {
+                    has_side_effect = true;
+                }
+                ValueKind::Call(call) => callees.entry(func).or_default().push(call.callee()),
+                _ => {}
+            }
+        }
+        if !has_side_effect {
+            pure.insert(func);
+        }
+    }
+    //先假设都没有副作用，调用了有副作用的函数的再去掉，直到不再变化
+    let resolve = |callee: Function| {
+        let name = &program.func(callee).name()[1..];
+        fwd_target(name).map_or(callee, |target| names[target])
+    };
+    loop {
+        let impure: Vec<Function> = pure
+            .iter()
+            .copied()
+            .filter(|func| {
+                callees
+                    .get(func)
+                    .into_iter()
+                    .flatten()
+                    .any(|&callee| !pure.contains(&resolve(callee)))
+            })
+            .collect();
+        if impure.is_empty() {
+            break;
+        }
+        for func in impure {
+            pure.remove(&func);
+        }
+    }
+    for &func in program.funcs().keys() {
+        if fwd_target(&program.func(func).name()[1..]).is_some() && pure.contains(&resolve(func)) {
+            pure.insert(func);
+        }
+    }
+    pure
+}
+
+///局部alloc，以及由它们经过getelemptr/getptr算出来的指针
+fn local_pointers(func: &FunctionData) -> HashSet<Value> {
+    let mut local = HashSet::new();
+    for (_, inst) in insts(func) {
+        match func.dfg().value(inst).kind() {
+            ValueKind::Alloc(_) => {
+                local.insert(inst);
+            }
+            ValueKind::GetElemPtr(get_elem_ptr) if local.contains(&get_elem_ptr.src()) => {
+                local.insert(inst);
+            }
+            ValueKind::GetPtr(get_ptr) if local.contains(&get_ptr.src()) => {
+                local.insert(inst);
+            }
+            _ => {}
+        }
+    }
+    local
+}
+
+///删除一个函数中没有用的指令和基本块参数，返回是否有改动
+///删掉对局部数组的读之后，对它的store可能也变成没有用的，所以反复进行直到不再变化
+pub fn dce(func: &mut FunctionData, pure: &HashSet<Function>) -> bool {
+    let mut changed = false;
+    while dce_once(func, pure) {
+        changed = true;
+    }
+    changed
+}
+
+fn dce_once(func: &mut FunctionData, pure: &HashSet<Function>) -> bool {
+    let dead_stores = unread_stores(func);
+    let mut live = HashSet::new();
+    let mut worklist = vec![];
+    for (_, inst) in insts(func) {
+        let critical = match func.dfg().value(inst).kind() {
+            ValueKind::Store(_) => !dead_stores.contains(&inst),
+            ValueKind::Call(call) => !pure.contains(&call.callee()),
+            ValueKind::Branch(_) | ValueKind::Jump(_) | ValueKind::Return(_) => true,
+            _ => false,
+        };
+        if critical {
+            live.insert(inst);
+            worklist.push(inst);
+        }
+    }
+
+    //基本块参数属于哪个基本块，在参数中的下标
+    let mut param_pos = HashMap::new();
+    for &bb in func.layout().bbs().keys() {
+        for (i, &param) in func.dfg().bb(bb).params().iter().enumerate() {
+            param_pos.insert(param, (bb, i));
+        }
+    }
+    let mark = |value: Value, live: &mut HashSet<Value>, worklist: &mut Vec<Value>| {
+        if !value.is_global() && live.insert(value) {
+            worklist.push(value);
+        }
+    };
+    while let Some(value) = worklist.pop() {
+        //用到的基本块参数，每个前驱传入的对应实参也用到了
+        if let Some(&(bb, i)) = param_pos.get(&value) {
+            for &pred in func.dfg().bb(bb).used_by() {
+                for (target, args) in edges(func, pred) {
+                    if target == bb {
+                        mark(args[i], &mut live, &mut worklist);
+                    }
+                }
+            }
+            continue;
+        }
+        let kind = func.dfg().value(value).kind();
+        match kind {
+            //实参等到对应的参数被用到时再标记
+            ValueKind::Branch(branch) => mark(branch.cond(), &mut live, &mut worklist),
+            ValueKind::Jump(_) => {}
+            _ => {
+                for operand in kind.value_uses() {
+                    mark(operand, &mut live, &mut worklist);
+                }
+            }
+        }
+    }
+
+    //先去掉参数和传给它们的实参，实参可能是要删的指令；参数又可能被要删的指令用到，最后再删
+    let dead_params: Vec<Value> = param_pos
+        .keys()
+        .copied()
+        .filter(|param| !live.contains(param))
+        .collect();
+    remove_params(func, &dead_params, &param_pos);
+    let dead: Vec<Value> = insts(func)
+        .into_iter()
+        .map(|(_, inst)| inst)
+        .filter(|inst| !live.contains(inst))
+        .collect();
+    remove_insts(func, &dead);
+    for &param in &dead_params {
+        func.dfg_mut().remove_value(param);
+    }
+    !dead.is_empty() || !dead_params.is_empty()
+}
+
+///br/jump的每条出边，(目标, 实参)
+fn edges(func: &FunctionData, inst: Value) -> Vec<(BasicBlock, &[Value])> {
+    match func.dfg().value(inst).kind() {
+        ValueKind::Branch(branch) => vec![
+            (branch.true_bb(), branch.true_args()),
+            (branch.false_bb(), branch.false_args()),
+        ],
+        ValueKind::Jump(jump) => vec![(jump.target(), jump.args())],
+        _ => vec![],
+    }
+}
+
+///只被store、从没被读过的局部alloc，返回对它们的store
+///alloc经过getelemptr/getptr算出的指针也只能被store或者继续算地址
+fn unread_stores(func: &FunctionData) -> HashSet<Value> {
+    let mut users: HashMap<Value, Vec<Value>> = HashMap::new();
+    for (_, inst) in insts(func) {
+        for value in func.dfg().value(inst).kind().value_uses() {
+            users.entry(value).or_default().push(inst);
+        }
+    }
+    let mut dead_stores = HashSet::new();
+    for (_, inst) in insts(func) {
+        if !matches!(func.dfg().value(inst).kind(), ValueKind::Alloc(_)) {
+            continue;
+        }
+        let mut stores = vec![];
+        let mut pointers = vec![inst];
+        let mut unread = true;
+        while let Some(pointer) = pointers.pop() {
+            for &user in users.get(&pointer).into_iter().flatten() {
+                match func.dfg().value(user).kind() {
+                    ValueKind::Store(store)
+                        if store.dest() == pointer && store.value() != pointer =>
+                    {
+                        stores.push(user)
+                    }
+                    ValueKind::GetElemPtr(_) | ValueKind::GetPtr(_) => pointers.push(user),
+                    _ => unread = false,
+                }
+            }
+        }
+        if unread {
+            dead_stores.extend(stores);
+        }
+    }
+    dead_stores
+}
+
+///从基本块中去掉参数，同时去掉每个前驱传入的对应实参，参数本身由调用者删除
+fn remove_params(
+    func: &mut FunctionData,
+    params: &[Value],
+    param_pos: &HashMap<Value, (BasicBlock, usize)>,
+) {
+    let mut removed: HashMap<BasicBlock, HashSet<usize>> = HashMap::new();
+    for param in params {
+        let (bb, i) = param_pos[param];
+        removed.entry(bb).or_default().insert(i);
+    }
+    //重建跳转指令时会检查实参和参数是否一致，所以先删参数再删所有前驱的实参
+    let mut preds = HashSet::new();
+    for (&bb, indices) in &removed {
+        preds.extend(func.dfg().bb(bb).used_by().iter().copied());
+        let mut index = 0;
+        func.dfg_mut().bb_mut(bb).params_mut().retain(|_| {
+            index += 1;
+            !indices.contains(&(index - 1))
+        });
+    }
+    for pred in preds {
+        rewrite_edges(func, pred, |target, args| match removed.get(&target) {
+            Some(indices) => {
+                let args = args
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(i, _)| !indices.contains(i))
+                    .map(|(_, arg)| arg)
+                    .collect();
+                (target, args)
+            }
+            None => (target, args),
+        });
+    }
+}
@@ -0,0 +1,246 @@
+//! 诊断信息
+//!
+//! 把AST上记录的字节偏移换算成`文件:行:列`，并渲染出带插入符的源码片段，例如
+//!
+//! ```text
+//! error: 尝试调用非函数`a`
+//!  --> test.sy:3:3
+//!   |
+//! 3 |   a(1);
+//!   |   ^^^^
+//! ```
+
+use std::fmt;
+
+use lalrpop_util::lexer::Token;
+use lalrpop_util::ParseError;
+
+use crate::ast::Span;
+
+///预处理后的一行在原始文件中的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineOrigin {
+    pub file: usize, //文件在SourceFile::files中的下标
+    pub line: usize, //在该文件中的行号，从1开始
+}
+
+///源文件，负责字节偏移到行列号的换算
+#[derive(Debug)]
+pub struct SourceFile {
+    pub name: String,
+    pub content: String,
+    line_starts: Vec<usize>,  //每一行首字节的偏移
+    files: Vec<String>,       //预处理时读入的所有文件
+    origins: Vec<LineOrigin>, //每一行来自哪个文件的哪一行，为空时就是name本身
+}
+
+impl SourceFile {
+    pub fn new(name: String, content: String) -> Self {
+        let mut line_starts = vec![0];
+        for (i, c) in content.char_indices() {
+            if c == '\n' {
+                line_starts.push(i + 1);
+            }
+        }
+        SourceFile {
+            name,
+            content,
+            line_starts,
+            files: vec![],
+            origins: vec![],
+        }
+    }
+
+    ///预处理得到的源文件，content的第i行来自origins[i]
+    pub fn with_origins(
+        name: String,
+        content: String,
+        files: Vec<String>,
+        origins: Vec<LineOrigin>,
+    ) -> Self {
+        SourceFile {
+            files,
+            origins,
+            ..SourceFile::new(name, content)
+        }
+    }
+
+    ///第line行(从1开始)在原始文件中的文件名和行号
+    ///文件末尾的空行算作最后一行的下一行
+    fn origin(&self, line: usize) -> (&str, usize) {
+        match (self.origins.get(line - 1), self.origins.last()) {
+            (Some(origin), _) => (&self.files[origin.file], origin.line),
+            (None, Some(last)) => (&self.files[last.file], last.line + 1),
+            (None, None) => (&self.name, line),
+        }
+    }
+
+    ///字节偏移所在的行号，从0开始
+    fn line_index(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        }
+    }
+
+    ///第line行(从0开始)的内容，不含换行符
+    fn line_text(&self, line: usize) -> &str {
+        let start = self.line_starts[line];
+        let end = match self.line_starts.get(line + 1) {
+            Some(&next) => next - 1,
+            None => self.content.len(),
+        };
+        self.content[start..end].trim_end_matches('\r')
+    }
+
+    ///每一行首字节的偏移和它在原始文件中的行号
+    pub fn line_table(&self) -> Vec<(usize, usize)> {
+        (0..self.line_starts.len())
+            .map(|line| (self.line_starts[line], self.origin(line + 1).1))
+            .collect()
+    }
+
+    ///字节偏移对应的行号和列号，均从1开始，列号按字符计
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.content.len());
+        let line = self.line_index(offset);
+        let col = self.content[self.line_starts[line]..offset].chars().count();
+        (line + 1, col + 1)
+    }
+}
+
+///诊断等级
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Level::Error => write!(f, "error"),
+            Level::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+///一条诊断信息，span为None时只输出文字
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Diagnostic {
+    pub fn error(span: Span, message: String) -> Self {
+        Diagnostic {
+            level: Level::Error,
+            message,
+            span: Some(span),
+        }
+    }
+
+    pub fn warning(span: Span, message: String) -> Self {
+        Diagnostic {
+            level: Level::Warning,
+            message,
+            span: Some(span),
+        }
+    }
+
+    ///把lalrpop给出的语法错误转换成“期望X，实际遇到Y”的形式
+    pub fn from_parse_error(err: &ParseError<usize, Token, &str>, source: &SourceFile) -> Self {
+        match err {
+            ParseError::InvalidToken { location } => {
+                let c = source.content[*location..].chars().next().unwrap_or(' ');
+                let span = Span::new(*location, *location + c.len_utf8());
+                Diagnostic::error(span, format!("语法错误: 无法识别的字符`{}`", c))
+            }
+            ParseError::UnrecognizedEof { location, expected } => Diagnostic::error(
+                Span::new(*location, *location),
+                format!(
+                    "语法错误: 期望{}，实际遇到文件末尾",
+                    describe_expected(expected)
+                ),
+            ),
+            ParseError::UnrecognizedToken { token, expected } => Diagnostic::error(
+                Span::new(token.0, token.2),
+                format!(
+                    "语法错误: 期望{}，实际遇到`{}`",
+                    describe_expected(expected),
+                    token.1
+                ),
+            ),
+            ParseError::ExtraToken { token } => Diagnostic::error(
+                Span::new(token.0, token.2),
+                format!("语法错误: 多余的`{}`", token.1),
+            ),
+            ParseError::User { error } => {
+                Diagnostic::error(Span::new(0, 0), format!("语法错误: {}", error))
+            }
+        }
+    }
+
+    ///渲染成 file:line:col 加源码片段的形式
+    pub fn render(&self, source: &SourceFile) -> String {
+        let span = match self.span {
+            Some(span) => span,
+            None => return format!("{}: {}\n", self.level, self.message),
+        };
+        let (line, col) = source.line_col(span.start);
+        let text = source.line_text(line - 1);
+        let (file, origin_line) = source.origin(line);
+        let line_no = origin_line.to_string();
+        let pad = " ".repeat(line_no.len());
+
+        //插入符只画在第一行内，至少画一个
+        let prefix: String = text
+            .chars()
+            .take(col - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let line_end = source.line_starts[line - 1] + text.len();
+        let caret_len = source.content[span.start.min(line_end)..span.end.min(line_end)]
+            .chars()
+            .count()
+            .max(1);
+
+        let mut result = format!("{}: {}\n", self.level, self.message);
+        result += &format!("{}--> {}:{}:{}\n", pad, file, origin_line, col);
+        result += &format!("{} |\n", pad);
+        result += &format!("{} | {}\n", line_no, text);
+        result += &format!("{} | {}{}\n", pad, prefix, "^".repeat(caret_len));
+        result
+    }
+}
+
+///把lalrpop给出的期望终结符列表转换成可读的形式
+///终结符形如`"int"`，正则表达式则换成它代表的记号种类
+fn describe_expected(expected: &[String]) -> String {
+    let mut names: Vec<String> = vec![];
+    for terminal in expected {
+        let name = if terminal.starts_with("r#") {
+            if terminal.contains("_a-zA-Z") {
+                "标识符".to_string()
+            } else if terminal.contains("[eE]") || terminal.contains("[pP]") {
+                "浮点数".to_string()
+            } else if terminal.starts_with("r#\"\\\"") {
+                "字符串".to_string()
+            } else {
+                "整数".to_string()
+            }
+        } else {
+            format!("`{}`", terminal.trim_matches('"'))
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    match names.split_last() {
+        None => "其他记号".to_string(),
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{}或{}", rest.join("、"), last),
+    }
+}
@@ -1,8 +1,12 @@
-use core::panic;
-use koopa::ir::entities::ValueData;
+use crate::ds_for_opt::insts;
+use crate::error::{CompileError, Result};
+use koopa::ir::BasicBlock;
+use koopa::ir::BinaryOp;
+use koopa::ir::FunctionData;
 use koopa::ir::Program;
 use koopa::ir::Value;
-use std::collections::HashMap;
+use koopa::ir::ValueKind;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Write;
 
@@ -12,13 +16,205 @@ const TMP_REG: [&str; 15] = [
 
 //ture if val is in range [-2048, 2047]
 pub fn check_i12(val: i32) -> bool {
-    val >= -2048 && val <= 2047
+    (-2048..=2047).contains(&val)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum UserKind {
     Val(Value), //用不用区分Global?
     Tmpi32(i32),
+    BlockArg(usize), //跳转时从暂存区读回的第i个实参
+}
+
+///参数的传递位置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArgLoc {
+    Reg(usize),      //a0-a7
+    FloatReg(usize), //fa0-fa7
+    Stack(i32),      //相对调用者sp的偏移量
+}
+
+///按ilp32f约定计算每个参数的位置，float先用fa0-fa7，用完后和int一样用a0-a7，再用完放到栈上
+pub fn arg_locations(is_float: &[bool]) -> Vec<ArgLoc> {
+    let mut int_cnt = 0;
+    let mut float_cnt = 0;
+    let mut stack_offset = 0;
+    let mut locations = vec![];
+    for &float in is_float {
+        if float && float_cnt < 8 {
+            locations.push(ArgLoc::FloatReg(float_cnt));
+            float_cnt += 1;
+        } else if int_cnt < 8 {
+            locations.push(ArgLoc::Reg(int_cnt));
+            int_cnt += 1;
+        } else {
+            locations.push(ArgLoc::Stack(stack_offset));
+            stack_offset += 4;
+        }
+    }
+    locations
+}
+
+///栈上参数占用的空间
+pub fn stack_args_size(locations: &[ArgLoc]) -> i32 {
+    locations
+        .iter()
+        .filter(|loc| matches!(loc, ArgLoc::Stack(_)))
+        .count() as i32
+        * 4
+}
+
+///按C可变参数的约定计算每个可变实参占用的位置编号，返回(编号, 位置总数)
+///编号k<7时放在a{k+1}，否则放在栈上(k-7)*4处；float要提升成double占两个位置，且从奇数编号开始
+pub fn variadic_slots(is_float: &[bool]) -> (Vec<usize>, usize) {
+    let mut slot = 0;
+    let mut slots = vec![];
+    for &float in is_float {
+        if float {
+            slot += 1 - slot % 2;
+            slots.push(slot);
+            slot += 2;
+        } else {
+            slots.push(slot);
+            slot += 1;
+        }
+    }
+    (slots, slot)
+}
+
+///可变实参先全部写到参数区域再装入寄存器，栈上的部分放在sp处，寄存器部分跟在后面
+pub fn variadic_offset(slot: usize, total: usize) -> i32 {
+    if slot >= 7 {
+        (slot as i32 - 7) * 4
+    } else {
+        (total.saturating_sub(7) + slot) as i32 * 4
+    }
+}
+
+///比较链至少有这么多个不同的case值才换成跳转表
+const JUMP_TABLE_MIN_CASES: usize = 4;
+
+///由一串比较同一个值的基本块换成的跳转表
+#[derive(Debug)]
+pub struct JumpTable {
+    pub value: Value,             //被比较的值
+    pub low: i32,                 //最小的case值
+    pub targets: Vec<BasicBlock>, //值为low+i时跳到targets[i]
+    pub default: BasicBlock,      //不在表中时的跳转目标
+}
+
+///只有`%c = eq value, imm`和`br %c, target, next`两条指令的块，返回(value, imm, target, next)
+///换成跳转表后不再生成%c，所以%c只能被这条br使用，uses是函数中每个值被使用的次数
+fn case_test(
+    func: &FunctionData,
+    bb: BasicBlock,
+    uses: &HashMap<Value, usize>,
+) -> Option<(Value, i32, BasicBlock, BasicBlock)> {
+    let insts: Vec<Value> = func
+        .layout()
+        .bbs()
+        .node(&bb)?
+        .insts()
+        .keys()
+        .copied()
+        .collect();
+    let [cmp, br] = insts[..] else {
+        return None;
+    };
+    let ValueKind::Binary(cmp_inst) = func.dfg().value(cmp).kind() else {
+        return None;
+    };
+    let ValueKind::Branch(br_inst) = func.dfg().value(br).kind() else {
+        return None;
+    };
+    if cmp_inst.op() != BinaryOp::Eq
+        || br_inst.cond() != cmp
+        || uses.get(&cmp) != Some(&1)
+        || !br_inst.true_args().is_empty()
+        || !br_inst.false_args().is_empty()
+    {
+        return None;
+    }
+    let ValueKind::Integer(imm) = func.dfg().value(cmp_inst.rhs()).kind() else {
+        return None;
+    };
+    Some((
+        cmp_inst.lhs(),
+        imm.value(),
+        br_inst.true_bb(),
+        br_inst.false_bb(),
+    ))
+}
+
+///找出函数中可以换成跳转表的比较链(比如switch生成的%switch_test块)，
+///返回链头到跳转表的映射，以及链中其余不用再生成的块
+///链中除链头外的块只能由前一个块跳入，case值要足够稠密
+pub fn find_jump_tables(
+    func: &FunctionData,
+) -> (HashMap<BasicBlock, JumpTable>, HashSet<BasicBlock>) {
+    //值的used_by在优化遍重建指令后不准确，扫描指令求使用次数
+    let mut uses: HashMap<Value, usize> = HashMap::new();
+    for (_, inst) in insts(func) {
+        for value in func.dfg().value(inst).kind().value_uses() {
+            *uses.entry(value).or_default() += 1;
+        }
+    }
+    let mut tables = HashMap::new();
+    let mut merged = HashSet::new();
+    let mut visited = HashSet::new();
+    for &head in func.layout().bbs().keys() {
+        if visited.contains(&head) {
+            continue;
+        }
+        let Some((value, _, _, _)) = case_test(func, head, &uses) else {
+            continue;
+        };
+        //沿着不相等的分支往下找，同一个值先出现的比较优先
+        let mut chain = vec![head];
+        let mut cases: Vec<(i32, BasicBlock)> = vec![];
+        let mut seen = HashSet::new();
+        let mut bb = head;
+        let default = loop {
+            let (_, imm, target, next) = case_test(func, bb, &uses).unwrap();
+            if seen.insert(imm) {
+                cases.push((imm, target));
+            }
+            match case_test(func, next, &uses) {
+                Some((next_value, _, _, _))
+                    if next_value == value
+                        && !chain.contains(&next)
+                        && func.dfg().bb(next).used_by().len() == 1 =>
+                {
+                    chain.push(next);
+                    bb = next;
+                }
+                _ => break next,
+            }
+        };
+        visited.extend(chain.iter().copied());
+
+        let low = cases.iter().map(|&(imm, _)| imm).min().unwrap();
+        let high = cases.iter().map(|&(imm, _)| imm).max().unwrap();
+        let range = high as i64 - low as i64 + 1;
+        if cases.len() < JUMP_TABLE_MIN_CASES || range > 2 * cases.len() as i64 {
+            continue;
+        }
+        let mut targets = vec![default; range as usize];
+        for (imm, target) in cases {
+            targets[(imm as i64 - low as i64) as usize] = target;
+        }
+        merged.extend(chain[1..].iter().copied());
+        tables.insert(
+            head,
+            JumpTable {
+                value,
+                low,
+                targets,
+                default,
+            },
+        );
+    }
+    (tables, merged)
 }
 
 #[derive(Debug)]
@@ -26,6 +222,7 @@ pub struct GenerateAsmInfo {
     pub reg_user: Vec<Option<UserKind>>,
     pub name_to_offset: HashMap<Value, i32>,
     pub stack_size: i32,
+    pub float_params: HashMap<Value, usize>, //通过fa寄存器传入的参数，第一次使用时再搬到整数寄存器
 }
 
 impl GenerateAsmInfo {
@@ -34,6 +231,7 @@ impl GenerateAsmInfo {
             reg_user: vec![None; 15],
             name_to_offset: HashMap::new(),
             stack_size: 0,
+            float_params: HashMap::new(),
         }
     }
     ///将value的对sp偏移量设置为offset
@@ -47,72 +245,73 @@ impl GenerateAsmInfo {
         value: Value,
         offset: i32,
         program_info: &Program,
-    ) {
+    ) -> Result<()> {
         self.set_offset(value, offset);
-        let reg = self.get_reg(output, value, program_info);
+        let reg = self.get_reg(output, value, program_info)?;
         if check_i12(offset) {
-            writeln!(output, "  sw {}, {}(sp)", reg, offset).unwrap();
+            writeln!(output, "  sw {}, {}(sp)", reg, offset)?;
         } else {
-            let addr_reg = self.get_reg_i32(output, offset);
-            writeln!(output, "  add {}, sp, {}", addr_reg, addr_reg).unwrap();
-            writeln!(output, "  sw {}, 0({})", reg, addr_reg).unwrap();
-            self.free_reg(UserKind::Tmpi32(offset));
+            let addr_reg = self.get_reg_i32(output, offset)?;
+            writeln!(output, "  add {}, sp, {}", addr_reg, addr_reg)?;
+            writeln!(output, "  sw {}, 0({})", reg, addr_reg)?;
+            self.free_reg(UserKind::Tmpi32(offset))?;
         }
+        Ok(())
     }
     //TODO: 把寄存器当cache用，spill很好做，维护好寄存器上存没存变量，如果存了直接从寄存器读，没存从内存读，每次踢出写回内存
-    pub fn set_sp(&mut self, output: &mut File) {
+    pub fn set_sp(&mut self, output: &mut File) -> Result<()> {
         let delta = -self.stack_size;
         if check_i12(delta) {
-            writeln!(output, "  addi sp, sp, {}", delta).unwrap();
-            return;
+            writeln!(output, "  addi sp, sp, {}", delta)?;
         } else {
-            let reg = self.get_reg_i32(output, delta);
+            let reg = self.get_reg_i32(output, delta)?;
 
-            writeln!(output, "  add sp, sp, {}", reg).unwrap();
+            writeln!(output, "  add sp, sp, {}", reg)?;
 
-            self.free_reg(UserKind::Tmpi32(delta));
+            self.free_reg(UserKind::Tmpi32(delta))?;
         }
+        Ok(())
     }
-    pub fn reset_sp(&mut self, output: &mut File) {
+    pub fn reset_sp(&mut self, output: &mut File) -> Result<()> {
         let delta = self.stack_size;
         if check_i12(delta) {
-            writeln!(output, "  addi sp, sp, {}", delta).unwrap();
-            return;
+            writeln!(output, "  addi sp, sp, {}", delta)?;
         } else {
-            let reg = self.get_reg_i32(output, delta);
+            let reg = self.get_reg_i32(output, delta)?;
 
-            writeln!(output, "  add sp, sp, {}", reg).unwrap();
+            writeln!(output, "  add sp, sp, {}", reg)?;
 
-            self.free_reg(UserKind::Tmpi32(delta));
+            self.free_reg(UserKind::Tmpi32(delta))?;
         }
+        Ok(())
     }
     //在可用临时寄存器中分配一个寄存器
-    fn new_tmp_reg(&mut self, value: UserKind) -> String {
+    pub fn new_tmp_reg(&mut self, value: UserKind) -> Result<String> {
         for (i, user) in self.reg_user.iter_mut().enumerate() {
             if user.is_none() {
                 *user = Some(value);
-                return TMP_REG[i].to_string();
+                return Ok(TMP_REG[i].to_string());
             }
         }
-        panic!("寄存器不足");
+        Err(CompileError::internal("寄存器不足".to_string()))
     }
     // 释放寄存器
-    pub fn free_reg(&mut self, free_user: UserKind) {
+    pub fn free_reg(&mut self, free_user: UserKind) -> Result<()> {
         for iter in self.reg_user.iter_mut() {
             if let Some(user) = iter {
                 if *user == free_user {
                     *iter = None;
-                    return;
+                    return Ok(());
                 }
             }
         }
-        panic!("user不占有任何寄存器!");
+        Err(CompileError::internal("user不占有任何寄存器!".to_string()))
     }
     //专门为超过i12的偏移量分配寄存器
-    pub fn get_reg_i32(&mut self, output: &mut File, inum: i32) -> String {
-        let reg = self.new_tmp_reg(UserKind::Tmpi32(inum));
-        writeln!(output, "  li {}, {}", reg, inum).unwrap();
-        reg
+    pub fn get_reg_i32(&mut self, output: &mut File, inum: i32) -> Result<String> {
+        let reg = self.new_tmp_reg(UserKind::Tmpi32(inum))?;
+        writeln!(output, "  li {}, {}", reg, inum)?;
+        Ok(reg)
     }
     //直接把对应寄存器的所有者设置为param，对应传参和返回值
     pub fn set_reg(&mut self, reg: &str, param: Value) {
@@ -124,31 +323,35 @@ impl GenerateAsmInfo {
         }
     }
     // 获取value对应的寄存器，若为全局变量则返回全局变量地址
-    pub fn get_reg(&mut self, output: &mut File, value: Value, program_info: &Program) -> String {
+    pub fn get_reg(
+        &mut self,
+        output: &mut File,
+        value: Value,
+        program_info: &Program,
+    ) -> Result<String> {
         //为全局变量则返回全局变量地址
         if value.is_global() {
-            let reg = self.new_tmp_reg(UserKind::Val(value));
+            let reg = self.new_tmp_reg(UserKind::Val(value))?;
 
             writeln!(
                 output,
                 "  la {}, {}",
                 reg,
                 &program_info.borrow_value(value).name().as_ref().unwrap()[1..]
-            )
-            .unwrap();
-            return reg;
+            )?;
+            return Ok(reg);
         }
 
         //查询是否已经有reg
         for (i, user) in self.reg_user.iter().enumerate() {
             if let Some(v) = user {
                 if *v == UserKind::Val(value) {
-                    return TMP_REG[i].to_string();
+                    return Ok(TMP_REG[i].to_string());
                 }
             }
         }
         //还在栈中，分配一个寄存器
-        let reg = Self::new_tmp_reg(self, UserKind::Val(value));
+        let reg = Self::new_tmp_reg(self, UserKind::Val(value))?;
         let opt_offset = self.name_to_offset.get(&value);
         match opt_offset {
             Some(offset) => {
@@ -156,19 +359,22 @@ impl GenerateAsmInfo {
                 let offset = *offset;
                 if check_i12(offset) {
                     //偏移量在i12
-                    writeln!(output, "  lw {}, {}(sp)", reg, offset).unwrap();
+                    writeln!(output, "  lw {}, {}(sp)", reg, offset)?;
                 } else {
                     //偏移量超出i12
-                    let reg_inum = self.get_reg_i32(output, offset);
-                    writeln!(output, "  add {}, sp, {}", reg_inum, reg_inum).unwrap();
-                    writeln!(output, "  lw {}, 0({})", reg, reg_inum).unwrap();
-                    self.free_reg(UserKind::Tmpi32(offset));
+                    let reg_inum = self.get_reg_i32(output, offset)?;
+                    writeln!(output, "  add {}, sp, {}", reg_inum, reg_inum)?;
+                    writeln!(output, "  lw {}, 0({})", reg, reg_inum)?;
+                    self.free_reg(UserKind::Tmpi32(offset))?;
                 }
-                reg
+                Ok(reg)
             }
             None => {
-                //尚未分配入栈，不生成读栈代码
-                reg
+                //尚未分配入栈，不生成读栈代码；float参数需要从fa寄存器取出位模式
+                if let Some(index) = self.float_params.get(&value) {
+                    writeln!(output, "  fmv.x.w {}, fa{}", reg, index)?;
+                }
+                Ok(reg)
             }
         }
     }
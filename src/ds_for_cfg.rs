@@ -0,0 +1,179 @@
+//! 函数的控制流图和支配树
+//!
+//! IR验证和各个优化遍都基于它，基本块之间的边来自块末尾的br/jump
+use koopa::ir::{BasicBlock, FunctionData, Value, ValueKind};
+use std::collections::HashMap;
+
+///控制流图，只记录从入口可达的基本块
+#[derive(Debug, Default)]
+pub struct Cfg {
+    pub entry: Option<BasicBlock>,
+    pub succs: HashMap<BasicBlock, Vec<BasicBlock>>, //后继，br的两个目标相同时只记一次
+    pub preds: HashMap<BasicBlock, Vec<BasicBlock>>, //可达的前驱
+    pub rpo: Vec<BasicBlock>,                        //可达基本块的逆后序，第一个是入口
+    pub idom: HashMap<BasicBlock, BasicBlock>,       //直接支配者，入口的直接支配者是自己
+}
+
+///基本块的结束指令，最后一条指令不是br/jump/ret时返回None
+pub fn terminator(func: &FunctionData, bb: BasicBlock) -> Option<Value> {
+    let inst = *func.layout().bbs().node(&bb)?.insts().back_key()?;
+    match func.dfg().value(inst).kind() {
+        ValueKind::Branch(_) | ValueKind::Jump(_) | ValueKind::Return(_) => Some(inst),
+        _ => None,
+    }
+}
+
+///基本块的后继，按br的true、false顺序
+pub fn successors(func: &FunctionData, bb: BasicBlock) -> Vec<BasicBlock> {
+    let Some(inst) = terminator(func, bb) else {
+        return vec![];
+    };
+    let mut succs = vec![];
+    for target in func.dfg().value(inst).kind().bb_uses() {
+        if !succs.contains(&target) {
+            succs.push(target);
+        }
+    }
+    succs
+}
+
+impl Cfg {
+    pub fn new(func: &FunctionData) -> Self {
+        let mut cfg = Cfg {
+            entry: func.layout().entry_bb(),
+            ..Cfg::default()
+        };
+        let Some(entry) = cfg.entry else {
+            return cfg;
+        };
+
+        //深度优先求后序，(基本块, 下一个要访问的后继)
+        let mut postorder = vec![];
+        let mut visited = vec![entry];
+        let mut stack = vec![(entry, 0)];
+        cfg.succs.insert(entry, successors(func, entry));
+        while let Some((bb, next)) = stack.pop() {
+            let Some(&succ) = cfg.succs[&bb].get(next) else {
+                postorder.push(bb);
+                continue;
+            };
+            stack.push((bb, next + 1));
+            if !visited.contains(&succ) {
+                visited.push(succ);
+                cfg.succs.insert(succ, successors(func, succ));
+                stack.push((succ, 0));
+            }
+        }
+        cfg.rpo = postorder.into_iter().rev().collect();
+        for &bb in &cfg.rpo {
+            cfg.preds.entry(bb).or_default();
+            for &succ in &cfg.succs[&bb] {
+                cfg.preds.entry(succ).or_default().push(bb);
+            }
+        }
+        cfg.compute_idom();
+        cfg
+    }
+
+    ///Cooper-Harvey-Kennedy迭代算法求直接支配者
+    fn compute_idom(&mut self) {
+        let order: HashMap<BasicBlock, usize> = self
+            .rpo
+            .iter()
+            .enumerate()
+            .map(|(i, &bb)| (bb, i))
+            .collect();
+        let entry = self.rpo[0];
+        self.idom.insert(entry, entry);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &bb in &self.rpo[1..] {
+                let mut new_idom = None;
+                for &pred in &self.preds[&bb] {
+                    if !self.idom.contains_key(&pred) {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(other) => self.intersect(&order, pred, other),
+                    });
+                }
+                let new_idom = new_idom.unwrap();
+                if self.idom.get(&bb) != Some(&new_idom) {
+                    self.idom.insert(bb, new_idom);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    ///两个基本块在支配树上的最近公共祖先
+    fn intersect(
+        &self,
+        order: &HashMap<BasicBlock, usize>,
+        mut a: BasicBlock,
+        mut b: BasicBlock,
+    ) -> BasicBlock {
+        while a != b {
+            while order[&a] > order[&b] {
+                a = self.idom[&a];
+            }
+            while order[&b] > order[&a] {
+                b = self.idom[&b];
+            }
+        }
+        a
+    }
+
+    ///基本块是否从入口可达
+    pub fn is_reachable(&self, bb: BasicBlock) -> bool {
+        self.idom.contains_key(&bb)
+    }
+
+    ///支配树上每个基本块的子节点，按逆后序
+    pub fn dom_children(&self) -> HashMap<BasicBlock, Vec<BasicBlock>> {
+        let mut children: HashMap<BasicBlock, Vec<BasicBlock>> = HashMap::new();
+        for &bb in &self.rpo[1..] {
+            children.entry(self.idom[&bb]).or_default().push(bb);
+        }
+        children
+    }
+
+    ///每个基本块的支配边界
+    pub fn dominance_frontiers(&self) -> HashMap<BasicBlock, Vec<BasicBlock>> {
+        let mut frontiers: HashMap<BasicBlock, Vec<BasicBlock>> = HashMap::new();
+        for &bb in &self.rpo {
+            let preds = &self.preds[&bb];
+            if preds.len() < 2 {
+                continue;
+            }
+            for &pred in preds {
+                //从前驱沿支配树往上走到bb的直接支配者为止，经过的块的支配边界都包含bb
+                let mut runner = pred;
+                while runner != self.idom[&bb] {
+                    let frontier = frontiers.entry(runner).or_default();
+                    if !frontier.contains(&bb) {
+                        frontier.push(bb);
+                    }
+                    runner = self.idom[&runner];
+                }
+            }
+        }
+        frontiers
+    }
+
+    ///a是否支配b，两者都必须可达
+    pub fn dominates(&self, a: BasicBlock, mut b: BasicBlock) -> bool {
+        loop {
+            if a == b {
+                return true;
+            }
+            let idom = self.idom[&b];
+            if idom == b {
+                return false;
+            }
+            b = idom;
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! 语义检查用到的数据结构
+use crate::ast::BType;
+use crate::ast::FuncType;
+use crate::ast::Span;
+use crate::diagnostics::Diagnostic;
+use crate::ds_for_ir::GenerateIrInfo;
+use crate::error::{CompileError, Result};
+use crate::symbol_table::FuncInfoBase;
+use crate::symbol_table::ParamKind;
+use crate::symbol_table::SymbolInfo;
+
+///表达式的类型，非int类型记录产生它的位置，便于报错
+#[derive(Debug, Clone)]
+pub enum ExpType {
+    Int,
+    Float(Span),
+    Void(Span),
+    Array(BType, Vec<i32>, Span), //数组指针，vec为除第一维外的维度，vec:[] => *i32
+    Str(Span),                    //字符串字面量
+}
+
+impl ExpType {
+    pub fn from_btype(btype: BType, span: Span) -> Self {
+        match btype {
+            BType::Int => ExpType::Int,
+            BType::Float => ExpType::Float(span),
+        }
+    }
+
+    ///能否作为形参param的实参，int和float之间可以隐式转换
+    pub fn matches(&self, param: &ParamKind) -> bool {
+        match (self, param) {
+            (ExpType::Int | ExpType::Float(_), ParamKind::Int | ParamKind::Float) => true,
+            (ExpType::Array(btype, dims, _), ParamKind::Array(param_btype, param_dims)) => {
+                btype == param_btype && dims == param_dims
+            }
+            (ExpType::Str(_), ParamKind::Str) => true,
+            _ => false,
+        }
+    }
+
+    ///类型名，用于报错
+    pub fn name(&self) -> String {
+        match self {
+            ExpType::Int => "int".to_string(),
+            ExpType::Float(_) => "float".to_string(),
+            ExpType::Void(_) => "void".to_string(),
+            ExpType::Array(btype, dims, _) => array_type_name(*btype, dims),
+            ExpType::Str(_) => "字符串".to_string(),
+        }
+    }
+}
+
+///形参类型名，用于报错
+pub fn param_kind_name(param: &ParamKind) -> String {
+    match param {
+        ParamKind::Int => "int".to_string(),
+        ParamKind::Float => "float".to_string(),
+        ParamKind::Array(btype, dims) => array_type_name(*btype, dims),
+        ParamKind::Str => "字符串".to_string(),
+    }
+}
+
+///数组指针的类型名，形如int[][3]
+fn array_type_name(btype: BType, dims: &[i32]) -> String {
+    let mut name = format!("{}[]", btype.name());
+    for dim in dims {
+        name += &format!("[{}]", dim);
+    }
+    name
+}
+
+///语句执行完之后，控制流能否继续走到它后面的语句
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Next, //可能顺序执行到下一条语句
+    Jump, //一定经return/break/continue或死循环离开
+}
+
+impl Flow {
+    ///两条分支汇合后的控制流
+    pub fn join(self, other: Flow) -> Flow {
+        if self == Flow::Next || other == Flow::Next {
+            Flow::Next
+        } else {
+            Flow::Jump
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CheckInfo {
+    pub env: GenerateIrInfo, //复用生成IR时的作用域栈和符号表
+    pub errors: Vec<Diagnostic>,
+    pub warnings: Vec<Diagnostic>,
+    pub now_func: String,          //当前所在函数名
+    pub now_func_type: FuncType,   //当前所在函数的返回类型
+    pub loop_breaks: Vec<bool>,    //每层循环中是否出现过跳出该循环的break
+    pub loop_continues: Vec<bool>, //每层循环(不含switch)中是否出现过continue
+}
+
+impl Default for CheckInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CheckInfo {
+    pub fn new() -> Self {
+        CheckInfo {
+            env: GenerateIrInfo::new(),
+            errors: vec![],
+            warnings: vec![],
+            now_func: String::new(),
+            now_func_type: FuncType::Void,
+            loop_breaks: vec![],
+            loop_continues: vec![],
+        }
+    }
+
+    ///记录一条错误，检查继续进行
+    pub fn error(&mut self, span: Span, message: String) {
+        self.errors.push(Diagnostic::error(span, message));
+    }
+
+    ///记录一条警告
+    pub fn warning(&mut self, span: Span, message: String) {
+        self.warnings.push(Diagnostic::warning(span, message));
+    }
+
+    ///记录求值等过程返回的语义错误，其余错误原样返回
+    pub fn record(&mut self, err: CompileError) -> Result<()> {
+        match err {
+            CompileError::Semantic(diags) => {
+                self.errors.extend(diags);
+                Ok(())
+            }
+            _ => Err(err),
+        }
+    }
+
+    ///插入符号表，重复定义时记录错误并保留原有定义
+    pub fn insert_symbol(&mut self, key: String, value: SymbolInfo, span: Span) -> Result<()> {
+        match self.env.insert_symbol(key, value, span) {
+            Ok(()) => Ok(()),
+            Err(err) => self.record(err),
+        }
+    }
+
+    ///登记函数的声明或定义，与之前的声明冲突时记录错误并保留原有的签名
+    pub fn declare_func(
+        &mut self,
+        key: String,
+        func: FuncInfoBase,
+        span: Span,
+        is_def: bool,
+    ) -> Result<()> {
+        match self.env.declare_func(key, func, span, is_def) {
+            Ok(()) => Ok(()),
+            Err(err) => self.record(err),
+        }
+    }
+
+    ///要求表达式为int或float，否则报错，返回表达式的类型(出错时当作int)
+    pub fn expect_number(&mut self, ty: ExpType) -> ExpType {
+        match ty {
+            ExpType::Int | ExpType::Float(_) => return ty,
+            ExpType::Void(span) => self.error(span, "void函数的调用结果不能作为值使用".to_string()),
+            ExpType::Array(_, _, span) => self.error(span, "数组不能作为数值使用".to_string()),
+            ExpType::Str(span) => self.error(span, "字符串只能作为putf的格式串".to_string()),
+        }
+        ExpType::Int
+    }
+
+    ///要求表达式为int，否则报错，用于数组下标和取模等只能是整数的地方
+    pub fn expect_int(&mut self, ty: ExpType, what: &str) {
+        if let ExpType::Float(span) = self.expect_number(ty) {
+            self.error(span, format!("{}必须是整数，实际是float", what));
+        }
+    }
+}
@@ -1,23 +1,320 @@
+use crate::ast::BType;
+use crate::ast::FuncType;
+use crate::ast::Span;
+use crate::error::{CompileError, Result};
+use crate::symbol_table::FuncInfoBase;
+use crate::symbol_table::ParamKind;
 use crate::symbol_table::SymbolInfo;
 use crate::symbol_table::SymbolTable;
+use koopa::ir::builder::LocalBuilder;
+use koopa::ir::builder_traits::*;
+use koopa::ir::{BasicBlock, Function, FunctionData, Program, Type, TypeKind, Value, ValueKind};
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+///Koopa IR没有浮点类型，浮点数在IR中以i32的位模式出现，
+///浮点运算写成对下面这些内建函数的调用，生成汇编时直接展开成RV32F指令
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatIntrinsic {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    FromInt, //int转float
+    ToInt,   //float转int，向0截断
+}
+
+impl FloatIntrinsic {
+    pub const ALL: [FloatIntrinsic; 12] = [
+        FloatIntrinsic::Add,
+        FloatIntrinsic::Sub,
+        FloatIntrinsic::Mul,
+        FloatIntrinsic::Div,
+        FloatIntrinsic::Eq,
+        FloatIntrinsic::Ne,
+        FloatIntrinsic::Lt,
+        FloatIntrinsic::Gt,
+        FloatIntrinsic::Le,
+        FloatIntrinsic::Ge,
+        FloatIntrinsic::FromInt,
+        FloatIntrinsic::ToInt,
+    ];
+
+    ///在IR中的函数名，不带@
+    pub fn name(self) -> &'static str {
+        match self {
+            FloatIntrinsic::Add => "__sysy_fadd",
+            FloatIntrinsic::Sub => "__sysy_fsub",
+            FloatIntrinsic::Mul => "__sysy_fmul",
+            FloatIntrinsic::Div => "__sysy_fdiv",
+            FloatIntrinsic::Eq => "__sysy_feq",
+            FloatIntrinsic::Ne => "__sysy_fne",
+            FloatIntrinsic::Lt => "__sysy_flt",
+            FloatIntrinsic::Gt => "__sysy_fgt",
+            FloatIntrinsic::Le => "__sysy_fle",
+            FloatIntrinsic::Ge => "__sysy_fge",
+            FloatIntrinsic::FromInt => "__sysy_itof",
+            FloatIntrinsic::ToInt => "__sysy_ftoi",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|op| op.name() == name)
+    }
+
+    ///IR中的函数声明
+    pub fn decl(self) -> FunctionData {
+        let params = match self {
+            FloatIntrinsic::FromInt | FloatIntrinsic::ToInt => vec![Type::get_i32()],
+            _ => vec![Type::get_i32(), Type::get_i32()],
+        };
+        FunctionData::new_decl(format!("@{}", self.name()), params, Type::get_i32())
+    }
+}
+
+///putf的参数个数可变，Koopa IR无法声明这样的函数，
+///所以每种可变实参的组合单独声明一个函数，名字里记下每个实参是int(i)还是float(f)，
+///生成汇编时再按C的可变参数约定展开成对putf的调用
+const PUTF_PREFIX: &str = "__sysy_putf_";
+
+///可变实参组合对应的函数名，不带@
+pub fn putf_name(is_float: &[bool]) -> String {
+    let kinds: String = is_float
+        .iter()
+        .map(|&float| if float { 'f' } else { 'i' })
+        .collect();
+    format!("{}{}", PUTF_PREFIX, kinds)
+}
+
+///从函数名还原每个可变实参是否为float，不是putf时返回None
+pub fn putf_arg_kinds(name: &str) -> Option<Vec<bool>> {
+    let kinds = name.strip_prefix(PUTF_PREFIX)?;
+    Some(kinds.chars().map(|kind| kind == 'f').collect())
+}
+
+const STRING_PREFIX: &str = "__sysy_str_";
+
+///字符串字面量在IR中的全局变量名，不带@
+pub fn string_name(id: usize) -> String {
+    format!("{}{}", STRING_PREFIX, id)
+}
+
+///全局变量是否为字符串字面量
+pub fn is_string_name(name: &str) -> bool {
+    name.starts_with(STRING_PREFIX)
+}
+
+///Koopa IR文本中函数必须先声明后调用，且同名的decl和fun不能共存，
+///所以相互递归时，先输出的函数通过另一个名字的decl调用后输出的函数，生成汇编时再换回原名
+const FWD_PREFIX: &str = "__sysy_fwd_";
+
+///函数在相互递归中被提前调用时使用的名字，不带@
+pub fn fwd_name(name: &str) -> String {
+    format!("{}{}", FWD_PREFIX, name)
+}
+
+///提前调用的名字对应的真正函数名，不是这种名字时返回None
+pub fn fwd_target(name: &str) -> Option<&str> {
+    name.strip_prefix(FWD_PREFIX)
+}
+
+///数组在IR中的类型，dims为空时就是i32
+pub fn array_type(dims: &[i32]) -> Type {
+    dims.iter().rev().fold(Type::get_i32(), |ty, &dim| {
+        Type::get_array(ty, dim as usize)
+    })
+}
+
+///形参在IR中的类型，浮点数也以i32的位模式传递
+pub fn param_type(kind: &ParamKind) -> Type {
+    match kind {
+        ParamKind::Int | ParamKind::Float => Type::get_i32(),
+        ParamKind::Array(_, dims) => Type::get_pointer(array_type(dims)),
+        ParamKind::Str => Type::get_pointer(Type::get_i32()),
+    }
+}
+
+///返回值在IR中的类型
+pub fn ret_type(func_type: FuncType) -> Type {
+    match func_type {
+        FuncType::Int | FuncType::Float => Type::get_i32(),
+        FuncType::Void => Type::get_unit(),
+    }
+}
+
+///函数中按出现顺序调用的本文件中定义的函数
+fn defined_callees(program: &Program, func: Function) -> Vec<Function> {
+    let data = program.func(func);
+    let mut callees = vec![];
+    for (_, node) in data.layout().bbs() {
+        for &inst in node.insts().keys() {
+            if let ValueKind::Call(call) = data.dfg().value(inst).kind() {
+                let callee = call.callee();
+                let is_defined = !program.func(callee).dfg().bbs().is_empty();
+                if is_defined && !callees.contains(&callee) {
+                    callees.push(callee);
+                }
+            }
+        }
+    }
+    callees
+}
+
+///排序函数，声明在前，被调用的函数尽量先于调用者，使输出的IR文本可以被重新解析
+///深度优先遍历调用图，回边指向的函数处在环中，只能通过fwd_name的声明提前调用
+pub fn sort_funcs(program: &mut Program) {
+    let (defined, decls): (Vec<Function>, Vec<Function>) = program
+        .func_layout()
+        .iter()
+        .partition(|&&func| !program.func(func).dfg().bbs().is_empty());
+    let callees: HashMap<Function, Vec<Function>> = defined
+        .iter()
+        .map(|&func| (func, defined_callees(program, func)))
+        .collect();
+    let mut order = vec![];
+    let mut forward = vec![];
+    let mut state: HashMap<Function, u8> = HashMap::new(); //没有记录为未访问，1正在访问，2已输出
+    for &root in &defined {
+        if state.contains_key(&root) {
+            continue;
+        }
+        //(函数, 下一个要访问的被调用者)
+        let mut stack = vec![(root, 0)];
+        state.insert(root, 1);
+        while let Some((now, next)) = stack.pop() {
+            let Some(&callee) = callees[&now].get(next) else {
+                state.insert(now, 2);
+                order.push(now);
+                continue;
+            };
+            stack.push((now, next + 1));
+            match state.get(&callee) {
+                None => {
+                    state.insert(callee, 1);
+                    stack.push((callee, 0));
+                }
+                //递归调用自己不需要提前声明
+                Some(1) if callee != now => forward.push((now, callee)),
+                _ => {}
+            }
+        }
+    }
+
+    //重新按顺序加入所有函数，提前调用用的声明放在所有定义之前
+    //函数的句柄会变化，之后要修正调用指令
+    let mut new_handle = HashMap::new();
+    for func in decls {
+        let data = program.remove_func(func).unwrap();
+        new_handle.insert(func, program.new_func(data));
+    }
+    let mut fwd_decls = HashMap::new();
+    for &(_, callee) in &forward {
+        fwd_decls.entry(callee).or_insert_with(|| {
+            let data = program.func(callee);
+            let (params, ret) = match data.ty().kind() {
+                TypeKind::Function(params, ret) => (params.clone(), ret.clone()),
+                _ => unreachable!(),
+            };
+            let name = format!("@{}", fwd_name(&data.name()[1..]));
+            program.new_func(FunctionData::new_decl(name, params, ret))
+        });
+    }
+    for func in order {
+        let data = program.remove_func(func).unwrap();
+        new_handle.insert(func, program.new_func(data));
+    }
+    for &func in new_handle.values() {
+        retarget_calls(program, func, |callee| new_handle.get(&callee).copied());
+    }
+    for (caller, callee) in forward {
+        let fwd = fwd_decls[&callee];
+        let callee = new_handle[&callee];
+        retarget_calls(program, new_handle[&caller], |target| {
+            (target == callee).then_some(fwd)
+        });
+    }
+}
+
+///把func中的调用改为调用retarget返回的函数，返回None的保持不变
+fn retarget_calls(
+    program: &mut Program,
+    func: Function,
+    retarget: impl Fn(Function) -> Option<Function>,
+) {
+    let data = program.func_mut(func);
+    let mut calls = vec![];
+    for (_, node) in data.layout().bbs() {
+        for &inst in node.insts().keys() {
+            if let ValueKind::Call(call) = data.dfg().value(inst).kind() {
+                if let Some(callee) = retarget(call.callee()) {
+                    calls.push((inst, callee, call.args().to_vec()));
+                }
+            }
+        }
+    }
+    for (inst, callee, args) in calls {
+        data.dfg_mut().replace_value_with(inst).call(callee, args);
+    }
+}
+
+///函数的浮点调用约定：哪些参数和返回值是浮点数
+///IR中看不出浮点类型，由前端生成后交给后端，用于决定用a还是fa寄存器传递
+#[derive(Debug, Clone, Default)]
+pub struct FloatAbi {
+    pub params: Vec<bool>,
+    pub ret: bool,
+}
+
+///函数名(不带@) => 浮点调用约定
+pub type FloatAbiTable = HashMap<String, FloatAbi>;
+
+///break所在的块，循环和switch共用一个栈，continue只认循环
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakTarget {
+    While(i32),  //while/for/do-while循环块编号
+    Switch(i32), //switch块编号
+}
 
 #[derive(Debug)]
 pub struct GenerateIrInfo {
-    pub now_id: i32,
     pub now_block_id: i32,
     //pub table: SymbolTable,
     pub tables: Vec<SymbolTable>,
     pub block_id: Vec<i32>,
     pub if_id: i32,
-    pub and_or_id: i32,          //短路求值块编号
-    pub while_id: i32,           //while循环块编号
-    pub while_history: Vec<i32>, //从当前到根的循环块编号栈
+    pub and_or_id: i32,                       //短路求值块编号
+    pub while_id: i32,                        //while循环块编号
+    pub while_history: Vec<BreakTarget>,      //从当前到根的循环块和switch块编号栈
+    pub switch_id: i32,                       //switch块编号
+    pub div_by_zero: bool,                    //求值时是否因为除以0放弃了折叠
+    pub decl_type: BType,                     //当前声明语句的类型
+    pub now_func_type: FuncType,              //当前所在函数的返回类型
+    pub strings: HashMap<Vec<u8>, Value>,     //字符串字面量对应的全局变量，相同的字面量共用一个
+    pub defined_funcs: HashSet<String>,       //已经定义过的函数
+    pub runtime: Vec<(String, FuncInfoBase)>, //运行时库的函数，按头文件中的顺序
+    pub line_table: Vec<(usize, usize)>,      //源码每一行首字节的偏移和它在原始文件中的行号
+    pub now_func: Option<Function>,           //正在生成的函数
+    pub now_bb: Option<BasicBlock>,           //正在生成的基本块，指令追加在它的末尾
+    pub bbs: HashMap<String, BasicBlock>,     //当前函数中标号对应的基本块，标号带%
+    pub funcs: HashMap<String, Function>,     //函数名(不带@) => IR中的函数
+    pub allocs: HashMap<String, Value>,       //变量名(不带@) => alloc得到的指针
+}
+
+impl Default for GenerateIrInfo {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl GenerateIrInfo {
     pub fn new() -> Self {
         GenerateIrInfo {
-            now_id: 0,
             now_block_id: 0,
             //先push一个空的block，编号为0，代表全局?
             //这里的block是对作用域的抽象，和任何符号无关
@@ -27,6 +324,19 @@ impl GenerateIrInfo {
             and_or_id: 0,
             while_id: 0,
             while_history: vec![],
+            switch_id: 0,
+            div_by_zero: false,
+            decl_type: BType::Int,
+            now_func_type: FuncType::Void,
+            strings: HashMap::new(),
+            defined_funcs: HashSet::new(),
+            runtime: vec![],
+            line_table: vec![],
+            now_func: None,
+            now_bb: None,
+            bbs: HashMap::new(),
+            funcs: HashMap::new(),
+            allocs: HashMap::new(),
             //table: symbol_table::SymbolTable::new(),
         }
     }
@@ -51,39 +361,30 @@ impl GenerateIrInfo {
                 });
             }
         }
-        panic!(
-            "尝试查询不存在的变量: {}\n当前block_id为{}\n符号表结构为{:#?}",
-            key, self.now_block_id, self.tables,
-        );
+        None
     }
     ///查询当前符号是否为全局符号
-    pub fn is_global_symbol(&self, key: &str) -> bool {
+    pub fn is_global_symbol(&self, key: &str) -> Result<bool> {
         match self.search_symbol(key) {
             Some(SymbolReturn { content, dep }) => match content {
-                SymbolInfo::Var(_) => match dep {
-                    0 => true,
-                    _ => false,
-                },
-                SymbolInfo::Func(_) => true,
-                SymbolInfo::Array(_) => match dep {
-                    0 => true,
-                    _ => false,
-                },
-                SymbolInfo::ArrayPointer(_) => match dep {
-                    0 => true,
-                    _ => false,
-                },
-                SymbolInfo::Const(_) => panic!("尝试查询常量的全局性"),
+                SymbolInfo::Var(_) => Ok(dep == 0),
+                SymbolInfo::Func(_) => Ok(true),
+                SymbolInfo::Array(_) => Ok(dep == 0),
+                SymbolInfo::ArrayPointer(_) => Ok(dep == 0),
+                SymbolInfo::Const(_) => Err(CompileError::internal(format!(
+                    "尝试查询常量{}的全局性",
+                    key
+                ))),
             },
-            None => panic!(
-                "尝试查询不存在的符号: {}\n当前block_id为{}\n符号表结构为{:#?}",
-                key, self.now_block_id, self.tables,
-            ),
+            None => Err(CompileError::internal(format!(
+                "尝试查询不存在的符号: {}, 当前block_id为{}",
+                key, self.now_block_id,
+            ))),
         }
     }
     ///得到正确**变量、函数或数组**名
-    pub fn get_name(&self, key: &str) -> String {
-        match self.search_symbol(key) {
+    pub fn get_name(&self, key: &str) -> Result<String> {
+        let name = match self.search_symbol(key) {
             Some(SymbolReturn { content, dep }) => match content {
                 SymbolInfo::Var(_) => match dep {
                     //全局变量符号前面加上GLOBAL_VAR_"关键字
@@ -120,27 +421,323 @@ impl GenerateIrInfo {
                             + &self.block_id[dep as usize].to_string()
                     }
                 },
-                SymbolInfo::Const(_) => panic!("尝试查询常量的名称"),
+                SymbolInfo::Const(_) => {
+                    return Err(CompileError::internal(format!("尝试查询常量{}的名称", key)))
+                }
             },
-            None => panic!(
-                "尝试查询不存在的变量: {}\n当前block_id为{}\n符号表结构为{:#?}",
-                key, self.now_block_id, self.tables,
-            ),
-        }
+            None => {
+                return Err(CompileError::internal(format!(
+                    "尝试查询不存在的变量: {}, 当前block_id为{}",
+                    key, self.now_block_id,
+                )))
+            }
+        };
+        Ok(name)
     }
 
-    //TODO: 检测符号表重复插入，避免符号覆盖
+    ///检查key能否插入table，同一作用域内不允许重复定义，不同作用域间的遮蔽是合法的
+    fn check_redefinition(table: &SymbolTable, key: &str, span: Span) -> Result<()> {
+        match table.get(key) {
+            None => Ok(()),
+            Some(SymbolInfo::Func(func_info)) if func_info.is_lib => Err(CompileError::semantic(
+                span,
+                format!("不能重新定义库函数`{}`", key),
+            )),
+            Some(_) => Err(CompileError::semantic(
+                span,
+                format!("重复定义了符号`{}`", key),
+            )),
+        }
+    }
 
-    ///插入符号表
-    pub fn insert_symbol(&mut self, key: String, value: SymbolInfo) {
-        self.tables.last_mut().unwrap().insert(key, value);
+    ///插入符号表，span为定义处的位置，用于报告重复定义
+    pub fn insert_symbol(&mut self, key: String, value: SymbolInfo, span: Span) -> Result<()> {
+        let table = self.tables.last_mut().unwrap();
+        Self::check_redefinition(table, &key, span)?;
+        table.insert(key, value);
 
         symbol_table_debug!("插入符号表成功\n表结构为{:#?}", self.tables);
+        Ok(())
     }
     ///插入全局符号，全局符号表就是tables[0]
-    pub fn insert_global_symbol(&mut self, key: String, value: SymbolInfo) {
+    pub fn insert_global_symbol(
+        &mut self,
+        key: String,
+        value: SymbolInfo,
+        span: Span,
+    ) -> Result<()> {
+        Self::check_redefinition(&self.tables[0], &key, span)?;
         self.tables[0].insert(key, value);
         symbol_table_debug!("插入全局符号表成功\n表结构为{:#?}", self.tables);
+        Ok(())
+    }
+
+    ///登记函数的声明或定义，签名必须和之前的声明一致
+    ///库函数可以再声明但不能重新定义，同一个函数只能定义一次
+    pub fn declare_func(
+        &mut self,
+        key: String,
+        func: FuncInfoBase,
+        span: Span,
+        is_def: bool,
+    ) -> Result<()> {
+        match self.tables[0].get(&key) {
+            None => {}
+            Some(SymbolInfo::Func(old)) if old.is_lib && is_def => {
+                return Err(CompileError::semantic(
+                    span,
+                    format!("不能重新定义库函数`{}`", key),
+                ))
+            }
+            Some(SymbolInfo::Func(old))
+                if old.ret_type != func.ret_type || old.params != func.params =>
+            {
+                return Err(CompileError::semantic(
+                    span,
+                    format!("函数`{}`与之前的声明不一致", key),
+                ))
+            }
+            Some(SymbolInfo::Func(_)) => {}
+            Some(_) => {
+                return Err(CompileError::semantic(
+                    span,
+                    format!("重复定义了符号`{}`", key),
+                ))
+            }
+        }
+        if is_def && !self.defined_funcs.insert(key.clone()) {
+            return Err(CompileError::semantic(
+                span,
+                format!("重复定义了函数`{}`", key),
+            ));
+        }
+        if self.tables[0].get(&key).is_none() {
+            self.tables[0].insert(key, SymbolInfo::Func(func));
+        }
+        Ok(())
+    }
+
+    ///字节偏移所在行在原始文件中的行号，没有行号表时为0
+    pub fn source_line(&self, offset: usize) -> usize {
+        let index = self
+            .line_table
+            .partition_point(|&(start, _)| start <= offset);
+        match index.checked_sub(1) {
+            Some(index) => self.line_table[index].1,
+            None => 0,
+        }
+    }
+
+    ///starttime()/stoptime()由运行时库中带行号的_sysy_starttime/_sysy_stoptime实现
+    ///返回实际要调用的函数名，运行时库中没有这两个函数时返回None，按原样调用
+    pub fn timer_func(&self, ident: &str, func_info: &FuncInfoBase) -> Option<String> {
+        if !func_info.is_lib || !matches!(ident, "starttime" | "stoptime") {
+            return None;
+        }
+        let name = format!("_sysy_{}", ident);
+        match self.tables[0].get(&name) {
+            Some(SymbolInfo::Func(timer)) if timer.is_lib && timer.params == [ParamKind::Int] => {
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+
+    ///把运行时库的函数插入全局符号表
+    pub fn insert_lib_funcs(&mut self) {
+        for (name, func_info) in &self.runtime {
+            self.tables[0].insert(name.clone(), SymbolInfo::Func(func_info.clone()));
+        }
+        //putf(fmt, ...)的格式串之后可以跟任意个参数
+        self.tables[0].insert(
+            "putf".to_string(),
+            SymbolInfo::Func(FuncInfoBase {
+                variadic: true,
+                ..FuncInfoBase::new_lib(FuncType::Void, vec![ParamKind::Str])
+            }),
+        );
+    }
+
+    ///字符串字面量对应的全局变量，第一次出现时生成以0结尾的全局数组
+    pub fn string_literal(&mut self, program: &mut Program, bytes: &[u8]) -> Value {
+        if let Some(&string) = self.strings.get(bytes) {
+            return string;
+        }
+        let elems: Vec<Value> = bytes
+            .iter()
+            .chain(&[0])
+            .map(|&byte| program.new_value().integer(byte as i32))
+            .collect();
+        let init = program.new_value().aggregate(elems);
+        let string = program.new_value().global_alloc(init);
+        let name = string_name(self.strings.len());
+        program.set_value_name(string, Some(format!("@{}", name)));
+        self.strings.insert(bytes.to_vec(), string);
+        string
+    }
+
+    ///浮点运算的内建函数，第一次用到时生成声明，生成汇编时展开成RV32F指令
+    pub fn float_intrinsic(&mut self, program: &mut Program, op: FloatIntrinsic) -> Function {
+        if let Some(&func) = self.funcs.get(op.name()) {
+            return func;
+        }
+        let func = program.new_func(op.decl());
+        self.funcs.insert(op.name().to_string(), func);
+        func
+    }
+
+    ///putf的某种可变实参组合对应的函数，第一次出现时生成声明
+    pub fn putf_decl(&mut self, program: &mut Program, is_float: &[bool]) -> Function {
+        let name = putf_name(is_float);
+        if let Some(&func) = self.funcs.get(&name) {
+            return func;
+        }
+        let mut params = vec![Type::get_pointer(Type::get_i32())];
+        params.extend(is_float.iter().map(|_| Type::get_i32()));
+        let decl = FunctionData::new_decl(format!("@{}", name), params, Type::get_unit());
+        let func = program.new_func(decl);
+        self.funcs.insert(name, func);
+        func
+    }
+
+    ///符号表中的函数对应的IR函数，第一次用到时按签名创建
+    ///本文件中的函数创建时带有形参，定义时再补上函数体，没有定义的输出为decl
+    pub fn func(&mut self, program: &mut Program, key: &str) -> Result<Function> {
+        if let Some(&func) = self.funcs.get(key) {
+            return Ok(func);
+        }
+        let func_info = match self.tables[0].get(key) {
+            Some(SymbolInfo::Func(func_info)) => func_info,
+            _ => {
+                return Err(CompileError::internal(format!(
+                    "尝试查询不存在的函数: {}",
+                    key
+                )))
+            }
+        };
+        let name = format!("@{}", key);
+        let params = func_info.params.iter().map(param_type).collect();
+        let ret = ret_type(func_info.ret_type);
+        let data = match func_info.is_lib {
+            true => FunctionData::new_decl(name, params, ret),
+            false => FunctionData::new(name, params, ret),
+        };
+        let func = program.new_func(data);
+        self.funcs.insert(key.to_string(), func);
+        Ok(func)
+    }
+
+    ///当前函数
+    pub fn func_data<'a>(&self, program: &'a mut Program) -> &'a mut FunctionData {
+        program.func_mut(self.now_func.unwrap())
+    }
+
+    ///在当前基本块的末尾插入一条指令
+    pub fn inst(&self, program: &mut Program, build: impl FnOnce(LocalBuilder) -> Value) -> Value {
+        let func_data = self.func_data(program);
+        let inst = build(func_data.dfg_mut().new_value());
+        func_data
+            .layout_mut()
+            .bb_mut(self.now_bb.unwrap())
+            .insts_mut()
+            .push_key_back(inst)
+            .unwrap();
+        inst
+    }
+
+    ///当前函数中的整数常量
+    pub fn integer(&self, program: &mut Program, val: i32) -> Value {
+        self.func_data(program).dfg_mut().new_value().integer(val)
+    }
+
+    ///标号对应的基本块，第一次用到时创建，label带%
+    pub fn bb(&mut self, program: &mut Program, label: &str) -> BasicBlock {
+        if let Some(&bb) = self.bbs.get(label) {
+            return bb;
+        }
+        let bb = self
+            .func_data(program)
+            .dfg_mut()
+            .new_bb()
+            .basic_block(Some(label.to_string()));
+        self.bbs.insert(label.to_string(), bb);
+        bb
+    }
+
+    ///把标号对应的基本块接在函数末尾，之后的指令都生成到这个块中
+    pub fn start_bb(&mut self, program: &mut Program, label: &str) {
+        let bb = self.bb(program, label);
+        self.func_data(program)
+            .layout_mut()
+            .bbs_mut()
+            .push_key_back(bb)
+            .unwrap();
+        self.now_bb = Some(bb);
+    }
+
+    ///生成跳转到label的jump
+    pub fn jump(&mut self, program: &mut Program, label: &str) {
+        let target = self.bb(program, label);
+        self.inst(program, |builder| builder.jump(target));
+    }
+
+    ///生成条件跳转，cond非0时跳转到true_label，否则跳转到false_label
+    pub fn branch(
+        &mut self,
+        program: &mut Program,
+        cond: Value,
+        true_label: &str,
+        false_label: &str,
+    ) {
+        let true_bb = self.bb(program, true_label);
+        let false_bb = self.bb(program, false_label);
+        self.inst(program, |builder| builder.branch(cond, true_bb, false_bb));
+    }
+
+    ///在当前基本块中分配局部变量，name不带@
+    pub fn alloc(&mut self, program: &mut Program, name: String, ty: Type) -> Value {
+        let alloc = self.inst(program, |builder| builder.alloc(ty));
+        self.func_data(program)
+            .dfg_mut()
+            .set_value_name(alloc, Some(format!("@{}", name)));
+        self.allocs.insert(name, alloc);
+        alloc
+    }
+
+    ///分配全局变量，name不带@
+    pub fn global_alloc(&mut self, program: &mut Program, name: String, init: Value) -> Value {
+        let alloc = program.new_value().global_alloc(init);
+        program.set_value_name(alloc, Some(format!("@{}", name)));
+        self.allocs.insert(name, alloc);
+        alloc
+    }
+
+    ///变量、数组或数组指针对应的alloc
+    pub fn get_alloc(&self, key: &str) -> Result<Value> {
+        let name = self.get_name(key)?;
+        match self.allocs.get(&name) {
+            Some(&alloc) => Ok(alloc),
+            None => Err(CompileError::internal(format!("符号`{}`没有分配空间", key))),
+        }
+    }
+
+    ///收集所有函数的浮点调用约定，交给后端
+    pub fn float_abi_table(&self) -> FloatAbiTable {
+        let mut abi_table = FloatAbiTable::new();
+        for (name, symbol) in self.tables[0].iter() {
+            if let SymbolInfo::Func(func_info) = symbol {
+                let abi = FloatAbi {
+                    params: func_info
+                        .params
+                        .iter()
+                        .map(|param| *param == ParamKind::Float)
+                        .collect(),
+                    ret: func_info.ret_type == FuncType::Float,
+                };
+                abi_table.insert(name.clone(), abi);
+            }
+        }
+        abi_table
     }
 
     ///新建一个block
@@ -166,10 +763,11 @@ impl GenerateIrInfo {
         symbol_table_debug!("表结构为{:#?}", self.tables);
     }
 
-    ///新建一个while循环块
+    ///新建一个循环块，while/for/do-while都用%while_begin_N作为continue的目标，
+    ///%while_end_N作为break的目标
     pub fn push_while(&mut self) {
         self.while_id += 1;
-        self.while_history.push(self.while_id);
+        self.while_history.push(BreakTarget::While(self.while_id));
 
         while_stack_debug!(
             "新建while循环块: {}\nwhile栈结构为{:#?}",
@@ -186,4 +784,44 @@ impl GenerateIrInfo {
 
         while_stack_debug!("while栈结构为{:#?}", self.while_history);
     }
+
+    ///新建一个switch块，%switch_end_N作为break的目标
+    pub fn push_switch(&mut self) {
+        self.switch_id += 1;
+        self.while_history.push(BreakTarget::Switch(self.switch_id));
+
+        while_stack_debug!(
+            "新建switch块: {}\nwhile栈结构为{:#?}",
+            self.switch_id,
+            self.while_history
+        );
+    }
+
+    ///删除一个switch块
+    pub fn pop_switch(&mut self) {
+        while_stack_debug!("删除switch块: {}", self.while_history.last().unwrap(),);
+
+        self.while_history.pop();
+
+        while_stack_debug!("while栈结构为{:#?}", self.while_history);
+    }
+
+    ///break跳转的目标，最内层是循环时跳出循环，是switch时跳出switch
+    pub fn break_label(&self) -> Option<String> {
+        self.while_history.last().map(|target| match target {
+            BreakTarget::While(id) => format!("%while_end_{}", id),
+            BreakTarget::Switch(id) => format!("%switch_end_{}", id),
+        })
+    }
+
+    ///continue跳转的目标，跳过外层的switch找到最内层的循环
+    pub fn continue_label(&self) -> Option<String> {
+        self.while_history
+            .iter()
+            .rev()
+            .find_map(|target| match target {
+                BreakTarget::While(id) => Some(format!("%while_begin_{}", id)),
+                BreakTarget::Switch(_) => None,
+            })
+    }
 }
@@ -0,0 +1,186 @@
+//! 优化遍共用的修改IR的工具
+//!
+//! koopa重建一个值(replace_value_with)时会丢掉这个值的used_by，所以优化遍不依赖值的used_by，
+//! 需要使用关系时扫描函数中的指令求出来。基本块的used_by总是准确的，可以直接用
+use koopa::ir::builder_traits::*;
+use koopa::ir::{BasicBlock, FunctionData, Type, Value, ValueKind};
+use std::collections::HashMap;
+
+///按布局顺序列出函数中的所有指令和它们所在的基本块
+pub fn insts(func: &FunctionData) -> Vec<(BasicBlock, Value)> {
+    let mut insts = vec![];
+    for (&bb, node) in func.layout().bbs() {
+        for &inst in node.insts().keys() {
+            insts.push((bb, inst));
+        }
+    }
+    insts
+}
+
+///把指令的每个操作数换成map_value的结果后重建指令，名字保持不变
+pub fn rebuild_inst(func: &mut FunctionData, inst: Value, map_value: impl Fn(Value) -> Value) {
+    let data = func.dfg().value(inst);
+    let name = data.name().clone();
+    let map_args = |args: &[Value]| args.iter().map(|&arg| map_value(arg)).collect::<Vec<_>>();
+    let kind = data.kind().clone();
+    let builder = func.dfg_mut().replace_value_with(inst);
+    match kind {
+        ValueKind::Load(load) => builder.load(map_value(load.src())),
+        ValueKind::Store(store) => builder.store(map_value(store.value()), map_value(store.dest())),
+        ValueKind::GetPtr(get_ptr) => {
+            builder.get_ptr(map_value(get_ptr.src()), map_value(get_ptr.index()))
+        }
+        ValueKind::GetElemPtr(get_elem_ptr) => builder.get_elem_ptr(
+            map_value(get_elem_ptr.src()),
+            map_value(get_elem_ptr.index()),
+        ),
+        ValueKind::Binary(binary) => builder.binary(
+            binary.op(),
+            map_value(binary.lhs()),
+            map_value(binary.rhs()),
+        ),
+        ValueKind::Branch(branch) => builder.branch_with_args(
+            map_value(branch.cond()),
+            branch.true_bb(),
+            branch.false_bb(),
+            map_args(branch.true_args()),
+            map_args(branch.false_args()),
+        ),
+        ValueKind::Jump(jump) => builder.jump_with_args(jump.target(), map_args(jump.args())),
+        ValueKind::Call(call) => builder.call(call.callee(), map_args(call.args())),
+        ValueKind::Return(ret) => builder.ret(ret.value().map(&map_value)),
+        //alloc没有操作数
+        _ => return,
+    };
+    func.dfg_mut().set_value_name(inst, name);
+}
+
+///重建br/jump，每条出边的(目标, 参数)换成f的结果
+pub fn rewrite_edges(
+    func: &mut FunctionData,
+    inst: Value,
+    mut f: impl FnMut(BasicBlock, Vec<Value>) -> (BasicBlock, Vec<Value>),
+) {
+    match func.dfg().value(inst).kind().clone() {
+        ValueKind::Branch(branch) => {
+            let (true_bb, true_args) = f(branch.true_bb(), branch.true_args().to_vec());
+            let (false_bb, false_args) = f(branch.false_bb(), branch.false_args().to_vec());
+            func.dfg_mut().replace_value_with(inst).branch_with_args(
+                branch.cond(),
+                true_bb,
+                false_bb,
+                true_args,
+                false_args,
+            );
+        }
+        ValueKind::Jump(jump) => {
+            let (target, args) = f(jump.target(), jump.args().to_vec());
+            func.dfg_mut()
+                .replace_value_with(inst)
+                .jump_with_args(target, args);
+        }
+        _ => {}
+    }
+}
+
+///把所有指令中对map里的值的使用换成映射后的值，映射可以是链式的
+pub fn replace_uses(func: &mut FunctionData, map: &HashMap<Value, Value>) {
+    if map.is_empty() {
+        return;
+    }
+    let resolve = |mut value: Value| {
+        while let Some(&next) = map.get(&value) {
+            value = next;
+        }
+        value
+    };
+    for (_, inst) in insts(func) {
+        let kind = func.dfg().value(inst).kind();
+        if kind.value_uses().any(|value| map.contains_key(&value)) {
+            rebuild_inst(func, inst, resolve);
+        }
+    }
+}
+
+///给基本块追加参数，返回新参数
+///koopa只能在创建基本块时创建参数，所以先创建一个参数更多的临时基本块，把多出的参数移过来再删掉它
+pub fn add_bb_params(func: &mut FunctionData, bb: BasicBlock, tys: Vec<Type>) -> Vec<Value> {
+    let old_len = func.dfg().bb(bb).params().len();
+    let mut all_tys: Vec<Type> = func
+        .dfg()
+        .bb(bb)
+        .params()
+        .iter()
+        .map(|&param| func.dfg().value(param).ty().clone())
+        .collect();
+    all_tys.extend(tys);
+    let tmp = func
+        .dfg_mut()
+        .new_bb()
+        .basic_block_with_params(None, all_tys);
+    let params = func.dfg_mut().bb_mut(tmp).params_mut().split_off(old_len);
+    func.dfg_mut()
+        .bb_mut(bb)
+        .params_mut()
+        .extend(params.iter().copied());
+    func.dfg_mut().remove_bb(tmp);
+    params
+}
+
+///从布局和数据流图中删除一组指令，这组指令之外不能再有指令使用它们
+pub fn remove_insts(func: &mut FunctionData, insts: &[Value]) {
+    for &inst in insts {
+        if let Some(bb) = func.layout().parent_bb(inst) {
+            func.layout_mut().bb_mut(bb).insts_mut().remove(&inst);
+        }
+    }
+    //先删使用者再删被使用的值，被使用的次数只统计这组指令之内的
+    let mut users: HashMap<Value, usize> = insts.iter().map(|&inst| (inst, 0)).collect();
+    for &inst in insts {
+        for value in func.dfg().value(inst).kind().value_uses() {
+            if let Some(count) = users.get_mut(&value) {
+                *count += 1;
+            }
+        }
+    }
+    let mut ready: Vec<Value> = insts
+        .iter()
+        .copied()
+        .filter(|inst| users[inst] == 0)
+        .collect();
+    while let Some(inst) = ready.pop() {
+        if !func.dfg().value(inst).used_by().is_empty() {
+            continue;
+        }
+        let data = func.dfg_mut().remove_value(inst);
+        for value in data.kind().value_uses() {
+            if let Some(count) = users.get_mut(&value) {
+                *count -= 1;
+                if *count == 0 {
+                    ready.push(value);
+                }
+            }
+        }
+    }
+}
+
+///删除一组基本块和其中的所有指令，这组基本块之外不能再有指令跳转到它们
+pub fn remove_bbs(func: &mut FunctionData, bbs: &[BasicBlock]) {
+    let mut dead = vec![];
+    for bb in bbs {
+        dead.extend(
+            func.layout()
+                .bbs()
+                .node(bb)
+                .unwrap()
+                .insts()
+                .keys()
+                .copied(),
+        );
+    }
+    remove_insts(func, &dead);
+    for bb in bbs {
+        func.layout_mut().bbs_mut().remove(bb);
+        func.dfg_mut().remove_bb(*bb);
+    }
+}
@@ -0,0 +1,68 @@
+//! 编译错误类型
+//!
+//! 整条流水线(解析、生成IR、生成汇编)都返回`Result<T, CompileError>`，
+//! 由main统一渲染并以非零状态退出
+
+use std::fmt;
+use std::io;
+
+use crate::ast::Span;
+use crate::diagnostics::Diagnostic;
+use crate::diagnostics::SourceFile;
+
+#[derive(Debug)]
+pub enum CompileError {
+    ///语法错误，可能有多条
+    Syntax(Vec<Diagnostic>),
+    ///语义错误，可能有多条
+    Semantic(Vec<Diagnostic>),
+    ///编译器内部错误，说明编译器自身有bug
+    Internal(String),
+    ///读写文件失败
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, CompileError>;
+
+impl CompileError {
+    ///在span处构造一条语义错误
+    pub fn semantic(span: Span, message: String) -> Self {
+        CompileError::Semantic(vec![Diagnostic::error(span, message)])
+    }
+
+    ///构造一条编译器内部错误
+    pub fn internal(message: String) -> Self {
+        CompileError::Internal(message)
+    }
+
+    ///渲染成最终输出给用户的文字
+    pub fn render(&self, source: &SourceFile) -> String {
+        match self {
+            CompileError::Syntax(diags) | CompileError::Semantic(diags) => {
+                diags.iter().map(|diag| diag.render(source)).collect()
+            }
+            _ => format!("{}\n", self),
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileError::Syntax(diags) | CompileError::Semantic(diags) => {
+                for diag in diags {
+                    writeln!(f, "{}: {}", diag.level, diag.message)?;
+                }
+                Ok(())
+            }
+            CompileError::Internal(message) => write!(f, "internal error: {}", message),
+            CompileError::Io(err) => write!(f, "error: {}", err),
+        }
+    }
+}
+
+impl From<io::Error> for CompileError {
+    fn from(err: io::Error) -> Self {
+        CompileError::Io(err)
+    }
+}
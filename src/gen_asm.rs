@@ -1,15 +1,120 @@
 //! 根据内存形式 Koopa IR 生成汇编
-use std::fmt::write;
 use std::{fs::File, io::Write};
 
 use crate::ds_for_asm::check_i12;
 use crate::ds_for_asm::GenerateAsmInfo;
 use crate::ds_for_asm::UserKind;
+use crate::ds_for_asm::{arg_locations, stack_args_size, ArgLoc};
+use crate::ds_for_asm::{find_jump_tables, JumpTable};
+use crate::ds_for_asm::{variadic_offset, variadic_slots};
+use crate::ds_for_cfg::Cfg;
+use crate::ds_for_ir::{fwd_target, is_string_name, putf_arg_kinds};
+use crate::ds_for_ir::{FloatAbi, FloatAbiTable, FloatIntrinsic};
+use crate::error::Result;
 use koopa::ir::values::*;
 use koopa::ir::*;
 pub trait GenerateAsm {
     type GenerateResult;
-    fn generate(&self, output: &mut File, program_info: &Program) -> Self::GenerateResult;
+    fn generate(
+        &self,
+        output: &mut File,
+        program_info: &Program,
+        abi: &FloatAbiTable,
+    ) -> Result<Self::GenerateResult>;
+}
+
+///putf的可变实参中float要提升成double，RV32F没有fcvt.d.s，只能用整数运算转换
+///输入a0为float的位模式，输出a0为double的低32位、a1为高32位，只改动a0-a1和t0-t4
+const FTOD_HELPER: &str = "  .text
+__sysy_ftod:
+  srli t0, a0, 31
+  slli t0, t0, 31
+  srli t1, a0, 23
+  andi t1, t1, 255
+  li t2, 8388607
+  and t2, a0, t2
+  li t3, 255
+  beq t1, t3, .Lftod_special
+  beqz t1, .Lftod_small
+  addi t1, t1, 896
+.Lftod_pack:
+  slli t1, t1, 20
+  or t0, t0, t1
+  srli t3, t2, 3
+  or a1, t0, t3
+  slli a0, t2, 29
+  ret
+.Lftod_special:
+  li t1, 2047
+  j .Lftod_pack
+.Lftod_small:
+  beqz t2, .Lftod_pack
+  li t1, 897
+  li t3, 8388608
+.Lftod_norm:
+  slli t2, t2, 1
+  addi t1, t1, -1
+  and t4, t2, t3
+  beqz t4, .Lftod_norm
+  xor t2, t2, t3
+  j .Lftod_pack
+";
+
+///查询函数的float调用约定，查不到时当作参数和返回值都是int
+fn func_abi(abi: &FloatAbiTable, name: &str, param_cnt: usize) -> FloatAbi {
+    match abi.get(name) {
+        Some(func_abi) => func_abi.clone(),
+        None => FloatAbi {
+            params: vec![false; param_cnt],
+            ret: false,
+        },
+    }
+}
+
+///把对float内建函数的调用展开成RV32F指令，参数和结果都是float的位模式
+fn float_intrinsic(
+    output: &mut File,
+    func_data: &koopa::ir::FunctionData,
+    func_info: &mut GenerateAsmInfo,
+    op: FloatIntrinsic,
+    args: &[Value],
+    inst: Value,
+    program_info: &Program,
+) -> Result<()> {
+    for (i, &arg) in args.iter().enumerate() {
+        let reg_arg = get_reg(output, func_data, func_info, arg, program_info)?;
+        if op == FloatIntrinsic::FromInt {
+            writeln!(output, "  fcvt.s.w ft{}, {}", i, reg_arg)?;
+        } else {
+            writeln!(output, "  fmv.w.x ft{}, {}", i, reg_arg)?;
+        }
+        free_reg(func_data, func_info, arg)?;
+    }
+    let reg_ret = get_reg(output, func_data, func_info, inst, program_info)?;
+    match op {
+        FloatIntrinsic::Add | FloatIntrinsic::Sub | FloatIntrinsic::Mul | FloatIntrinsic::Div => {
+            let name = match op {
+                FloatIntrinsic::Add => "fadd.s",
+                FloatIntrinsic::Sub => "fsub.s",
+                FloatIntrinsic::Mul => "fmul.s",
+                _ => "fdiv.s",
+            };
+            writeln!(output, "  {} ft0, ft0, ft1", name)?;
+            writeln!(output, "  fmv.x.w {}, ft0", reg_ret)?;
+        }
+        FloatIntrinsic::Eq => writeln!(output, "  feq.s {}, ft0, ft1", reg_ret)?,
+        FloatIntrinsic::Ne => {
+            writeln!(output, "  feq.s {}, ft0, ft1", reg_ret)?;
+            writeln!(output, "  seqz {}, {}", reg_ret, reg_ret)?;
+        }
+        FloatIntrinsic::Lt => writeln!(output, "  flt.s {}, ft0, ft1", reg_ret)?,
+        FloatIntrinsic::Gt => writeln!(output, "  flt.s {}, ft1, ft0", reg_ret)?,
+        FloatIntrinsic::Le => writeln!(output, "  fle.s {}, ft0, ft1", reg_ret)?,
+        FloatIntrinsic::Ge => writeln!(output, "  fle.s {}, ft1, ft0", reg_ret)?,
+        FloatIntrinsic::FromInt => writeln!(output, "  fmv.x.w {}, ft0", reg_ret)?,
+        FloatIntrinsic::ToInt => writeln!(output, "  fcvt.w.s {}, ft0, rtz", reg_ret)?,
+    }
+    Ok(())
 }
 
 fn get_reg(
@@ -18,7 +123,7 @@ fn get_reg(
     func_info: &mut GenerateAsmInfo,
     value: Value,
     program_info: &Program,
-) -> String {
+) -> Result<String> {
     if value.is_global() {
         //全局变量直接去找get_reg
         return func_info.get_reg(output, value, program_info);
@@ -28,105 +133,270 @@ fn get_reg(
     match val_data.kind() {
         ValueKind::Integer(val) => {
             if val.value() == 0 {
-                return "x0".to_string();
+                return Ok("x0".to_string());
             }
-            let reg = func_info.get_reg_i32(output, val.value());
-            reg
+            func_info.get_reg_i32(output, val.value())
         }
+        //未初始化的变量的值任意，直接当作0
+        ValueKind::Undef(_) => Ok("x0".to_string()),
         _ => func_info.get_reg(output, value, program_info), //expr
     }
 }
 
 ///释放寄存器，TODO:先都释放掉，后面加spill再把用到这个的都删掉
-fn free_reg(func_data: &koopa::ir::FunctionData, func_info: &mut GenerateAsmInfo, value: Value) {
+fn free_reg(
+    func_data: &koopa::ir::FunctionData,
+    func_info: &mut GenerateAsmInfo,
+    value: Value,
+) -> Result<()> {
     if value.is_global() {
         //全局变量直接释放，不要再表中查询
-        func_info.free_reg(UserKind::Val(value));
-        return;
+        return func_info.free_reg(UserKind::Val(value));
     }
     let val_data = func_data.dfg().value(value);
     match val_data.kind() {
         ValueKind::Integer(val) => {
             if val.value() == 0 {
                 //0不需要释放，之前用的x0
-                return;
+                return Ok(());
             }
-            func_info.free_reg(UserKind::Tmpi32(val.value()));
+            func_info.free_reg(UserKind::Tmpi32(val.value()))
+        }
+        ValueKind::Undef(_) => Ok(()),
+        _ => func_info.free_reg(UserKind::Val(value)),
+    }
+}
+
+fn load_by_offset(
+    output: &mut File,
+    func_info: &mut GenerateAsmInfo,
+    reg: &str,
+    offset: i32,
+) -> Result<()> {
+    if check_i12(offset) {
+        writeln!(output, "  lw {}, {}(sp)", reg, offset)?;
+    } else {
+        let reg_addr = func_info.get_reg_i32(output, offset)?;
+        writeln!(output, "  add {}, sp, {}", reg_addr, reg_addr)?;
+        writeln!(output, "  lw {}, 0({})", reg, reg_addr)?;
+        func_info.free_reg(UserKind::Tmpi32(offset))?;
+    }
+    Ok(())
+}
+
+///字符串字面量放在只读数据段，IR中的每个i32元素对应一个字节
+fn generate_string(output: &mut File, program_info: &Program, value: Value) -> Result<()> {
+    let data = program_info.borrow_value(value);
+    let ValueKind::GlobalAlloc(alloc) = data.kind() else {
+        unreachable!()
+    };
+    let init = program_info.borrow_value(alloc.init());
+    let ValueKind::Aggregate(aggregate) = init.kind() else {
+        unreachable!()
+    };
+    let bytes: Vec<String> = aggregate
+        .elems()
+        .iter()
+        .map(|&elem| match program_info.borrow_value(elem).kind() {
+            ValueKind::Integer(byte) => byte.value().to_string(),
+            _ => unreachable!(),
+        })
+        .collect();
+    writeln!(output, "  .byte {}", bytes.join(", "))?;
+    Ok(())
+}
+
+///用跳转表分派比较链：先减去最小的case值，越界时跳到default，否则从表中取出目标地址跳转
+///跳转表放在只读数据段，以函数名和链头的块名命名
+fn jump_table(
+    output: &mut File,
+    func_data: &koopa::ir::FunctionData,
+    func_info: &mut GenerateAsmInfo,
+    table: &JumpTable,
+    head_name: &str,
+    program_info: &Program,
+) -> Result<()> {
+    let bb_name = |bb| func_data.dfg().bb(bb).name().clone().unwrap()[1..].to_string();
+    let reg_value = get_reg(output, func_data, func_info, table.value, program_info)?;
+    let reg_index = func_info.get_reg_i32(output, table.low)?;
+    writeln!(output, "  sub {}, {}, {}", reg_index, reg_value, reg_index)?;
+    free_reg(func_data, func_info, table.value)?;
+
+    //无符号比较同时排除了小于最小值的情况
+    let len = table.targets.len() as i32;
+    let reg_addr = func_info.get_reg_i32(output, len)?;
+    writeln!(
+        output,
+        "  bltu {}, {}, JTTEMP_{}",
+        reg_index, reg_addr, head_name
+    )?;
+    writeln!(output, "  j {}", bb_name(table.default))?;
+    writeln!(output, "JTTEMP_{}:", head_name)?;
+    writeln!(output, "  la {}, JTABLE_{}", reg_addr, head_name)?;
+    writeln!(output, "  slli {}, {}, 2", reg_index, reg_index)?;
+    writeln!(output, "  add {}, {}, {}", reg_addr, reg_addr, reg_index)?;
+    writeln!(output, "  lw {}, 0({})", reg_addr, reg_addr)?;
+    writeln!(output, "  jr {}", reg_addr)?;
+    func_info.free_reg(UserKind::Tmpi32(len))?;
+    func_info.free_reg(UserKind::Tmpi32(table.low))?;
+
+    writeln!(output, "  .section .rodata")?;
+    writeln!(output, "  .align 2")?;
+    writeln!(output, "JTABLE_{}:", head_name)?;
+    for &target in &table.targets {
+        writeln!(output, "  .word {}", bb_name(target))?;
+    }
+    writeln!(output, "  .text")?;
+    Ok(())
+}
+
+///跳转到target之前把实参写到target的参数的栈位置上
+///实参中有target自己的参数时(比如循环中交换两个变量)，这些实参先复制到暂存区，避免被先写的参数覆盖
+fn block_args(
+    output: &mut File,
+    func_data: &koopa::ir::FunctionData,
+    func_info: &mut GenerateAsmInfo,
+    target: BasicBlock,
+    args: &[Value],
+    scratch_offset: i32,
+    program_info: &Program,
+) -> Result<()> {
+    let params = func_data.dfg().bb(target).params();
+    let moves: Vec<(Value, Value)> = params
+        .iter()
+        .copied()
+        .zip(args.iter().copied())
+        .filter(|(param, arg)| param != arg)
+        .collect();
+    let saved: Vec<bool> = moves.iter().map(|(_, arg)| params.contains(arg)).collect();
+    for (i, &(_, arg)) in moves.iter().enumerate() {
+        if saved[i] {
+            let reg_arg = get_reg(output, func_data, func_info, arg, program_info)?;
+            store_by_offset(output, func_info, &reg_arg, scratch_offset + i as i32 * 4)?;
+            free_reg(func_data, func_info, arg)?;
         }
-        _ => {
-            func_info.free_reg(UserKind::Val(value));
+    }
+    for (i, &(param, arg)) in moves.iter().enumerate() {
+        let param_offset = func_info.name_to_offset[&param];
+        if saved[i] {
+            let reg = func_info.new_tmp_reg(UserKind::BlockArg(i))?;
+            load_by_offset(output, func_info, &reg, scratch_offset + i as i32 * 4)?;
+            store_by_offset(output, func_info, &reg, param_offset)?;
+            func_info.free_reg(UserKind::BlockArg(i))?;
+        } else {
+            let reg_arg = get_reg(output, func_data, func_info, arg, program_info)?;
+            store_by_offset(output, func_info, &reg_arg, param_offset)?;
+            free_reg(func_data, func_info, arg)?;
         }
     }
+    Ok(())
 }
 
-fn store_by_offset(output: &mut File, func_info: &mut GenerateAsmInfo, reg: &str, offset: i32) {
+fn store_by_offset(
+    output: &mut File,
+    func_info: &mut GenerateAsmInfo,
+    reg: &str,
+    offset: i32,
+) -> Result<()> {
     if check_i12(offset) {
-        writeln!(output, "  sw {}, {}(sp)", reg, offset).unwrap();
+        writeln!(output, "  sw {}, {}(sp)", reg, offset)?;
     } else {
-        let reg_addr = func_info.get_reg_i32(output, offset);
-        writeln!(output, "  add {}, sp, {}", reg_addr, reg_addr).unwrap();
-        writeln!(output, "  sw {}, 0({})", reg, reg_addr).unwrap();
-        func_info.free_reg(UserKind::Tmpi32(offset));
+        let reg_addr = func_info.get_reg_i32(output, offset)?;
+        writeln!(output, "  add {}, sp, {}", reg_addr, reg_addr)?;
+        writeln!(output, "  sw {}, 0({})", reg, reg_addr)?;
+        func_info.free_reg(UserKind::Tmpi32(offset))?;
     }
+    Ok(())
 }
 
 /// 为Program实现GenerateAsm trait
 impl GenerateAsm for Program {
     type GenerateResult = ();
-    fn generate(&self, output: &mut File, program_info: &Program) {
+    fn generate(
+        &self,
+        output: &mut File,
+        program_info: &Program,
+        abi: &FloatAbiTable,
+    ) -> Result<()> {
         for &value in self.inst_layout() {
             let data = self.borrow_value(value);
             let name = &data.name().as_ref().unwrap()[1..];
             //info.insert_value(value, name.into());
-            writeln!(output, "  .data").unwrap();
-            writeln!(output, "  .globl {}", name).unwrap();
-            writeln!(output, "{}:", name).unwrap();
-            value.generate(output, program_info);
+            if is_string_name(name) {
+                writeln!(output, "  .section .rodata")?;
+                writeln!(output, "{}:", name)?;
+                generate_string(output, program_info, value)?;
+                continue;
+            }
+            writeln!(output, "  .data")?;
+            writeln!(output, "  .globl {}", name)?;
+            writeln!(output, "{}:", name)?;
+            value.generate(output, program_info, abi)?;
         }
         // 遍历函数列表
+        let mut need_ftod = false;
         for &func in self.func_layout() {
-            self.func(func).generate(output, program_info);
+            let func_data = self.func(func);
+            if let Some(is_float) = putf_arg_kinds(&func_data.name()[1..]) {
+                need_ftod |= is_float.contains(&true);
+            }
+            func_data.generate(output, program_info, abi)?;
+        }
+        if need_ftod {
+            write!(output, "{}", FTOD_HELPER)?;
         }
+        Ok(())
     }
 }
 
 impl GenerateAsm for koopa::ir::Value {
     type GenerateResult = ();
-    fn generate(&self, output: &mut File, program_info: &Program) {
+    fn generate(
+        &self,
+        output: &mut File,
+        program_info: &Program,
+        _abi: &FloatAbiTable, //全局初值只有整数，用不到调用约定
+    ) -> Result<()> {
         let data = program_info.borrow_value(*self);
         match data.kind() {
             ValueKind::GlobalAlloc(v) => {
                 let x = v.init();
-                x.generate(output, program_info);
+                x.generate(output, program_info, _abi)?;
             }
             ValueKind::Aggregate(v) => {
                 for &elem in v.elems() {
-                    elem.generate(output, program_info);
+                    elem.generate(output, program_info, _abi)?;
                 }
             }
             ValueKind::Integer(v) => {
-                writeln!(output, "  .word {}", v.value()).unwrap();
+                writeln!(output, "  .word {}", v.value())?;
             }
             ValueKind::ZeroInit(_) => {
-                writeln!(output, "  .zero {}", data.ty().size()).unwrap();
+                writeln!(output, "  .zero {}", data.ty().size())?;
             }
             _ => {}
         }
+        Ok(())
     }
 }
 
 /// 为FunctionData实现GenerateAsm trait
 impl GenerateAsm for koopa::ir::FunctionData {
     type GenerateResult = ();
-    fn generate(&self, output: &mut File, program_info: &Program) {
+    fn generate(
+        &self,
+        output: &mut File,
+        program_info: &Program,
+        abi: &FloatAbiTable,
+    ) -> Result<()> {
         //跳过声明
         if self.layout().entry_bb().is_none() {
-            return;
+            return Ok(());
         }
 
-        writeln!(output, "  .text").unwrap();
-        writeln!(output, "  .globl {}", &self.name()[1..]).unwrap();
-        writeln!(output, "{}:", &self.name()[1..]).unwrap();
+        writeln!(output, "  .text")?;
+        writeln!(output, "  .globl {}", &self.name()[1..])?;
+        writeln!(output, "{}:", &self.name()[1..])?;
 
         let mut func_info = GenerateAsmInfo::new();
 
@@ -145,23 +415,37 @@ impl GenerateAsm for koopa::ir::FunctionData {
                                  */
 
         //计算各部分大小
+        //参数和基本块参数都有自己的栈位置，跳转时还需要一块和基本块参数一样大的暂存区
+        let mut max_bb_params = 0;
+        local_var_size += self.params().len() as i32 * 4;
         for (&bb, node) in self.layout().bbs() {
+            let bb_params = self.dfg().bb(bb).params().len() as i32;
+            local_var_size += bb_params * 4;
+            max_bb_params = max_bb_params.max(bb_params);
             for &inst in node.insts().keys() {
                 let value_data = self.dfg().value(inst);
                 let value_type = value_data.ty();
                 match value_data.kind() {
                     ValueKind::Call(call_inst) => {
-                        let mut now_params_size = 0;
-                        let mut now_params_cnt = 0;
-                        ra_size = 4; //有函数调用，需要保存ra
-                        for &param in call_inst.args() {
-                            now_params_cnt += 1;
-                            if now_params_cnt > 8 {
-                                now_params_size += self.dfg().value(param).ty().size() as i32;
+                        let callee_name = &program_info.func(call_inst.callee()).name()[1..];
+                        //提前调用的函数换回原来的名字
+                        let callee_name = fwd_target(callee_name).unwrap_or(callee_name);
+                        if let Some(is_float) = putf_arg_kinds(callee_name) {
+                            //可变实参全部先写到参数区域
+                            ra_size = 4;
+                            let now_params_size = variadic_slots(&is_float).1 as i32 * 4;
+                            if now_params_size > param_size {
+                                param_size = now_params_size;
+                            }
+                        } else if FloatIntrinsic::from_name(callee_name).is_none() {
+                            //float内建函数直接展开，不是真正的调用
+                            ra_size = 4; //有函数调用，需要保存ra
+                            let callee_abi = func_abi(abi, callee_name, call_inst.args().len());
+                            let now_params_size =
+                                stack_args_size(&arg_locations(&callee_abi.params));
+                            if now_params_size > param_size {
+                                param_size = now_params_size;
                             }
-                        }
-                        if now_params_size > param_size {
-                            param_size = now_params_size;
                         }
                         local_var_size += value_type.size() as i32;
                     }
@@ -187,49 +471,92 @@ impl GenerateAsm for koopa::ir::FunctionData {
                 }
             }
         }
+        local_var_size += max_bb_params * 4;
         //计算sp偏移量并对齐16
         func_info.stack_size = ra_size + local_var_size + param_size;
         func_info.stack_size = (func_info.stack_size + 15) & !15; //check?
 
         //取回参数，此时栈指针还没有移动
-        {
-            let mut now_params_offset = 0;
-            for (i, &param) in self.params().iter().enumerate() {
-                let reg_param = get_reg(output, self, &mut func_info, param, program_info);
-                if i <= 7 {
-                    //参数个数小于等于8个，从a0-a7读，这里直接把对应寄存器的占用情况设置成对应参数
+        let self_abi = func_abi(abi, &self.name()[1..], self.params().len());
+        for (&param, loc) in self.params().iter().zip(arg_locations(&self_abi.params)) {
+            get_reg(output, self, &mut func_info, param, program_info)?;
+            match loc {
+                ArgLoc::Reg(i) => {
+                    //从a0-a7读，这里直接把对应寄存器的占用情况设置成对应参数
                     func_info.set_reg(&("a".to_owned() + &i.to_string()), param)
                     //注意，寄存器里的东西读完就不要再访问了，我们的前端保证一定会先把他们备份一遍
-                } else {
-                    //超过8个从栈上读取
-                    //直接把参数对应偏移量设置成对应位置
-                    func_info.set_offset(param, now_params_offset + func_info.stack_size);
-                    now_params_offset += self.dfg().value(param).ty().size() as i32;
                 }
-                free_reg(self, &mut func_info, param);
+                ArgLoc::FloatReg(i) => {
+                    //从fa0-fa7读，第一次使用时再搬到整数寄存器
+                    func_info.float_params.insert(param, i);
+                }
+                ArgLoc::Stack(offset) => {
+                    //从栈上读取，直接把参数对应偏移量设置成对应位置
+                    func_info.set_offset(param, offset + func_info.stack_size);
+                }
             }
+            free_reg(self, &mut func_info, param)?;
         }
 
         //移动栈指针
-        func_info.set_sp(output);
+        func_info.set_sp(output)?;
 
         //计算ra偏移量，是否保存ra是由ra_size决定的
         let ra_offset = local_var_size + param_size;
         if ra_size > 0 {
             //保存ra
-            store_by_offset(output, &mut func_info, "ra", ra_offset);
+            store_by_offset(output, &mut func_info, "ra", ra_offset)?;
         }
 
         //为每个元素分配栈偏移量，从参数区域正上方开始
         let mut now_stack_offset = param_size;
 
-        // 遍历基本块列表
-        for (&bb, node) in self.layout().bbs() {
+        //通过寄存器传入的参数存到栈上，之后在任何地方使用都从栈上读
+        for (&param, loc) in self.params().iter().zip(arg_locations(&self_abi.params)) {
+            if let ArgLoc::Stack(_) = loc {
+                continue;
+            }
+            get_reg(output, self, &mut func_info, param, program_info)?;
+            func_info.new_var(output, param, now_stack_offset, program_info)?;
+            free_reg(self, &mut func_info, param)?;
+            now_stack_offset += 4;
+        }
+        //基本块参数的栈位置预先分配好，前驱可能在这个基本块之后生成
+        for &bb in self.layout().bbs().keys() {
+            for &param in self.dfg().bb(bb).params() {
+                func_info.set_offset(param, now_stack_offset);
+                now_stack_offset += 4;
+            }
+        }
+        let scratch_offset = now_stack_offset;
+        now_stack_offset += max_bb_params * 4;
+
+        //稠密的比较链换成跳转表，链中除链头外的块不再生成
+        let (jump_tables, merged_bbs) = find_jump_tables(self);
+
+        // 按逆后序遍历基本块，值的定义总在使用之前生成，使用时已经分配了栈位置
+        let cfg = Cfg::new(self);
+        for &bb in &cfg.rpo {
+            let node = self.layout().bbs().node(&bb).unwrap();
+            if merged_bbs.contains(&bb) {
+                continue;
+            }
             // 一些必要的处理
             let bb_data = self.dfg().bb(bb);
             let block_name = bb_data.name().clone().unwrap();
-            if block_name != "%entry".to_owned() {
-                writeln!(output, "{}:", &block_name[1..]).unwrap();
+            if block_name != "%entry" {
+                writeln!(output, "{}:", &block_name[1..])?;
+            }
+            if let Some(table) = jump_tables.get(&bb) {
+                jump_table(
+                    output,
+                    self,
+                    &mut func_info,
+                    table,
+                    &format!("{}_{}", &self.name()[1..], &block_name[1..]),
+                    program_info,
+                )?;
+                continue;
             }
 
             // 遍历指令列表
@@ -252,60 +579,58 @@ impl GenerateAsm for koopa::ir::FunctionData {
                             };
                             //偏移量
                             let reg_index =
-                                get_reg(output, self, &mut func_info, index, program_info);
-                            let reg_ret = get_reg(output, self, &mut func_info, inst, program_info);
+                                get_reg(output, self, &mut func_info, index, program_info)?;
+                            let reg_ret =
+                                get_reg(output, self, &mut func_info, inst, program_info)?;
 
-                            let reg_size = func_info.get_reg_i32(output, src_size as i32);
+                            let reg_size = func_info.get_reg_i32(output, src_size as i32)?;
 
                             //把index乘以src_size存在reg_ret，因为另外两个都有可能是x0
-                            writeln!(output, "  mul {}, {}, {}", reg_ret, reg_index, reg_size)
-                                .unwrap();
-                            func_info.free_reg(UserKind::Tmpi32(src_size as i32));
-                            free_reg(self, &mut func_info, index);
+                            writeln!(output, "  mul {}, {}, {}", reg_ret, reg_index, reg_size)?;
+                            func_info.free_reg(UserKind::Tmpi32(src_size as i32))?;
+                            free_reg(self, &mut func_info, index)?;
 
                             //基地址
                             let reg_src =
-                                get_reg(output, self, &mut func_info, src_value, program_info);
+                                get_reg(output, self, &mut func_info, src_value, program_info)?;
 
-                            writeln!(output, "  add {}, {}, {}", reg_ret, reg_src, reg_ret)
-                                .unwrap();
+                            writeln!(output, "  add {}, {}, {}", reg_ret, reg_src, reg_ret)?;
 
-                            free_reg(self, &mut func_info, src_value);
+                            free_reg(self, &mut func_info, src_value)?;
 
-                            func_info.new_var(output, inst, now_stack_offset, program_info);
+                            func_info.new_var(output, inst, now_stack_offset, program_info)?;
                             now_stack_offset += value_data.ty().size() as i32;
-                            free_reg(self, &mut func_info, inst);
+                            free_reg(self, &mut func_info, inst)?;
                         } else {
                             //偏移量
                             let reg_index =
-                                get_reg(output, self, &mut func_info, index, program_info);
-                            let reg_ret = get_reg(output, self, &mut func_info, inst, program_info);
+                                get_reg(output, self, &mut func_info, index, program_info)?;
+                            let reg_ret =
+                                get_reg(output, self, &mut func_info, inst, program_info)?;
 
                             //对应数组类型大小
                             let src_size = match value_data.ty().kind() {
                                 TypeKind::Pointer(base) => base.size(),
                                 _ => unreachable!(),
                             };
-                            let reg_size = func_info.get_reg_i32(output, src_size as i32);
+                            let reg_size = func_info.get_reg_i32(output, src_size as i32)?;
 
                             //把index乘以src_size存在reg_ret，因为另外两个都有可能是x0
-                            writeln!(output, "  mul {}, {}, {}", reg_ret, reg_index, reg_size)
-                                .unwrap();
-                            func_info.free_reg(UserKind::Tmpi32(src_size as i32));
-                            free_reg(self, &mut func_info, index);
+                            writeln!(output, "  mul {}, {}, {}", reg_ret, reg_index, reg_size)?;
+                            func_info.free_reg(UserKind::Tmpi32(src_size as i32))?;
+                            free_reg(self, &mut func_info, index)?;
 
                             //基地址
                             let reg_src =
-                                get_reg(output, self, &mut func_info, src_value, program_info);
+                                get_reg(output, self, &mut func_info, src_value, program_info)?;
 
-                            writeln!(output, "  add {}, {}, {}", reg_ret, reg_src, reg_ret)
-                                .unwrap();
+                            writeln!(output, "  add {}, {}, {}", reg_ret, reg_src, reg_ret)?;
 
-                            free_reg(self, &mut func_info, src_value);
+                            free_reg(self, &mut func_info, src_value)?;
 
-                            func_info.new_var(output, inst, now_stack_offset, program_info);
+                            func_info.new_var(output, inst, now_stack_offset, program_info)?;
                             now_stack_offset += value_data.ty().size() as i32;
-                            free_reg(self, &mut func_info, inst);
+                            free_reg(self, &mut func_info, inst)?;
                         }
                     }
                     ValueKind::GetElemPtr(getelmprt_inst) => {
@@ -322,60 +647,58 @@ impl GenerateAsm for koopa::ir::FunctionData {
                             };
                             //偏移量
                             let reg_index =
-                                get_reg(output, self, &mut func_info, index, program_info);
-                            let reg_ret = get_reg(output, self, &mut func_info, inst, program_info);
+                                get_reg(output, self, &mut func_info, index, program_info)?;
+                            let reg_ret =
+                                get_reg(output, self, &mut func_info, inst, program_info)?;
 
-                            let reg_size = func_info.get_reg_i32(output, src_size as i32);
+                            let reg_size = func_info.get_reg_i32(output, src_size as i32)?;
 
                             //把index乘以src_size存在reg_ret，因为另外两个都有可能是x0
-                            writeln!(output, "  mul {}, {}, {}", reg_ret, reg_index, reg_size)
-                                .unwrap();
-                            func_info.free_reg(UserKind::Tmpi32(src_size as i32));
-                            free_reg(self, &mut func_info, index);
+                            writeln!(output, "  mul {}, {}, {}", reg_ret, reg_index, reg_size)?;
+                            func_info.free_reg(UserKind::Tmpi32(src_size as i32))?;
+                            free_reg(self, &mut func_info, index)?;
 
                             //基地址
                             let reg_src =
-                                get_reg(output, self, &mut func_info, src_value, program_info);
+                                get_reg(output, self, &mut func_info, src_value, program_info)?;
 
-                            writeln!(output, "  add {}, {}, {}", reg_ret, reg_src, reg_ret)
-                                .unwrap();
+                            writeln!(output, "  add {}, {}, {}", reg_ret, reg_src, reg_ret)?;
 
-                            free_reg(self, &mut func_info, src_value);
+                            free_reg(self, &mut func_info, src_value)?;
 
-                            func_info.new_var(output, inst, now_stack_offset, program_info);
+                            func_info.new_var(output, inst, now_stack_offset, program_info)?;
                             now_stack_offset += value_data.ty().size() as i32;
-                            free_reg(self, &mut func_info, inst);
+                            free_reg(self, &mut func_info, inst)?;
                         } else {
                             //偏移量
                             let reg_index =
-                                get_reg(output, self, &mut func_info, index, program_info);
-                            let reg_ret = get_reg(output, self, &mut func_info, inst, program_info);
+                                get_reg(output, self, &mut func_info, index, program_info)?;
+                            let reg_ret =
+                                get_reg(output, self, &mut func_info, inst, program_info)?;
 
                             //对应数组类型大小
                             let src_size = match value_data.ty().kind() {
                                 TypeKind::Pointer(base) => base.size(),
                                 _ => unreachable!(),
                             };
-                            let reg_size = func_info.get_reg_i32(output, src_size as i32);
+                            let reg_size = func_info.get_reg_i32(output, src_size as i32)?;
 
                             //把index乘以src_size存在reg_ret，因为另外两个都有可能是x0
-                            writeln!(output, "  mul {}, {}, {}", reg_ret, reg_index, reg_size)
-                                .unwrap();
-                            func_info.free_reg(UserKind::Tmpi32(src_size as i32));
-                            free_reg(self, &mut func_info, index);
+                            writeln!(output, "  mul {}, {}, {}", reg_ret, reg_index, reg_size)?;
+                            func_info.free_reg(UserKind::Tmpi32(src_size as i32))?;
+                            free_reg(self, &mut func_info, index)?;
 
                             //基地址
                             let reg_src =
-                                get_reg(output, self, &mut func_info, src_value, program_info);
+                                get_reg(output, self, &mut func_info, src_value, program_info)?;
 
-                            writeln!(output, "  add {}, {}, {}", reg_ret, reg_src, reg_ret)
-                                .unwrap();
+                            writeln!(output, "  add {}, {}, {}", reg_ret, reg_src, reg_ret)?;
 
-                            free_reg(self, &mut func_info, src_value);
+                            free_reg(self, &mut func_info, src_value)?;
 
-                            func_info.new_var(output, inst, now_stack_offset, program_info);
+                            func_info.new_var(output, inst, now_stack_offset, program_info)?;
                             now_stack_offset += value_data.ty().size() as i32;
-                            free_reg(self, &mut func_info, inst);
+                            free_reg(self, &mut func_info, inst)?;
                         }
                     }
 
@@ -387,83 +710,140 @@ impl GenerateAsm for koopa::ir::FunctionData {
                             _ => unreachable!(),
                         };
                         let data_offset = now_stack_offset + value_data.ty().size() as i32;
-                        let reg_ret = get_reg(output, self, &mut func_info, inst, program_info);
-                        let offset_reg = func_info.get_reg_i32(output, data_offset);
-                        writeln!(output, "  add {}, sp, {}", reg_ret, offset_reg).unwrap();
-                        func_info.free_reg(UserKind::Tmpi32(data_offset));
+                        let reg_ret = get_reg(output, self, &mut func_info, inst, program_info)?;
+                        let offset_reg = func_info.get_reg_i32(output, data_offset)?;
+                        writeln!(output, "  add {}, sp, {}", reg_ret, offset_reg)?;
+                        func_info.free_reg(UserKind::Tmpi32(data_offset))?;
 
-                        func_info.new_var(output, inst, now_stack_offset, program_info);
+                        func_info.new_var(output, inst, now_stack_offset, program_info)?;
                         //加上指针的偏移
                         now_stack_offset += value_data.ty().size() as i32;
                         //加上指针指向内存位置的偏移
                         now_stack_offset += alloc_size as i32;
-                        free_reg(self, &mut func_info, inst);
+                        free_reg(self, &mut func_info, inst)?;
                     }
 
                     ValueKind::Load(load_inst) => {
                         // 处理 load 指令
                         let addr = load_inst.src();
 
-                        let reg_ret = get_reg(output, self, &mut func_info, inst, program_info);
+                        let reg_ret = get_reg(output, self, &mut func_info, inst, program_info)?;
 
-                        let addr_reg = func_info.get_reg(output, addr, program_info);
-                        writeln!(output, "  lw {}, 0({})", reg_ret, addr_reg).unwrap();
-                        free_reg(self, &mut func_info, addr);
+                        let addr_reg = func_info.get_reg(output, addr, program_info)?;
+                        writeln!(output, "  lw {}, 0({})", reg_ret, addr_reg)?;
+                        free_reg(self, &mut func_info, addr)?;
 
-                        func_info.new_var(output, inst, now_stack_offset, program_info);
+                        func_info.new_var(output, inst, now_stack_offset, program_info)?;
                         now_stack_offset += value_data.ty().size() as i32;
-                        free_reg(self, &mut func_info, inst);
+                        free_reg(self, &mut func_info, inst)?;
                     }
 
                     ValueKind::Store(store_inst) => {
                         // 处理 store 指令
                         let addr = store_inst.dest();
                         let value = store_inst.value();
-                        let reg_val = get_reg(output, self, &mut func_info, value, program_info);
+                        let reg_val = get_reg(output, self, &mut func_info, value, program_info)?;
 
-                        let addr_reg = func_info.get_reg(output, addr, program_info);
-                        writeln!(output, "  sw {}, 0({})", reg_val, addr_reg).unwrap();
-                        free_reg(self, &mut func_info, addr);
-                        free_reg(self, &mut func_info, value);
+                        let addr_reg = func_info.get_reg(output, addr, program_info)?;
+                        writeln!(output, "  sw {}, 0({})", reg_val, addr_reg)?;
+                        free_reg(self, &mut func_info, addr)?;
+                        free_reg(self, &mut func_info, value)?;
                     }
                     ValueKind::Call(call_inst) => {
-                        let mut now_params_offset = 0;
-                        for (i, &param) in call_inst.args().iter().enumerate() {
+                        let callee_name = &program_info.func(call_inst.callee()).name()[1..];
+                        //提前调用的函数换回原来的名字
+                        let callee_name = fwd_target(callee_name).unwrap_or(callee_name);
+                        if let Some(op) = FloatIntrinsic::from_name(callee_name) {
+                            float_intrinsic(
+                                output,
+                                self,
+                                &mut func_info,
+                                op,
+                                call_inst.args(),
+                                inst,
+                                program_info,
+                            )?;
+                            func_info.new_var(output, inst, now_stack_offset, program_info)?;
+                            free_reg(self, &mut func_info, inst)?;
+                            now_stack_offset += value_data.ty().size() as i32;
+                            continue;
+                        }
+
+                        if let Some(is_float) = putf_arg_kinds(callee_name) {
+                            //先把可变实参依次写到参数区域，float经__sysy_ftod转成double
+                            let args = call_inst.args();
+                            let (slots, total) = variadic_slots(&is_float);
+                            for ((&arg, &float), slot) in args[1..].iter().zip(&is_float).zip(slots)
+                            {
+                                let reg_arg =
+                                    get_reg(output, self, &mut func_info, arg, program_info)?;
+                                if float {
+                                    writeln!(output, "  mv a0, {}", reg_arg)?;
+                                    free_reg(self, &mut func_info, arg)?;
+                                    writeln!(output, "  call __sysy_ftod")?;
+                                    let lo_offset = variadic_offset(slot, total);
+                                    store_by_offset(output, &mut func_info, "a0", lo_offset)?;
+                                    let hi_offset = variadic_offset(slot + 1, total);
+                                    store_by_offset(output, &mut func_info, "a1", hi_offset)?;
+                                } else {
+                                    let offset = variadic_offset(slot, total);
+                                    store_by_offset(output, &mut func_info, &reg_arg, offset)?;
+                                    free_reg(self, &mut func_info, arg)?;
+                                }
+                            }
+                            //再把前7个位置装入a1-a7，格式串放在a0
+                            for slot in 0..total.min(7) {
+                                let offset = variadic_offset(slot, total);
+                                let reg = format!("a{}", slot + 1);
+                                load_by_offset(output, &mut func_info, &reg, offset)?;
+                            }
+                            let reg_fmt =
+                                get_reg(output, self, &mut func_info, args[0], program_info)?;
+                            writeln!(output, "  mv a0, {}", reg_fmt)?;
+                            free_reg(self, &mut func_info, args[0])?;
+                            writeln!(output, "  call putf")?;
+                            continue;
+                        }
+
+                        let callee_abi = func_abi(abi, callee_name, call_inst.args().len());
+                        for (&param, loc) in call_inst
+                            .args()
+                            .iter()
+                            .zip(arg_locations(&callee_abi.params))
+                        {
                             let reg_param =
-                                get_reg(output, self, &mut func_info, param, program_info);
-                            if i <= 7 {
-                                //参数个数小于等于8个，存在a0-a7
-                                writeln!(output, "  mv a{}, {}", i, reg_param).unwrap();
-                            } else {
-                                //存栈上
-                                store_by_offset(
-                                    output,
-                                    &mut func_info,
-                                    &reg_param,
-                                    now_params_offset,
-                                );
-
-                                now_params_offset += self.dfg().value(param).ty().size() as i32;
+                                get_reg(output, self, &mut func_info, param, program_info)?;
+                            match loc {
+                                ArgLoc::Reg(i) => {
+                                    writeln!(output, "  mv a{}, {}", i, reg_param)?;
+                                }
+                                ArgLoc::FloatReg(i) => {
+                                    writeln!(output, "  fmv.w.x fa{}, {}", i, reg_param)?;
+                                }
+                                ArgLoc::Stack(offset) => {
+                                    store_by_offset(output, &mut func_info, &reg_param, offset)?;
+                                }
                             }
-                            free_reg(self, &mut func_info, param);
+                            free_reg(self, &mut func_info, param)?;
                         }
-                        writeln!(
-                            output,
-                            "  call {}",
-                            &(program_info.func(call_inst.callee()).name())[1..]
-                        )
-                        .unwrap();
+                        writeln!(output, "  call {}", callee_name)?;
 
                         if value_data.ty().is_unit() {
                             //没有返回值，不需要设置返回值
                             continue;
                         }
 
-                        func_info.set_reg("a0", inst); //把a0的所有者给inst
+                        if callee_abi.ret {
+                            //float返回值在fa0中
+                            let reg_ret = func_info.get_reg(output, inst, program_info)?;
+                            writeln!(output, "  fmv.x.w {}, fa0", reg_ret)?;
+                        } else {
+                            func_info.set_reg("a0", inst); //把a0的所有者给inst
+                        }
 
                         //正式分入栈中，会在func_info生成一条sw指令
-                        func_info.new_var(output, inst, now_stack_offset, program_info);
-                        free_reg(self, &mut func_info, inst); //TODO
+                        func_info.new_var(output, inst, now_stack_offset, program_info)?;
+                        free_reg(self, &mut func_info, inst)?; //TODO
 
                         now_stack_offset += value_data.ty().size() as i32;
                     }
@@ -472,157 +852,168 @@ impl GenerateAsm for koopa::ir::FunctionData {
                         let true_bb_name = self.dfg().bb(br_inst.true_bb()).name().clone().unwrap();
                         let false_bb_name =
                             self.dfg().bb(br_inst.false_bb()).name().clone().unwrap();
-                        let reg_cond = get_reg(output, self, &mut func_info, cond, program_info);
-                        writeln!(
+                        let reg_cond = get_reg(output, self, &mut func_info, cond, program_info)?;
+                        //同一个基本块可能是多个br的false目标，标签按当前函数和基本块命名
+                        let br_label = format!("BRTEMP_{}_{}", &self.name()[1..], &block_name[1..]);
+                        writeln!(output, "  beqz {}, {}", reg_cond, br_label)?;
+                        free_reg(self, &mut func_info, cond)?;
+                        block_args(
+                            output,
+                            self,
+                            &mut func_info,
+                            br_inst.true_bb(),
+                            br_inst.true_args(),
+                            scratch_offset,
+                            program_info,
+                        )?;
+                        writeln!(output, "  j {}", &true_bb_name[1..])?;
+                        writeln!(output, "{}:", br_label)?;
+                        block_args(
                             output,
-                            "  beqz {}, BRTEMP_{}",
-                            reg_cond,
-                            &false_bb_name[1..]
-                        )
-                        .unwrap();
-                        writeln!(output, "  j {}", &true_bb_name[1..]).unwrap();
-                        writeln!(output, "BRTEMP_{}:", &false_bb_name[1..]).unwrap();
-                        writeln!(output, "  j {}", &false_bb_name[1..]).unwrap();
-                        free_reg(self, &mut func_info, cond);
+                            self,
+                            &mut func_info,
+                            br_inst.false_bb(),
+                            br_inst.false_args(),
+                            scratch_offset,
+                            program_info,
+                        )?;
+                        writeln!(output, "  j {}", &false_bb_name[1..])?;
                     }
                     ValueKind::Jump(jump_inst) => {
                         // 处理 jump 指令
                         let target_bb = jump_inst.target();
+                        block_args(
+                            output,
+                            self,
+                            &mut func_info,
+                            target_bb,
+                            jump_inst.args(),
+                            scratch_offset,
+                            program_info,
+                        )?;
                         let target_data = self.dfg().bb(target_bb);
                         let target_name = target_data.name().clone().unwrap();
-                        writeln!(output, "  j {}", &target_name[1..]).unwrap();
+                        writeln!(output, "  j {}", &target_name[1..])?;
                     }
                     ValueKind::Return(ret_inst) => {
                         // 处理 ret 指令
-                        match ret_inst.value() {
-                            Some(ret_val) => {
-                                let ret_data = self.dfg().value(ret_val);
-
-                                match ret_data.kind() {
-                                    ValueKind::Integer(val) => {
-                                        writeln!(output, "  li a0, {}", val.value()).unwrap();
-                                    }
-                                    _ => {
-                                        //其他情况**都**直接从内存读到寄存器？
-                                        let reg_ret = get_reg(
-                                            output,
-                                            self,
-                                            &mut func_info,
-                                            ret_val,
-                                            program_info,
-                                        );
-                                        writeln!(output, "  mv a0, {}", reg_ret).unwrap();
-
-                                        free_reg(self, &mut func_info, ret_val);
-                                    }
+                        if let Some(ret_val) = ret_inst.value() {
+                            let ret_data = self.dfg().value(ret_val);
+
+                            match ret_data.kind() {
+                                ValueKind::Integer(val) => {
+                                    writeln!(output, "  li a0, {}", val.value())?;
+                                }
+                                _ => {
+                                    //其他情况**都**直接从内存读到寄存器？
+                                    let reg_ret = get_reg(
+                                        output,
+                                        self,
+                                        &mut func_info,
+                                        ret_val,
+                                        program_info,
+                                    )?;
+                                    writeln!(output, "  mv a0, {}", reg_ret)?;
+
+                                    free_reg(self, &mut func_info, ret_val)?;
                                 }
                             }
-                            None => {}
+                            if self_abi.ret {
+                                //float函数通过fa0返回
+                                writeln!(output, "  fmv.w.x fa0, a0")?;
+                            }
                         }
                         if ra_size > 0 {
                             //恢复ra
-                            let addr_reg = func_info.get_reg_i32(output, ra_offset);
-                            writeln!(output, "  add {}, sp, {}", addr_reg, addr_reg).unwrap();
-                            writeln!(output, "  lw ra, 0({})", addr_reg).unwrap();
-                            func_info.free_reg(UserKind::Tmpi32(ra_offset));
+                            let addr_reg = func_info.get_reg_i32(output, ra_offset)?;
+                            writeln!(output, "  add {}, sp, {}", addr_reg, addr_reg)?;
+                            writeln!(output, "  lw ra, 0({})", addr_reg)?;
+                            func_info.free_reg(UserKind::Tmpi32(ra_offset))?;
                         }
-                        func_info.reset_sp(output);
-                        writeln!(output, "  ret").unwrap();
+                        func_info.reset_sp(output)?;
+                        writeln!(output, "  ret")?;
                     }
                     ValueKind::Binary(bin_inst) => {
                         // 处理二元运算
                         let lhs = bin_inst.lhs();
                         let rhs = bin_inst.rhs();
 
-                        let reg_l = get_reg(output, self, &mut func_info, lhs, program_info);
+                        let reg_l = get_reg(output, self, &mut func_info, lhs, program_info)?;
 
-                        let reg_r = get_reg(output, self, &mut func_info, rhs, program_info);
+                        let reg_r = get_reg(output, self, &mut func_info, rhs, program_info)?;
 
-                        let reg_ret = get_reg(output, self, &mut func_info, inst, program_info); //指令ID对应结果
+                        let reg_ret = get_reg(output, self, &mut func_info, inst, program_info)?; //指令ID对应结果
 
                         match bin_inst.op() {
                             BinaryOp::Add => {
-                                writeln!(output, "  add {}, {}, {}", reg_ret, reg_l, reg_r)
-                                    .unwrap();
+                                writeln!(output, "  add {}, {}, {}", reg_ret, reg_l, reg_r)?;
                             }
                             BinaryOp::Sub => {
-                                writeln!(output, "  sub {}, {}, {}", reg_ret, reg_l, reg_r)
-                                    .unwrap();
+                                writeln!(output, "  sub {}, {}, {}", reg_ret, reg_l, reg_r)?;
                             }
                             BinaryOp::Mul => {
-                                writeln!(output, "  mul {}, {}, {}", reg_ret, reg_l, reg_r)
-                                    .unwrap();
+                                writeln!(output, "  mul {}, {}, {}", reg_ret, reg_l, reg_r)?;
                             }
                             BinaryOp::Div => {
-                                writeln!(output, "  div {}, {}, {}", reg_ret, reg_l, reg_r)
-                                    .unwrap();
+                                writeln!(output, "  div {}, {}, {}", reg_ret, reg_l, reg_r)?;
                             }
                             BinaryOp::Mod => {
-                                writeln!(output, "  rem {}, {}, {}", reg_ret, reg_l, reg_r)
-                                    .unwrap();
+                                writeln!(output, "  rem {}, {}, {}", reg_ret, reg_l, reg_r)?;
                             }
                             BinaryOp::And => {
-                                writeln!(output, "  and {}, {}, {}", reg_ret, reg_l, reg_r)
-                                    .unwrap();
+                                writeln!(output, "  and {}, {}, {}", reg_ret, reg_l, reg_r)?;
                             }
                             BinaryOp::Or => {
-                                writeln!(output, "  or {}, {}, {}", reg_ret, reg_l, reg_r).unwrap();
+                                writeln!(output, "  or {}, {}, {}", reg_ret, reg_l, reg_r)?;
                             }
                             BinaryOp::Xor => {
-                                writeln!(output, "  xor {}, {}, {}", reg_ret, reg_l, reg_r)
-                                    .unwrap();
+                                writeln!(output, "  xor {}, {}, {}", reg_ret, reg_l, reg_r)?;
                             }
                             BinaryOp::Shl => {
-                                writeln!(output, "  sll {}, {}, {}", reg_ret, reg_l, reg_r)
-                                    .unwrap();
+                                writeln!(output, "  sll {}, {}, {}", reg_ret, reg_l, reg_r)?;
                             }
                             BinaryOp::Shr => {
-                                writeln!(output, "  srl {}, {}, {}", reg_ret, reg_l, reg_r)
-                                    .unwrap();
+                                writeln!(output, "  srl {}, {}, {}", reg_ret, reg_l, reg_r)?;
                             }
                             BinaryOp::Sar => {
-                                writeln!(output, "  sra {}, {}, {}", reg_ret, reg_l, reg_r)
-                                    .unwrap();
+                                writeln!(output, "  sra {}, {}, {}", reg_ret, reg_l, reg_r)?;
                             }
                             BinaryOp::Eq => {
-                                writeln!(output, "  xor {}, {}, {}", reg_ret, reg_l, reg_r)
-                                    .unwrap();
-                                writeln!(output, "  seqz {}, {}", reg_ret, reg_ret).unwrap();
+                                writeln!(output, "  xor {}, {}, {}", reg_ret, reg_l, reg_r)?;
+                                writeln!(output, "  seqz {}, {}", reg_ret, reg_ret)?;
                             }
                             BinaryOp::NotEq => {
-                                writeln!(output, "  xor {}, {}, {}", reg_ret, reg_l, reg_r)
-                                    .unwrap();
-                                writeln!(output, "  snez {}, {}", reg_ret, reg_ret).unwrap();
+                                writeln!(output, "  xor {}, {}, {}", reg_ret, reg_l, reg_r)?;
+                                writeln!(output, "  snez {}, {}", reg_ret, reg_ret)?;
                             }
                             BinaryOp::Lt => {
-                                writeln!(output, "  slt {}, {}, {}", reg_ret, reg_l, reg_r)
-                                    .unwrap();
+                                writeln!(output, "  slt {}, {}, {}", reg_ret, reg_l, reg_r)?;
                             }
                             BinaryOp::Gt => {
-                                writeln!(output, "  sgt {}, {}, {}", reg_ret, reg_l, reg_r)
-                                    .unwrap();
+                                writeln!(output, "  sgt {}, {}, {}", reg_ret, reg_l, reg_r)?;
                             }
                             BinaryOp::Le => {
-                                writeln!(output, "  sgt {}, {}, {}", reg_ret, reg_l, reg_r)
-                                    .unwrap();
-                                writeln!(output, "  seqz {}, {}", reg_ret, reg_ret).unwrap();
+                                writeln!(output, "  sgt {}, {}, {}", reg_ret, reg_l, reg_r)?;
+                                writeln!(output, "  seqz {}, {}", reg_ret, reg_ret)?;
                             }
                             BinaryOp::Ge => {
-                                writeln!(output, "  slt {}, {}, {}", reg_ret, reg_l, reg_r)
-                                    .unwrap();
-                                writeln!(output, "  seqz {}, {}", reg_ret, reg_ret).unwrap();
+                                writeln!(output, "  slt {}, {}, {}", reg_ret, reg_l, reg_r)?;
+                                writeln!(output, "  seqz {}, {}", reg_ret, reg_ret)?;
                             }
-                            _ => {}
                         }
 
                         //正式分入栈中，会在func_info生成一条sw指令
-                        func_info.new_var(output, inst, now_stack_offset, program_info);
+                        func_info.new_var(output, inst, now_stack_offset, program_info)?;
                         now_stack_offset += value_data.ty().size() as i32;
 
-                        //释放所有用到的寄存器(如果有)
-                        free_reg(self, &mut func_info, lhs);
-                        free_reg(self, &mut func_info, rhs);
-                        free_reg(self, &mut func_info, inst);
+                        //释放所有用到的寄存器(如果有)，同一个非常量的值作两个操作数时只占用一个寄存器
+                        free_reg(self, &mut func_info, lhs)?;
+                        if rhs != lhs
+                            || matches!(self.dfg().value(rhs).kind(), ValueKind::Integer(_))
+                        {
+                            free_reg(self, &mut func_info, rhs)?;
+                        }
+                        free_reg(self, &mut func_info, inst)?;
                     }
                     // 其他种类暂时遇不到
                     _ => {}
@@ -630,5 +1021,6 @@ impl GenerateAsm for koopa::ir::FunctionData {
             }
         }
         //恢复栈指针
+        Ok(())
     }
 }
@@ -1,21 +1,25 @@
-use core::panic;
-use std::fmt::write;
-use std::mem::Discriminant;
-use std::result;
-///!实现生成Koopa IR
-use std::{fs::File, io::Write};
-
-use koopa::front::ast::Return;
+//!实现生成Koopa IR
+//!直接用koopa的builder接口在内存中构建koopa::ir::Program
+use koopa::ir::builder_traits::*;
+use koopa::ir::{BinaryOp, Program, Type, Value};
 
 use crate::ast::*;
+use crate::calc_exp::eval_case;
+use crate::calc_exp::eval_const;
+use crate::calc_exp::literal_value;
 use crate::calc_exp::Eval;
+use crate::ds_for_ir::FloatIntrinsic;
 use crate::ds_for_ir::GenerateIrInfo;
+use crate::ds_for_ir::{array_type, param_type, sort_funcs};
+use crate::error::{CompileError, Result};
 
+use crate::array_solve::eval_param_kind;
 use crate::array_solve::GenDefDim;
 use crate::array_solve::GlobalArrayInit;
-use crate::array_solve::LocalArrayInit;
 use crate::symbol_table::ArrayInfoBase;
+use crate::symbol_table::ConstValue;
 use crate::symbol_table::FuncInfoBase;
+use crate::symbol_table::ParamKind;
 use crate::symbol_table::SymbolInfo::Array;
 use crate::symbol_table::SymbolInfo::ArrayPointer;
 use crate::symbol_table::SymbolInfo::Const;
@@ -26,7 +30,11 @@ use crate::symbol_table::VarInfoBase;
 pub trait GenerateIR {
     ///用于记录不同种类单元的返回情况
     type GenerateResult;
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> Self::GenerateResult;
+    fn generate(
+        &self,
+        program: &mut Program,
+        info: &mut GenerateIrInfo,
+    ) -> Result<Self::GenerateResult>;
 }
 
 ///用于记录子树中是否已经return/jump/br
@@ -35,66 +43,243 @@ pub enum Returned {
     No,
 }
 
+///表达式的结果，浮点数在IR中以i32的位模式出现
 #[derive(Debug, Clone)]
 pub enum ExpResult {
     Value(i32),
-    ID(i32),
+    ID(Value),
+    Float(f32),
+    FloatID(Value),
 }
 impl ExpResult {
-    pub fn unwrap(self) -> String {
+    ///作为指令操作数的值，编译期常量在当前函数中生成整数常量
+    pub fn into_value(self, program: &mut Program, info: &GenerateIrInfo) -> Value {
         match self {
-            ExpResult::Value(x) => x.to_string(),
-            ExpResult::ID(x) => "%".to_owned() + &x.to_string(),
+            ExpResult::Value(x) => info.integer(program, x),
+            ExpResult::Float(x) => info.integer(program, x.to_bits() as i32),
+            ExpResult::ID(x) | ExpResult::FloatID(x) => x,
+        }
+    }
+    pub fn is_float(&self) -> bool {
+        matches!(self, ExpResult::Float(_) | ExpResult::FloatID(_))
+    }
+}
+impl From<ConstValue> for ExpResult {
+    fn from(val: ConstValue) -> Self {
+        match val {
+            ConstValue::Int(x) => ExpResult::Value(x),
+            ConstValue::Float(x) => ExpResult::Float(x),
+        }
+    }
+}
+
+///调用浮点运算的内建函数，返回调用的结果
+fn call_float_intrinsic(
+    program: &mut Program,
+    info: &mut GenerateIrInfo,
+    op: FloatIntrinsic,
+    args: &[&ExpResult],
+) -> Result<Value> {
+    let args: Vec<Value> = args
+        .iter()
+        .map(|arg| (*arg).clone().into_value(program, info))
+        .collect();
+    let func = info.float_intrinsic(program, op);
+    Ok(info.inst(program, |builder| builder.call(func, args)))
+}
+
+///把表达式的值隐式转换成btype类型
+pub fn convert(
+    exp: ExpResult,
+    btype: BType,
+    program: &mut Program,
+    info: &mut GenerateIrInfo,
+) -> Result<ExpResult> {
+    Ok(match (exp, btype) {
+        (ExpResult::Value(x), BType::Float) => ExpResult::Float(x as f32),
+        (ExpResult::Float(x), BType::Int) => ExpResult::Value(x as i32),
+        (exp @ ExpResult::ID(_), BType::Float) => {
+            let id = call_float_intrinsic(program, info, FloatIntrinsic::FromInt, &[&exp])?;
+            ExpResult::FloatID(id)
+        }
+        (exp @ ExpResult::FloatID(_), BType::Int) => {
+            let id = call_float_intrinsic(program, info, FloatIntrinsic::ToInt, &[&exp])?;
+            ExpResult::ID(id)
+        }
+        (exp, _) => exp,
+    })
+}
+
+///作为条件使用时的真假，浮点数和0比较得到int
+fn truth_value(
+    exp: ExpResult,
+    program: &mut Program,
+    info: &mut GenerateIrInfo,
+) -> Result<ExpResult> {
+    Ok(match exp {
+        ExpResult::Float(x) => ExpResult::Value((x != 0.0) as i32),
+        ExpResult::FloatID(_) => {
+            let zero = ExpResult::Float(0.0);
+            let id = call_float_intrinsic(program, info, FloatIntrinsic::Ne, &[&exp, &zero])?;
+            ExpResult::ID(id)
         }
+        exp => exp,
+    })
+}
+
+///二元运算两边有一个是浮点数时，把两边都转换成浮点数，返回是否为浮点运算
+fn unify_operands(
+    lhs: ExpResult,
+    rhs: ExpResult,
+    program: &mut Program,
+    info: &mut GenerateIrInfo,
+) -> Result<(ExpResult, ExpResult, bool)> {
+    if lhs.is_float() || rhs.is_float() {
+        let lhs = convert(lhs, BType::Float, program, info)?;
+        let rhs = convert(rhs, BType::Float, program, info)?;
+        Ok((lhs, rhs, true))
+    } else {
+        Ok((lhs, rhs, false))
+    }
+}
+
+///生成二元整数运算
+fn binary(
+    op: BinaryOp,
+    lhs: ExpResult,
+    rhs: ExpResult,
+    program: &mut Program,
+    info: &mut GenerateIrInfo,
+) -> Value {
+    let lhs = lhs.into_value(program, info);
+    let rhs = rhs.into_value(program, info);
+    info.inst(program, |builder| builder.binary(op, lhs, rhs))
+}
+
+///生成二元算术运算，有一边是浮点数时按浮点数计算
+fn arith(
+    op: ArithOp,
+    lhs: ExpResult,
+    rhs: ExpResult,
+    program: &mut Program,
+    info: &mut GenerateIrInfo,
+) -> Result<ExpResult> {
+    let (lhs, rhs, is_float) = unify_operands(lhs, rhs, program, info)?;
+    if is_float {
+        let op = match op {
+            ArithOp::Add => FloatIntrinsic::Add,
+            ArithOp::Sub => FloatIntrinsic::Sub,
+            ArithOp::Mul => FloatIntrinsic::Mul,
+            ArithOp::Div => FloatIntrinsic::Div,
+            ArithOp::Mod => return Err(CompileError::internal("浮点数不能取模".to_string())),
+        };
+        let id = call_float_intrinsic(program, info, op, &[&lhs, &rhs])?;
+        return Ok(ExpResult::FloatID(id));
     }
+    let op = match op {
+        ArithOp::Add => BinaryOp::Add,
+        ArithOp::Sub => BinaryOp::Sub,
+        ArithOp::Mul => BinaryOp::Mul,
+        ArithOp::Div => BinaryOp::Div,
+        ArithOp::Mod => BinaryOp::Mod,
+    };
+    Ok(ExpResult::ID(binary(op, lhs, rhs, program, info)))
+}
+
+///生成位运算，语义检查保证两边都是int
+fn bit_op(
+    op: BinaryOp,
+    lhs: ExpResult,
+    rhs: ExpResult,
+    program: &mut Program,
+    info: &mut GenerateIrInfo,
+) -> Result<ExpResult> {
+    Ok(ExpResult::ID(binary(op, lhs, rhs, program, info)))
+}
+
+///生成复合赋值和自增自减：读出左值的旧值，运算后写回原处
+fn op_assign(
+    lval: &LVal,
+    op: ArithOp,
+    rhs: ExpResult,
+    program: &mut Program,
+    info: &mut GenerateIrInfo,
+) -> Result<()> {
+    let (old, dest, btype) = match lval.generate(program, info)? {
+        LvalResult::PointerArray(_) => {
+            return Err(CompileError::semantic(
+                lval.span,
+                format!("不能给数组`{}`整体赋值", lval.ident),
+            ));
+        }
+        LvalResult::Pointer(array_ptr, btype) => {
+            let old = info.inst(program, |builder| builder.load(array_ptr));
+            (old, array_ptr, btype)
+        }
+        //变量的LVal已经读出了旧值
+        LvalResult::Value(old, btype) => (old, info.get_alloc(&lval.ident)?, btype),
+    };
+    let old = match btype {
+        BType::Int => ExpResult::ID(old),
+        BType::Float => ExpResult::FloatID(old),
+    };
+    let result = arith(op, old, rhs, program, info)?;
+    let result = convert(result, btype, program, info)?.into_value(program, info);
+    info.inst(program, |builder| builder.store(result, dest));
+    Ok(())
 }
 
 ///为CompUnit实现 GenerateIR trait
 impl GenerateIR for CompUnit {
     type GenerateResult = ();
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) {
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<()> {
         symbol_table_debug!(
             "程序开始,符号表和block表分别为{:#?}\n{:#?}",
             info.tables,
             info.block_id
         );
-        //先加入所有SysY库函数的声明
-        let lib_func_def = "decl @getint(): i32\n".to_string()
-            + "decl @getch(): i32\n"
-            + "decl @getarray(*i32): i32\n"
-            + "decl @putint(i32)\n"
-            + "decl @putch(i32)\n"
-            + "decl @putarray(i32, *i32)\n"
-            + "decl @starttime()\n"
-            + "decl @stoptime()\n";
-        writeln!(output, "{}", lib_func_def).unwrap();
-        //然后把它们插入到全局符号表
-        info.insert_global_symbol("getint".to_string(), Func(FuncInfoBase::new(FuncType::Int)));
-        info.insert_global_symbol("getch".to_string(), Func(FuncInfoBase::new(FuncType::Int)));
-        info.insert_global_symbol(
-            "getarray".to_string(),
-            Func(FuncInfoBase::new(FuncType::Int)),
-        );
-        info.insert_global_symbol(
-            "putint".to_string(),
-            Func(FuncInfoBase::new(FuncType::Void)),
-        );
-        info.insert_global_symbol("putch".to_string(), Func(FuncInfoBase::new(FuncType::Void)));
-        info.insert_global_symbol(
-            "putarray".to_string(),
-            Func(FuncInfoBase::new(FuncType::Void)),
-        );
-        info.insert_global_symbol(
-            "starttime".to_string(),
-            Func(FuncInfoBase::new(FuncType::Void)),
-        );
-        info.insert_global_symbol(
-            "stoptime".to_string(),
-            Func(FuncInfoBase::new(FuncType::Void)),
-        );
-
+        //先把运行时库函数和本文件中所有函数的签名插入到全局符号表
+        info.insert_lib_funcs();
+        declare_funcs(self, info);
+        //运行时库函数按头文件中的顺序声明
+        let runtime: Vec<String> = info.runtime.iter().map(|(name, _)| name.clone()).collect();
+        for name in runtime {
+            info.func(program, &name)?;
+        }
         for item in &self.item {
-            item.generate(output, info);
+            item.generate(program, info)?;
+        }
+
+        //被调用的函数先输出，相互递归时通过另一个名字的decl提前调用
+        sort_funcs(program);
+        Ok(())
+    }
+}
+
+///预先登记所有函数的签名，使函数可以在定义之前被调用，比如相互递归的函数
+///形参的维度可能用到全局常量，所以在临时环境中按顺序求出全局常量，出错的留给之后逐项处理时报告
+pub fn declare_funcs(ast: &CompUnit, info: &mut GenerateIrInfo) {
+    let mut scratch = GenerateIrInfo::new();
+    let mut scratch_program = Program::new();
+    for item in &ast.item {
+        match item {
+            CompItem::Decl(decl @ Decl::ConstDecl(_)) => {
+                let _ = decl.generate(&mut scratch_program, &mut scratch);
+            }
+            CompItem::Decl(_) => {}
+            CompItem::FuncDef(func_def) => {
+                let params: Result<Vec<ParamKind>> = func_def
+                    .func_fparams
+                    .iter()
+                    .map(|param| eval_param_kind(param, &mut scratch))
+                    .collect();
+                if let (Ok(params), None) = (params, info.tables[0].get(&func_def.ident)) {
+                    info.tables[0].insert(
+                        func_def.ident.clone(),
+                        Func(FuncInfoBase::new(func_def.func_type, params)),
+                    );
+                }
+            }
         }
     }
 }
@@ -102,148 +287,121 @@ impl GenerateIR for CompUnit {
 ///为CompItem实现GenerateIR trait
 impl GenerateIR for CompItem {
     type GenerateResult = ();
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) {
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<()> {
         match self {
             CompItem::FuncDef(func_def) => {
-                func_def.generate(output, info);
+                func_def.generate(program, info)?;
             }
             CompItem::Decl(decl) => {
-                decl.generate(output, info);
+                decl.generate(program, info)?;
             }
         }
+        Ok(())
     }
 }
 
 ///为FuncDef实现GenerateIR trait
 impl GenerateIR for FuncDef {
     type GenerateResult = ();
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) {
-        //每个函数层都是一个新的作用域层，具体来说是 {func {block} }
-        //这样我们可以保证形参的作用域大于block中的任何变量
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<()> {
+        //每个函数层都是一个新的作用域层，形参和函数体最外层的声明都在这一层
+        //这样我们可以保证形参的作用域大于block中的任何变量，且函数体中不能重新定义形参
         info.push_block();
-        info.insert_global_symbol(self.ident.clone(), Func(FuncInfoBase::new(self.func_type)));
-        write!(output, "fun @{}", info.get_name(&self.ident)).unwrap();
-        write!(output, "(").unwrap();
-        for (i, func_fparam) in self.func_fparams.iter().enumerate() {
-            match &func_fparam.dims {
-                None => {
-                    if i != 0 {
-                        write!(output, ", ").unwrap();
-                    }
-
-                    info.insert_symbol(func_fparam.ident.clone(), Var(VarInfoBase::new()));
-
-                    write!(output, "%{}: ", info.get_name(&func_fparam.ident)).unwrap();
-                    match &func_fparam.btype {
-                        BType::Int => write!(output, "i32").unwrap(),
-                    }
-                }
-                Some(dims) => {
-                    if i != 0 {
-                        write!(output, ", ").unwrap();
-                    }
-
-                    let mut real_dims: Vec<i32> = vec![];
-                    for dim in dims {
-                        real_dims.push(dim.eval(info).expect("数组维度中出现非常量表达式"));
-                    }
-                    //插入符号表，标明是**数组指针**！
-                    info.insert_symbol(
-                        func_fparam.ident.clone(),
-                        ArrayPointer(ArrayInfoBase {
-                            dims: real_dims.clone(), //borrow
-                        }),
-                    );
-
-                    write!(output, "%{}: *", info.get_name(&func_fparam.ident)).unwrap();
-
-                    let left = "[".to_string().repeat(real_dims.len()); //TODO : repeat 0 ?
-                    write!(output, "{}i32", left).unwrap();
-                    for dim in real_dims.iter().rev() {
-                        write!(output, ", {}]", dim).unwrap();
-                    }
-                }
-            }
-        }
-        write!(output, ")").unwrap();
-
-        match self.func_type {
-            FuncType::Int => write!(output, ": i32").unwrap(),
-            FuncType::Void => {}
+        let mut params = vec![];
+        for func_fparam in &self.func_fparams {
+            params.push(eval_param_kind(func_fparam, info)?);
         }
-        write!(output, " ").unwrap();
-        write!(output, "{{\n").unwrap();
-        write!(output, "%entry:\n").unwrap();
+        info.declare_func(
+            self.ident.clone(),
+            FuncInfoBase::new(self.func_type, params.clone()),
+            self.span,
+            self.block.is_some(),
+        )?;
+        //没有定义的函数输出为decl
+        let func = info.func(program, &self.ident)?;
+        let Some(block) = &self.block else {
+            //原型声明只登记签名
+            info.pop_block();
+            return Ok(());
+        };
+        info.now_func = Some(func);
+        info.now_func_type = self.func_type;
+        info.bbs.clear();
+        info.start_bb(program, "%entry");
 
         //先将形参复制为临时变量，便于后续生成目标代码
-        for func_fparam in &self.func_fparams {
-            match &func_fparam.dims {
-                None => {
-                    let param_name = info.get_name(&func_fparam.ident);
-                    writeln!(output, "  @{} = alloc i32", param_name).unwrap();
-                    writeln!(output, "  store %{}, @{}", param_name, param_name).unwrap();
-                    //这里注意到底用%还是@取决于LVal生成的load是啥
-                }
-                Some(dims) => {
-                    let param_name = info.get_name(&func_fparam.ident);
-                    write!(output, "  @{} = alloc *", param_name).unwrap();
-                    let mut real_dims: Vec<i32> = vec![];
-                    for dim in dims {
-                        real_dims.push(dim.eval(info).expect("数组维度中出现非常量表达式"));
-                    }
-                    let left = "[".to_string().repeat(real_dims.len()); //TODO : repeat 0 ?
-                    write!(output, "{}i32", left).unwrap();
-                    for dim in real_dims.iter().rev() {
-                        write!(output, ", {}]", dim).unwrap();
-                    }
-                    writeln!(output, "").unwrap(); //换行
-
-                    writeln!(output, "  store %{}, @{}", param_name, param_name).unwrap();
-                }
-            }
+        let param_values = program.func(func).params().to_vec();
+        for ((func_fparam, kind), param) in self.func_fparams.iter().zip(&params).zip(param_values)
+        {
+            let symbol = match kind {
+                //插入符号表，标明是**数组指针**！
+                ParamKind::Array(btype, dims) => ArrayPointer(ArrayInfoBase {
+                    btype: *btype,
+                    dims: dims.clone(),
+                    const_init: None,
+                }),
+                _ => Var(VarInfoBase::new(func_fparam.btype)),
+            };
+            info.insert_symbol(func_fparam.ident.clone(), symbol, func_fparam.span)?;
+
+            let param_name = info.get_name(&func_fparam.ident)?;
+            info.func_data(program)
+                .dfg_mut()
+                .set_value_name(param, Some(format!("%{}", param_name)));
+            //浮点数也以i32的位模式传递
+            let alloc = info.alloc(program, param_name, param_type(kind));
+            info.inst(program, |builder| builder.store(param, alloc));
         }
 
-        match self.block.generate(output, info) {
-            Returned::Yes => {}
-            Returned::No => match self.func_type {
-                FuncType::Int => writeln!(output, "  ret 0").unwrap(),
-                FuncType::Void => writeln!(output, "  ret").unwrap(),
-            },
+        if let Returned::No = generate_items(&block.items, program, info)? {
+            let ret = match self.func_type {
+                FuncType::Int | FuncType::Float => Some(info.integer(program, 0)),
+                FuncType::Void => None,
+            };
+            info.inst(program, |builder| builder.ret(ret));
         }
-        write!(output, "}}\n").unwrap();
+        info.now_func = None;
+        info.now_bb = None;
         //记得删除函数层block
         info.pop_block();
+        Ok(())
     }
 }
 
 ///为Block实现GenerateIR trait
 impl GenerateIR for Block {
     type GenerateResult = Returned;
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> Returned {
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<Returned> {
         //目前现在block的GenerateIR trait调用新建block
         //注意只有FuncDef和Stmt会推导出Block
         info.push_block();
-        for item in &self.items {
-            match item.generate(output, info) {
-                Returned::Yes => {
-                    info.pop_block();
-                    return Returned::Yes;
-                }
-                Returned::No => {}
-            }
-        }
+        let returned = generate_items(&self.items, program, info)?;
         info.pop_block();
-        Returned::No
+        Ok(returned)
+    }
+}
+
+///依次生成块中的语句，遇到返回的语句就停止
+fn generate_items(
+    items: &[BlockItem],
+    program: &mut Program,
+    info: &mut GenerateIrInfo,
+) -> Result<Returned> {
+    for item in items {
+        if let Returned::Yes = item.generate(program, info)? {
+            return Ok(Returned::Yes);
+        }
     }
+    Ok(Returned::No)
 }
 
 ///为BlockItem实现GenerateIR trait
 impl GenerateIR for BlockItem {
     type GenerateResult = Returned;
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> Returned {
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<Returned> {
         match self {
-            BlockItem::Decl(decl) => decl.generate(output, info),
-            BlockItem::Stmt(stmt) => stmt.generate(output, info),
+            BlockItem::Decl(decl) => decl.generate(program, info),
+            BlockItem::Stmt(stmt) => stmt.generate(program, info),
         }
     }
 }
@@ -251,190 +409,345 @@ impl GenerateIR for BlockItem {
 ///为Stmt实现GenerateIR trait
 impl GenerateIR for Stmt {
     type GenerateResult = Returned;
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> Returned {
-        match self {
-            Stmt::Assign(lval, exp) => {
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<Returned> {
+        Ok(match &self.kind {
+            StmtKind::Assign(lval, exp) => {
                 //赋值语句
-                let exp_id = exp.generate(output, info); //计算右端exp的值
+                let exp_id = exp.generate(program, info)?; //计算右端exp的值
 
-                let lval_id = lval.generate(output, info);
+                let lval_id = lval.generate(program, info)?;
 
-                match lval_id {
+                let (dest, btype) = match lval_id {
                     LvalResult::PointerArray(_) => {
                         //如果是数组指针
-                        panic!("尝试向数组指针赋值");
-                    }
-                    LvalResult::Pointer(array_ptr_id) => {
-                        //如果是指针
-                        writeln!(output, "  store {}, %{}", exp_id.unwrap(), array_ptr_id).unwrap();
+                        return Err(CompileError::semantic(
+                            lval.span,
+                            format!("不能给数组`{}`整体赋值", lval.ident),
+                        ));
                     }
-                    LvalResult::Value(lval_id) => {
-                        //如果是变量
-                        writeln!(
-                            output,
-                            "  store {}, @{}",
-                            exp_id.unwrap(),
-                            info.get_name(&lval.ident)
-                        )
-                        .unwrap();
-                    }
-                }
+                    //如果是指针
+                    LvalResult::Pointer(array_ptr, btype) => (array_ptr, btype),
+                    //如果是变量
+                    LvalResult::Value(_, btype) => (info.get_alloc(&lval.ident)?, btype),
+                };
+                let value = convert(exp_id, btype, program, info)?.into_value(program, info);
+                info.inst(program, |builder| builder.store(value, dest));
                 Returned::No
             }
-            Stmt::Exp(exp) => {
-                match exp {
-                    Some(exp) => {
-                        exp.generate(output, info);
-                    }
-                    None => {}
+            StmtKind::Exp(exp) => {
+                if let Some(exp) = exp {
+                    exp.generate(program, info)?;
                 }
                 Returned::No
             }
-            Stmt::Block(block) => block.generate(output, info),
-            Stmt::RetExp(exp) => {
-                match exp {
+            StmtKind::Block(block) => block.generate(program, info)?,
+            StmtKind::RetExp(exp) => {
+                let value = match exp {
                     Some(exp) => {
-                        let exp_result = exp.generate(output, info);
-                        writeln!(output, "  ret {}", exp_result.unwrap()).unwrap();
-                    }
-                    None => {
-                        writeln!(output, "  ret").unwrap();
+                        let exp_result = exp.generate(program, info)?;
+                        let exp_result = match info.now_func_type.ret_btype() {
+                            Some(btype) => convert(exp_result, btype, program, info)?,
+                            None => exp_result,
+                        };
+                        Some(exp_result.into_value(program, info))
                     }
-                }
+                    None => None,
+                };
+                info.inst(program, |builder| builder.ret(value));
                 Returned::Yes
             }
-            Stmt::If(exp, then_stmt, else_stmt) => {
-                let exp_id = exp.generate(output, info);
+            StmtKind::If(exp, then_stmt, else_stmt) => {
+                let exp_id = exp.generate(program, info)?;
+                let cond = truth_value(exp_id, program, info)?.into_value(program, info);
                 //当前if else的编号
                 info.if_id += 1;
                 let now_if_id = info.if_id;
-                //if 的条件判断部分
-                if else_stmt.is_some() {
-                    //else存在
-                    writeln!(
-                        output,
-                        "  br {}, %if_true_{}, %if_false_{}",
-                        exp_id.unwrap(),
-                        now_if_id,
-                        now_if_id
-                    )
-                    .unwrap();
-                } else {
-                    //else不存在
-                    writeln!(
-                        output,
-                        "  br {}, %if_true_{}, %if_end_{}",
-                        exp_id.unwrap(),
-                        now_if_id,
-                        now_if_id
-                    )
-                    .unwrap();
+                let true_label = format!("%if_true_{}", now_if_id);
+                let false_label = format!("%if_false_{}", now_if_id);
+                let end_label = format!("%if_end_{}", now_if_id);
+                //if 的条件判断部分，else不存在时直接跳到结束
+                match else_stmt {
+                    Some(_) => info.branch(program, cond, &true_label, &false_label),
+                    None => info.branch(program, cond, &true_label, &end_label),
                 }
 
                 //if 的then部分
-                //生成then基础块标号
-                writeln!(output, "%if_true_{}:", now_if_id).unwrap();
-                //用于记录then和else中是否有return | 好像没用 TODO
-                let mut then_else_result = Returned::No;
-                match then_stmt.generate(output, info) {
+                info.start_bb(program, &true_label);
+                match then_stmt.generate(program, info)? {
                     Returned::Yes => {
                         //then部分有return，不生成跳转
-                        then_else_result = Returned::Yes;
                     }
                     Returned::No => {
                         //then部分没有return，生成跳转
-                        writeln!(output, "  jump %if_end_{}", now_if_id).unwrap();
+                        info.jump(program, &end_label);
                     }
                 }
-                if else_stmt.is_some() {
-                    //检查else是否存在
+                if let Some(else_stmt) = else_stmt {
                     //if 的else部分
-                    let else_stmt = (else_stmt).as_ref().unwrap();
-                    //生成else基础块标号
-                    writeln!(output, "%if_false_{}:", now_if_id).unwrap();
-                    match else_stmt.generate(output, info) {
+                    info.start_bb(program, &false_label);
+                    match else_stmt.generate(program, info)? {
                         Returned::Yes => {
                             //else部分有return，不生成跳转
-                            then_else_result = Returned::Yes;
                         }
                         Returned::No => {
                             //else部分没有return，生成跳转
-                            writeln!(output, "  jump %if_end_{}", now_if_id).unwrap();
+                            info.jump(program, &end_label);
                         }
                     }
                 }
-                //生成if结束基础块标号
-                writeln!(output, "%if_end_{}:", now_if_id).unwrap();
-                //then_else_result
+                //if结束基础块
+                info.start_bb(program, &end_label);
                 Returned::No
             }
-            Stmt::While(exp, stmt) => {
+            StmtKind::While(exp, stmt) => {
                 //生成while基础块
                 info.push_while();
                 let now_while_id = info.while_id;
-                writeln!(output, "  jump %while_begin_{}", now_while_id).unwrap();
-                writeln!(output, "%while_begin_{}:", now_while_id).unwrap();
-                let exp_id = exp.generate(output, info);
-                writeln!(
-                    output,
-                    "  br {}, %while_body_{}, %while_end_{}",
-                    exp_id.unwrap(),
-                    now_while_id,
-                    now_while_id
-                )
-                .unwrap();
-                //生成while循环体基础块标号
-                writeln!(output, "%while_body_{}:", now_while_id).unwrap();
-                match stmt.generate(output, info) {
+                let begin_label = format!("%while_begin_{}", now_while_id);
+                let body_label = format!("%while_body_{}", now_while_id);
+                let end_label = format!("%while_end_{}", now_while_id);
+                info.jump(program, &begin_label);
+                info.start_bb(program, &begin_label);
+                let exp_id = exp.generate(program, info)?;
+                let cond = truth_value(exp_id, program, info)?.into_value(program, info);
+                info.branch(program, cond, &body_label, &end_label);
+                //while循环体基础块
+                info.start_bb(program, &body_label);
+                match stmt.generate(program, info)? {
                     Returned::Yes => {
                         //循环体有return，不生成跳转
                     }
                     Returned::No => {
                         //循环体没有return，生成跳转
-                        writeln!(output, "  jump %while_begin_{}", now_while_id).unwrap();
+                        info.jump(program, &begin_label);
                     }
                 }
-                writeln!(output, "%while_end_{}:", now_while_id).unwrap();
+                info.start_bb(program, &end_label);
                 //删除while基础块
                 info.pop_while();
                 Returned::No
             }
-            Stmt::Break => {
-                writeln!(
-                    output,
-                    "  jump %while_end_{}",
-                    info.while_history
-                        .last()
-                        .expect("没有在while中使用break!!!")
-                )
-                .unwrap();
+            StmtKind::OpAssign(lval, op, exp) => {
+                let exp_id = exp.generate(program, info)?;
+                op_assign(lval, *op, exp_id, program, info)?;
+                Returned::No
+            }
+            StmtKind::Incr(lval, op) => {
+                op_assign(lval, *op, ExpResult::Value(1), program, info)?;
+                Returned::No
+            }
+            StmtKind::For(init, exp, step, stmt) => {
+                //初始化中声明的变量只在循环内可见
+                info.push_block();
+                for item in init {
+                    item.generate(program, info)?;
+                }
+                info.push_while();
+                let now_while_id = info.while_id;
+                let cond_label = format!("%while_cond_{}", now_while_id);
+                let body_label = format!("%while_body_{}", now_while_id);
+                let begin_label = format!("%while_begin_{}", now_while_id);
+                let end_label = format!("%while_end_{}", now_while_id);
+                info.jump(program, &cond_label);
+                info.start_bb(program, &cond_label);
+                match exp {
+                    Some(exp) => {
+                        let exp_id = exp.generate(program, info)?;
+                        let cond = truth_value(exp_id, program, info)?.into_value(program, info);
+                        info.branch(program, cond, &body_label, &end_label);
+                    }
+                    //省略条件时恒为真
+                    None => info.jump(program, &body_label),
+                }
+                info.start_bb(program, &body_label);
+                if let Returned::No = stmt.generate(program, info)? {
+                    info.jump(program, &begin_label);
+                }
+                //步进部分是continue的目标
+                info.start_bb(program, &begin_label);
+                for step in step {
+                    step.generate(program, info)?;
+                }
+                info.jump(program, &cond_label);
+                info.start_bb(program, &end_label);
+                info.pop_while();
+                info.pop_block();
+                Returned::No
+            }
+            StmtKind::DoWhile(stmt, exp) => {
+                info.push_while();
+                let now_while_id = info.while_id;
+                let body_label = format!("%while_body_{}", now_while_id);
+                let begin_label = format!("%while_begin_{}", now_while_id);
+                let end_label = format!("%while_end_{}", now_while_id);
+                info.jump(program, &body_label);
+                info.start_bb(program, &body_label);
+                if let Returned::No = stmt.generate(program, info)? {
+                    info.jump(program, &begin_label);
+                }
+                //条件判断部分是continue的目标
+                info.start_bb(program, &begin_label);
+                let exp_id = exp.generate(program, info)?;
+                let cond = truth_value(exp_id, program, info)?.into_value(program, info);
+                info.branch(program, cond, &body_label, &end_label);
+                info.start_bb(program, &end_label);
+                info.pop_while();
+                Returned::No
+            }
+            StmtKind::Switch(exp, arms) => {
+                let exp_id = exp.generate(program, info)?;
+                info.push_switch();
+                let now_switch_id = info.switch_id;
+                let arm_label = |i: usize| match arms[i].label {
+                    CaseLabel::Case(_) => format!("%switch_case_{}_{}", now_switch_id, i),
+                    CaseLabel::Default(_) => format!("%switch_default_{}", now_switch_id),
+                };
+                let end_label = format!("%switch_end_{}", now_switch_id);
+                //没有匹配的case时跳到default，没有default时直接结束
+                let default_label = arms
+                    .iter()
+                    .position(|arm| matches!(arm.label, CaseLabel::Default(_)))
+                    .map_or(end_label.clone(), arm_label);
+                let mut cases: Vec<(i32, String)> = vec![];
+                for (i, arm) in arms.iter().enumerate() {
+                    if let CaseLabel::Case(label) = &arm.label {
+                        cases.push((eval_case(label, info)?, arm_label(i)));
+                    }
+                }
+                //依次和每个case的值比较，后端会把稠密的比较链换成跳转表
+                let test_label = |k: usize| {
+                    if k < cases.len() {
+                        format!("%switch_test_{}_{}", now_switch_id, k)
+                    } else {
+                        default_label.clone()
+                    }
+                };
+                let exp_id = exp_id.into_value(program, info);
+                info.jump(program, &test_label(0));
+                for (k, (val, label)) in cases.iter().enumerate() {
+                    info.start_bb(program, &test_label(k));
+                    let val = info.integer(program, *val);
+                    let eq =
+                        info.inst(program, |builder| builder.binary(BinaryOp::Eq, exp_id, val));
+                    info.branch(program, eq, label, &test_label(k + 1));
+                }
+                for (i, arm) in arms.iter().enumerate() {
+                    info.start_bb(program, &arm_label(i));
+                    //每个分支单独一个作用域
+                    info.push_block();
+                    let mut returned = Returned::No;
+                    for item in &arm.items {
+                        if let Returned::Yes = item.generate(program, info)? {
+                            returned = Returned::Yes;
+                            break;
+                        }
+                    }
+                    info.pop_block();
+                    if let Returned::No = returned {
+                        //没有break时顺序执行到下一个分支
+                        let next_label = if i + 1 < arms.len() {
+                            arm_label(i + 1)
+                        } else {
+                            end_label.clone()
+                        };
+                        info.jump(program, &next_label);
+                    }
+                }
+                info.start_bb(program, &end_label);
+                info.pop_switch();
+                Returned::No
+            }
+            StmtKind::Break => {
+                let label = match info.break_label() {
+                    Some(label) => label,
+                    None => {
+                        return Err(CompileError::semantic(
+                            self.span,
+                            "break语句不在循环或switch中".to_string(),
+                        ))
+                    }
+                };
+                info.jump(program, &label);
                 Returned::Yes
             }
-            Stmt::Continue => {
-                writeln!(
-                    output,
-                    "  jump %while_begin_{}",
-                    info.while_history
-                        .last()
-                        .expect("没有在while中使用continue!!!")
-                )
-                .unwrap();
+            StmtKind::Continue => {
+                let label = match info.continue_label() {
+                    Some(label) => label,
+                    None => {
+                        return Err(CompileError::semantic(
+                            self.span,
+                            "continue语句不在循环中".to_string(),
+                        ))
+                    }
+                };
+                info.jump(program, &label);
                 Returned::Yes
             }
-        }
+        })
     }
 }
 
 ///为Exp实现GenerateIR trait
 impl GenerateIR for Exp {
     type GenerateResult = ExpResult;
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> ExpResult {
-        let eval_result = self.eval(info);
-        if eval_result.is_some() {
-            return ExpResult::Value(eval_result.unwrap());
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<ExpResult> {
+        if let Some(val) = self.eval(info)? {
+            return Ok(ExpResult::from(val));
         }
         match self {
-            Exp::LOrExp(lor_exp) => lor_exp.generate(output, info),
+            Exp::LOrExp(lor_exp, _) => lor_exp.generate(program, info),
+            /*
+            和LAndExp/LOrExp一样用一个局部变量保存结果，只计算选中的分支
+            两个分支都生成完才知道结果是否为float，所以回到两个分支的末尾补上类型转换
+              @cond_result_114 = alloc i32
+              br %cond, %cond_true_114, %cond_false_114
+            %cond_true_114:
+              store %then, @cond_result_114
+              jump %cond_end_114
+            %cond_false_114:
+              store %else, @cond_result_114
+              jump %cond_end_114
+            %cond_end_114:
+              %ans = load @cond_result_114
+            */
+            Exp::Cond(cond, then_exp, else_exp, _) => {
+                info.and_or_id += 1;
+                let now_cond_id = info.and_or_id;
+                let result = info.alloc(
+                    program,
+                    format!("cond_result_{}", now_cond_id),
+                    Type::get_i32(),
+                );
+                let cond_id = cond.generate(program, info)?;
+                let cond_id = truth_value(cond_id, program, info)?.into_value(program, info);
+                let true_label = format!("%cond_true_{}", now_cond_id);
+                let false_label = format!("%cond_false_{}", now_cond_id);
+                let end_label = format!("%cond_end_{}", now_cond_id);
+                info.branch(program, cond_id, &true_label, &false_label);
+                info.start_bb(program, &true_label);
+                let then_id = then_exp.generate(program, info)?;
+                let then_bb = info.now_bb;
+                info.start_bb(program, &false_label);
+                let else_id = else_exp.generate(program, info)?;
+                let else_bb = info.now_bb;
+                let btype = if then_id.is_float() || else_id.is_float() {
+                    BType::Float
+                } else {
+                    BType::Int
+                };
+                for (bb, id) in [(then_bb, then_id), (else_bb, else_id)] {
+                    info.now_bb = bb;
+                    let id = convert(id, btype, program, info)?.into_value(program, info);
+                    info.inst(program, |builder| builder.store(id, result));
+                    info.jump(program, &end_label);
+                }
+                info.start_bb(program, &end_label);
+                let ans = info.inst(program, |builder| builder.load(result));
+                Ok(match btype {
+                    BType::Int => ExpResult::ID(ans),
+                    BType::Float => ExpResult::FloatID(ans),
+                })
+            }
         }
     }
 }
@@ -442,64 +755,103 @@ impl GenerateIR for Exp {
 ///为UnaryExp实现GenerateIR trait
 impl GenerateIR for UnaryExp {
     type GenerateResult = ExpResult;
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> ExpResult {
-        let eval_result = self.eval(info);
-        if eval_result.is_some() {
-            return ExpResult::Value(eval_result.unwrap());
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<ExpResult> {
+        if let Some(val) = self.eval(info)? {
+            return Ok(ExpResult::from(val));
         }
         match self {
-            UnaryExp::PrimaryExp(primary_exp) => primary_exp.generate(output, info),
+            UnaryExp::PrimaryExp(primary_exp) => primary_exp.generate(program, info),
             UnaryExp::BinaryOp(op, exp) => {
-                let exp_id = exp.generate(output, info);
-                match op {
-                    UnaryOp::Neg => {
-                        info.now_id += 1;
-                        writeln!(output, "  %{} = sub 0, {}", info.now_id, exp_id.unwrap())
-                            .unwrap();
+                let exp_id = exp.generate(program, info)?;
+                match (op, exp_id.is_float()) {
+                    (UnaryOp::Neg, false) => {
+                        let id = binary(BinaryOp::Sub, ExpResult::Value(0), exp_id, program, info);
+                        Ok(ExpResult::ID(id))
+                    }
+                    (UnaryOp::Neg, true) => {
+                        //浮点数取负只需翻转符号位
+                        let sign = ExpResult::Value(i32::MIN);
+                        let id = binary(BinaryOp::Xor, exp_id, sign, program, info);
+                        Ok(ExpResult::FloatID(id))
+                    }
+                    (UnaryOp::Pos, _) => Ok(exp_id),
+                    (UnaryOp::BitNot, _) => {
+                        bit_op(BinaryOp::Xor, exp_id, ExpResult::Value(-1), program, info)
                     }
-                    UnaryOp::Pos => {}
-                    UnaryOp::Not => {
-                        info.now_id += 1;
-                        writeln!(output, "  %{} = eq 0, {}", info.now_id, exp_id.unwrap()).unwrap();
+                    (UnaryOp::Not, false) => {
+                        let id = binary(BinaryOp::Eq, ExpResult::Value(0), exp_id, program, info);
+                        Ok(ExpResult::ID(id))
+                    }
+                    (UnaryOp::Not, true) => {
+                        let zero = ExpResult::Float(0.0);
+                        let id = call_float_intrinsic(
+                            program,
+                            info,
+                            FloatIntrinsic::Eq,
+                            &[&exp_id, &zero],
+                        )?;
+                        Ok(ExpResult::ID(id))
                     }
                 }
-                ExpResult::ID(info.now_id)
             }
-            UnaryExp::Call(ident, exps) => {
+            UnaryExp::Call(ident, exps, span) => {
                 //计算每个形参表达式
                 let mut args = vec![];
                 for exp in exps {
-                    args.push(exp.generate(output, info));
-                    //TODO????
+                    args.push(exp.generate(program, info)?);
                 }
-                let x = info.search_symbol(ident).unwrap();
-                match x.content {
-                    Func(func_info) => match func_info.ret_type {
-                        FuncType::Void => {
-                            write!(output, "  call @{}", info.get_name(&ident)).unwrap();
-                        }
-                        FuncType::Int => {
-                            info.now_id += 1;
-                            write!(
-                                output,
-                                "  %{} = call @{}",
-                                info.now_id,
-                                info.get_name(&ident)
-                            )
-                            .unwrap();
+                let x = match info.search_symbol(ident) {
+                    Some(x) => x,
+                    None => {
+                        return Err(CompileError::semantic(
+                            *span,
+                            format!("调用了未定义的函数`{}`", ident),
+                        ))
+                    }
+                };
+                let func_info = match x.content {
+                    Func(func_info) => func_info,
+                    _ => {
+                        return Err(CompileError::semantic(
+                            *span,
+                            format!("尝试调用非函数`{}`", ident),
+                        ))
+                    }
+                };
+                //实参隐式转换成形参的类型
+                let mut real_args = vec![];
+                let mut variadic_floats = vec![];
+                for (i, arg) in args.into_iter().enumerate() {
+                    real_args.push(match func_info.params.get(i) {
+                        Some(ParamKind::Int) => convert(arg, BType::Int, program, info)?,
+                        Some(ParamKind::Float) => convert(arg, BType::Float, program, info)?,
+                        Some(ParamKind::Array(..) | ParamKind::Str) => arg,
+                        //可变参数保持原本的类型
+                        None => {
+                            variadic_floats.push(arg.is_float());
+                            arg
                         }
-                    },
-                    _ => panic!("尝试调用非函数"),
+                    });
                 }
-                write!(output, "(").unwrap();
-                for (i, arg) in args.iter().enumerate() {
-                    if i != 0 {
-                        write!(output, ", ").unwrap();
-                    }
-                    write!(output, "{}", arg.clone().unwrap()).unwrap();
+                let mut callee_name = ident.clone();
+                //starttime()/stoptime()改写成带行号的运行时函数
+                if let Some(timer) = info.timer_func(ident, &func_info) {
+                    real_args.push(ExpResult::Value(info.source_line(span.start) as i32));
+                    callee_name = timer;
                 }
-                writeln!(output, ")").unwrap();
-                ExpResult::ID(info.now_id)
+                let callee = match func_info.variadic {
+                    true => info.putf_decl(program, &variadic_floats),
+                    false => info.func(program, &callee_name)?,
+                };
+                let real_args: Vec<Value> = real_args
+                    .into_iter()
+                    .map(|arg| arg.into_value(program, info))
+                    .collect();
+                let call = info.inst(program, |builder| builder.call(callee, real_args));
+                Ok(match func_info.ret_type {
+                    FuncType::Float => ExpResult::FloatID(call),
+                    FuncType::Int | FuncType::Void => ExpResult::ID(call),
+                })
             }
         }
     }
@@ -508,36 +860,43 @@ impl GenerateIR for UnaryExp {
 ///为PrimaryExp实现GenerateIR trait
 impl GenerateIR for PrimaryExp {
     type GenerateResult = ExpResult;
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> ExpResult {
-        let eval_result = self.eval(info);
-        if eval_result.is_some() {
-            return ExpResult::Value(eval_result.unwrap());
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<ExpResult> {
+        if let Some(val) = self.eval(info)? {
+            return Ok(ExpResult::from(val));
         }
         match self {
-            PrimaryExp::Bexp(exp) => exp.generate(output, info),
-            PrimaryExp::Number(num) => {
-                info.now_id += 1;
-                //这里以后回来改
-                writeln!(output, "  %{} = add {}, 0", info.now_id, num).unwrap();
-                ExpResult::ID(info.now_id)
+            PrimaryExp::Bexp(exp) => exp.generate(program, info),
+            PrimaryExp::Number(num, span) => Ok(ExpResult::Value(literal_value(*num, *span)?)),
+            PrimaryExp::FloatNumber(num, _) => Ok(ExpResult::Float(*num)),
+            PrimaryExp::Str(bytes, _) => {
+                //字符串以指向首字符的指针传递
+                let string = info.string_literal(program, bytes);
+                let zero = info.integer(program, 0);
+                let ptr = info.inst(program, |builder| builder.get_elem_ptr(string, zero));
+                Ok(ExpResult::ID(ptr))
             }
             PrimaryExp::LVal(lval) => {
-                let lval_result = lval.generate(output, info);
+                let lval_result = lval.generate(program, info)?;
 
                 match lval_result {
-                    LvalResult::PointerArray(array_ptr_id) => {
+                    LvalResult::PointerArray(array_ptr) => {
                         //如果是数组指针
-                        return ExpResult::ID(array_ptr_id);
+                        Ok(ExpResult::ID(array_ptr))
                     }
-                    LvalResult::Pointer(array_ptr_id) => {
+                    LvalResult::Pointer(array_ptr, btype) => {
                         //如果是指针
-                        info.now_id += 1;
-                        writeln!(output, "  %{} = load %{}", info.now_id, array_ptr_id).unwrap();
-                        return ExpResult::ID(info.now_id);
+                        let val = info.inst(program, |builder| builder.load(array_ptr));
+                        Ok(match btype {
+                            BType::Int => ExpResult::ID(val),
+                            BType::Float => ExpResult::FloatID(val),
+                        })
                     }
-                    LvalResult::Value(lval_id) => {
+                    LvalResult::Value(val, btype) => {
                         //如果是值
-                        return ExpResult::ID(lval_id);
+                        Ok(match btype {
+                            BType::Int => ExpResult::ID(val),
+                            BType::Float => ExpResult::FloatID(val),
+                        })
                     }
                 }
             }
@@ -548,24 +907,16 @@ impl GenerateIR for PrimaryExp {
 ///为AddExp实现GenerateIR trait
 impl GenerateIR for AddExp {
     type GenerateResult = ExpResult;
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> ExpResult {
-        let eval_result = self.eval(info);
-        if eval_result.is_some() {
-            return ExpResult::Value(eval_result.unwrap());
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<ExpResult> {
+        if let Some(val) = self.eval(info)? {
+            return Ok(ExpResult::from(val));
         }
         match self {
-            AddExp::MulExp(mul_exp) => mul_exp.generate(output, info),
+            AddExp::MulExp(mul_exp) => mul_exp.generate(program, info),
             AddExp::BinaryExp(add_exp, op, mul_exp) => {
-                let mul_id = mul_exp.generate(output, info);
-                let add_id = add_exp.generate(output, info);
-                info.now_id += 1;
-                write!(output, "  %{} = ", info.now_id).unwrap();
-                match op {
-                    BinaryAddOp::Add => write!(output, "add").unwrap(),
-                    BinaryAddOp::Sub => write!(output, "sub").unwrap(),
-                }
-                writeln!(output, " {}, {}", add_id.unwrap(), mul_id.unwrap()).unwrap();
-                ExpResult::ID(info.now_id)
+                let mul_id = mul_exp.generate(program, info)?;
+                let add_id = add_exp.generate(program, info)?;
+                arith(op.into(), add_id, mul_id, program, info)
             }
         }
     }
@@ -574,25 +925,38 @@ impl GenerateIR for AddExp {
 ///为MulExp实现GenerateIR trait
 impl GenerateIR for MulExp {
     type GenerateResult = ExpResult;
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> ExpResult {
-        let eval_result = self.eval(info);
-        if eval_result.is_some() {
-            return ExpResult::Value(eval_result.unwrap());
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<ExpResult> {
+        if let Some(val) = self.eval(info)? {
+            return Ok(ExpResult::from(val));
         }
         match self {
-            MulExp::UnaryExp(unary_exp) => unary_exp.generate(output, info),
+            MulExp::UnaryExp(unary_exp) => unary_exp.generate(program, info),
             MulExp::BinaryExp(mul_exp, op, unary_exp) => {
-                let unary_id = unary_exp.generate(output, info);
-                let mul_id = mul_exp.generate(output, info);
-                info.now_id += 1;
-                write!(output, "  %{} = ", info.now_id).unwrap();
-                match op {
-                    BinaryMulOp::Mul => write!(output, "mul").unwrap(),
-                    BinaryMulOp::Div => write!(output, "div").unwrap(),
-                    BinaryMulOp::Mod => write!(output, "mod").unwrap(),
-                }
-                writeln!(output, " {}, {}", mul_id.unwrap(), unary_id.unwrap()).unwrap();
-                ExpResult::ID(info.now_id)
+                let unary_id = unary_exp.generate(program, info)?;
+                let mul_id = mul_exp.generate(program, info)?;
+                arith(op.into(), mul_id, unary_id, program, info)
+            }
+        }
+    }
+}
+
+///为ShiftExp实现GenerateIR trait
+impl GenerateIR for ShiftExp {
+    type GenerateResult = ExpResult;
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<ExpResult> {
+        if let Some(val) = self.eval(info)? {
+            return Ok(ExpResult::from(val));
+        }
+        match self {
+            ShiftExp::AddExp(add_exp) => add_exp.generate(program, info),
+            ShiftExp::BinaryExp(shift_exp, op, add_exp) => {
+                let rhs_id = add_exp.generate(program, info)?;
+                let lhs_id = shift_exp.generate(program, info)?;
+                let op = match op {
+                    BinaryShiftOp::Shl => BinaryOp::Shl,
+                    BinaryShiftOp::Sar => BinaryOp::Sar,
+                };
+                bit_op(op, lhs_id, rhs_id, program, info)
             }
         }
     }
@@ -601,26 +965,33 @@ impl GenerateIR for MulExp {
 ///为RelExp实现GenerateIR trait
 impl GenerateIR for RelExp {
     type GenerateResult = ExpResult;
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> ExpResult {
-        let eval_result = self.eval(info);
-        if eval_result.is_some() {
-            return ExpResult::Value(eval_result.unwrap());
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<ExpResult> {
+        if let Some(val) = self.eval(info)? {
+            return Ok(ExpResult::from(val));
         }
         match self {
-            RelExp::AddExp(add_exp) => add_exp.generate(output, info),
-            RelExp::BinaryExp(rel_exp, op, add_exp) => {
-                let add_id = add_exp.generate(output, info);
-                let rel_id = rel_exp.generate(output, info);
-                info.now_id += 1;
-                write!(output, "  %{} = ", info.now_id).unwrap();
-                match op {
-                    BinaryRelOp::Lt => write!(output, "lt").unwrap(),
-                    BinaryRelOp::Gt => write!(output, "gt").unwrap(),
-                    BinaryRelOp::Le => write!(output, "le").unwrap(),
-                    BinaryRelOp::Ge => write!(output, "ge").unwrap(),
+            RelExp::ShiftExp(shift_exp) => shift_exp.generate(program, info),
+            RelExp::BinaryExp(rel_exp, op, shift_exp) => {
+                let shift_id = shift_exp.generate(program, info)?;
+                let rel_id = rel_exp.generate(program, info)?;
+                let (rel_id, shift_id, is_float) = unify_operands(rel_id, shift_id, program, info)?;
+                if is_float {
+                    let op = match op {
+                        BinaryRelOp::Lt => FloatIntrinsic::Lt,
+                        BinaryRelOp::Gt => FloatIntrinsic::Gt,
+                        BinaryRelOp::Le => FloatIntrinsic::Le,
+                        BinaryRelOp::Ge => FloatIntrinsic::Ge,
+                    };
+                    let id = call_float_intrinsic(program, info, op, &[&rel_id, &shift_id])?;
+                    return Ok(ExpResult::ID(id));
                 }
-                writeln!(output, " {}, {}", rel_id.unwrap(), add_id.unwrap()).unwrap();
-                ExpResult::ID(info.now_id)
+                let op = match op {
+                    BinaryRelOp::Lt => BinaryOp::Lt,
+                    BinaryRelOp::Gt => BinaryOp::Gt,
+                    BinaryRelOp::Le => BinaryOp::Le,
+                    BinaryRelOp::Ge => BinaryOp::Ge,
+                };
+                Ok(ExpResult::ID(binary(op, rel_id, shift_id, program, info)))
             }
         }
     }
@@ -629,24 +1000,83 @@ impl GenerateIR for RelExp {
 ///为EqExp实现GenerateIR trait
 impl GenerateIR for EqExp {
     type GenerateResult = ExpResult;
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> ExpResult {
-        let eval_result = self.eval(info);
-        if eval_result.is_some() {
-            return ExpResult::Value(eval_result.unwrap());
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<ExpResult> {
+        if let Some(val) = self.eval(info)? {
+            return Ok(ExpResult::from(val));
         }
         match self {
-            EqExp::RelExp(rel_exp) => rel_exp.generate(output, info),
+            EqExp::RelExp(rel_exp) => rel_exp.generate(program, info),
             EqExp::BinaryExp(eq_exp, op, rel_exp) => {
-                let rel_id = rel_exp.generate(output, info);
-                let eq_id = eq_exp.generate(output, info);
-                info.now_id += 1;
-                write!(output, "  %{} = ", info.now_id).unwrap();
-                match op {
-                    BinaryEqOp::Eq => write!(output, "eq").unwrap(),
-                    BinaryEqOp::Ne => write!(output, "ne").unwrap(),
+                let rel_id = rel_exp.generate(program, info)?;
+                let eq_id = eq_exp.generate(program, info)?;
+                let (eq_id, rel_id, is_float) = unify_operands(eq_id, rel_id, program, info)?;
+                if is_float {
+                    let op = match op {
+                        BinaryEqOp::Eq => FloatIntrinsic::Eq,
+                        BinaryEqOp::Ne => FloatIntrinsic::Ne,
+                    };
+                    let id = call_float_intrinsic(program, info, op, &[&eq_id, &rel_id])?;
+                    return Ok(ExpResult::ID(id));
                 }
-                writeln!(output, " {}, {}", eq_id.unwrap(), rel_id.unwrap()).unwrap();
-                ExpResult::ID(info.now_id)
+                let op = match op {
+                    BinaryEqOp::Eq => BinaryOp::Eq,
+                    BinaryEqOp::Ne => BinaryOp::NotEq,
+                };
+                Ok(ExpResult::ID(binary(op, eq_id, rel_id, program, info)))
+            }
+        }
+    }
+}
+
+///为BitAndExp实现GenerateIR trait
+impl GenerateIR for BitAndExp {
+    type GenerateResult = ExpResult;
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<ExpResult> {
+        if let Some(val) = self.eval(info)? {
+            return Ok(ExpResult::from(val));
+        }
+        match self {
+            BitAndExp::EqExp(eq_exp) => eq_exp.generate(program, info),
+            BitAndExp::BinaryExp(and_exp, eq_exp) => {
+                let rhs_id = eq_exp.generate(program, info)?;
+                let lhs_id = and_exp.generate(program, info)?;
+                bit_op(BinaryOp::And, lhs_id, rhs_id, program, info)
+            }
+        }
+    }
+}
+
+///为BitXorExp实现GenerateIR trait
+impl GenerateIR for BitXorExp {
+    type GenerateResult = ExpResult;
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<ExpResult> {
+        if let Some(val) = self.eval(info)? {
+            return Ok(ExpResult::from(val));
+        }
+        match self {
+            BitXorExp::BitAndExp(and_exp) => and_exp.generate(program, info),
+            BitXorExp::BinaryExp(xor_exp, and_exp) => {
+                let rhs_id = and_exp.generate(program, info)?;
+                let lhs_id = xor_exp.generate(program, info)?;
+                bit_op(BinaryOp::Xor, lhs_id, rhs_id, program, info)
+            }
+        }
+    }
+}
+
+///为BitOrExp实现GenerateIR trait
+impl GenerateIR for BitOrExp {
+    type GenerateResult = ExpResult;
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<ExpResult> {
+        if let Some(val) = self.eval(info)? {
+            return Ok(ExpResult::from(val));
+        }
+        match self {
+            BitOrExp::BitXorExp(xor_exp) => xor_exp.generate(program, info),
+            BitOrExp::BinaryExp(or_exp, xor_exp) => {
+                let rhs_id = xor_exp.generate(program, info)?;
+                let lhs_id = or_exp.generate(program, info)?;
+                bit_op(BinaryOp::Or, lhs_id, rhs_id, program, info)
             }
         }
     }
@@ -656,71 +1086,51 @@ impl GenerateIR for EqExp {
 ///注意应该是实现逻辑and，Koopa IR中的是按位and
 impl GenerateIR for LAndExp {
     type GenerateResult = ExpResult;
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> ExpResult {
-        let eval_result = self.eval(info);
-        if eval_result.is_some() {
-            return ExpResult::Value(eval_result.unwrap());
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<ExpResult> {
+        if let Some(val) = self.eval(info)? {
+            return Ok(ExpResult::from(val));
         }
         match self {
-            LAndExp::EqExp(eq_exp) => eq_exp.generate(output, info),
+            LAndExp::BitOrExp(or_exp) => or_exp.generate(program, info),
             /*and短路求值逻辑
               @and_result_114 = alloc i32
               store 0, @and_result_114
               %lhs = ...
-              %lhs_ne_0_114 = ne %lhs 0
-              br %lhs_ne_0_114, %calc_rhs_114, %and_end_114
+              %lhs_ne_0 = ne %lhs 0
+              br %lhs_ne_0, %calc_rhs_114, %and_end_114
             %calc_rhs_114:
               %rhs = ...
-              %rhs_ne_0_114 = ne %rhs, 0
-              store %rhs_ne_0_114, @and_result_114
+              %rhs_ne_0 = ne %rhs, 0
+              store %rhs_ne_0, @and_result_114
               jump %and_end_114
             %and_end_114:
               %ans = load @and_result_114
             */
-            LAndExp::BinaryExp(land_exp, eq_exp) => {
+            LAndExp::BinaryExp(land_exp, or_exp) => {
                 info.and_or_id += 1;
                 let now_and_or_id = info.and_or_id;
-                writeln!(output, "  @and_result_{} = alloc i32", now_and_or_id).unwrap();
-                writeln!(output, "  store 0, @and_result_{}", now_and_or_id).unwrap();
-                let lhs_id = land_exp.generate(output, info);
-                writeln!(
-                    output,
-                    "  %lhs_ne_0_{} = ne {}, 0",
-                    now_and_or_id,
-                    lhs_id.unwrap()
-                )
-                .unwrap();
-                writeln!(
-                    output,
-                    "  br %lhs_ne_0_{}, %calc_rhs_{}, %and_end_{}",
-                    now_and_or_id, now_and_or_id, now_and_or_id
-                )
-                .unwrap();
-                writeln!(output, "%calc_rhs_{}:", now_and_or_id).unwrap();
-                let rhs_id = eq_exp.generate(output, info);
-                writeln!(
-                    output,
-                    "  %rhs_ne_0_{} = ne {}, 0",
-                    now_and_or_id,
-                    rhs_id.unwrap()
-                )
-                .unwrap();
-                writeln!(
-                    output,
-                    "  store %rhs_ne_0_{}, @and_result_{}",
-                    now_and_or_id, now_and_or_id
-                )
-                .unwrap();
-                writeln!(output, "  jump %and_end_{}", now_and_or_id).unwrap();
-                writeln!(output, "%and_end_{}:", now_and_or_id).unwrap();
-                info.now_id += 1;
-                writeln!(
-                    output,
-                    "  %{} = load @and_result_{}",
-                    info.now_id, now_and_or_id
-                )
-                .unwrap();
-                ExpResult::ID(info.now_id)
+                let result = info.alloc(
+                    program,
+                    format!("and_result_{}", now_and_or_id),
+                    Type::get_i32(),
+                );
+                let zero = info.integer(program, 0);
+                info.inst(program, |builder| builder.store(zero, result));
+                let lhs_id = land_exp.generate(program, info)?;
+                let lhs_id = truth_value(lhs_id, program, info)?;
+                let lhs_ne_0 = binary(BinaryOp::NotEq, lhs_id, ExpResult::Value(0), program, info);
+                let rhs_label = format!("%calc_rhs_{}", now_and_or_id);
+                let end_label = format!("%and_end_{}", now_and_or_id);
+                info.branch(program, lhs_ne_0, &rhs_label, &end_label);
+                info.start_bb(program, &rhs_label);
+                let rhs_id = or_exp.generate(program, info)?;
+                let rhs_id = truth_value(rhs_id, program, info)?;
+                let rhs_ne_0 = binary(BinaryOp::NotEq, rhs_id, ExpResult::Value(0), program, info);
+                info.inst(program, |builder| builder.store(rhs_ne_0, result));
+                info.jump(program, &end_label);
+                info.start_bb(program, &end_label);
+                let ans = info.inst(program, |builder| builder.load(result));
+                Ok(ExpResult::ID(ans))
             }
         }
     }
@@ -729,72 +1139,52 @@ impl GenerateIR for LAndExp {
 ///为LOrExp实现GenerateIR trait
 impl GenerateIR for LOrExp {
     type GenerateResult = ExpResult;
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> ExpResult {
-        let eval_result = self.eval(info);
-        if eval_result.is_some() {
-            return ExpResult::Value(eval_result.unwrap());
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<ExpResult> {
+        if let Some(val) = self.eval(info)? {
+            return Ok(ExpResult::from(val));
         }
         /*or短路求值逻辑
           @or_result_114 = alloc i32
           store 1, @or_result_114
           %lhs = ...
-          %lhs_eq_0_114 = eq %lhs 0
-          br %lhs_eq_0_114, %calc_rhs_114, %or_end_114
+          %lhs_eq_0 = eq %lhs 0
+          br %lhs_eq_0, %calc_rhs_114, %or_end_114
         %calc_rhs_114:
           %rhs = ...
-          %rhs_ne_0_114 = ne %rhs, 0
-          store %rhs_ne_0_114, @or_result_114
+          %rhs_ne_0 = ne %rhs, 0
+          store %rhs_ne_0, @or_result_114
           jump %or_end_114
         %or_end_114:
           %ans = load @or_result_114
         */
         match self {
-            LOrExp::LAndExp(land_exp) => land_exp.generate(output, info),
+            LOrExp::LAndExp(land_exp) => land_exp.generate(program, info),
             LOrExp::BinaryExp(lor_exp, land_exp) => {
                 info.and_or_id += 1;
                 let now_and_or_id = info.and_or_id;
-                writeln!(output, "  @or_result_{} = alloc i32", now_and_or_id).unwrap();
-                writeln!(output, "  store 1, @or_result_{}", now_and_or_id).unwrap();
-                let lhs_id = lor_exp.generate(output, info);
-                writeln!(
-                    output,
-                    "  %lhs_eq_0_{} = eq {}, 0",
-                    now_and_or_id,
-                    lhs_id.unwrap()
-                )
-                .unwrap();
-                writeln!(
-                    output,
-                    "  br %lhs_eq_0_{}, %calc_rhs_{}, %or_end_{}",
-                    now_and_or_id, now_and_or_id, now_and_or_id
-                )
-                .unwrap();
-                writeln!(output, "%calc_rhs_{}:", now_and_or_id).unwrap();
-                let rhs_id = land_exp.generate(output, info);
-                writeln!(
-                    output,
-                    "  %rhs_ne_0_{} = ne {}, 0",
-                    now_and_or_id,
-                    rhs_id.unwrap()
-                )
-                .unwrap();
-                writeln!(
-                    output,
-                    "  store %rhs_ne_0_{}, @or_result_{}",
-                    now_and_or_id, now_and_or_id
-                )
-                .unwrap();
-                writeln!(output, "  jump %or_end_{}", now_and_or_id).unwrap();
-                writeln!(output, "%or_end_{}:", now_and_or_id).unwrap();
-                info.now_id += 1;
-                writeln!(
-                    output,
-                    "  %{} = load @or_result_{}",
-                    info.now_id, now_and_or_id
-                )
-                .unwrap();
-
-                ExpResult::ID(info.now_id)
+                let result = info.alloc(
+                    program,
+                    format!("or_result_{}", now_and_or_id),
+                    Type::get_i32(),
+                );
+                let one = info.integer(program, 1);
+                info.inst(program, |builder| builder.store(one, result));
+                let lhs_id = lor_exp.generate(program, info)?;
+                let lhs_id = truth_value(lhs_id, program, info)?;
+                let lhs_eq_0 = binary(BinaryOp::Eq, lhs_id, ExpResult::Value(0), program, info);
+                let rhs_label = format!("%calc_rhs_{}", now_and_or_id);
+                let end_label = format!("%or_end_{}", now_and_or_id);
+                info.branch(program, lhs_eq_0, &rhs_label, &end_label);
+                info.start_bb(program, &rhs_label);
+                let rhs_id = land_exp.generate(program, info)?;
+                let rhs_id = truth_value(rhs_id, program, info)?;
+                let rhs_ne_0 = binary(BinaryOp::NotEq, rhs_id, ExpResult::Value(0), program, info);
+                info.inst(program, |builder| builder.store(rhs_ne_0, result));
+                info.jump(program, &end_label);
+                info.start_bb(program, &end_label);
+                let ans = info.inst(program, |builder| builder.load(result));
+
+                Ok(ExpResult::ID(ans))
             }
         }
     }
@@ -803,168 +1193,158 @@ impl GenerateIR for LOrExp {
 ///为Decl实现GenerateIR trait
 impl GenerateIR for Decl {
     type GenerateResult = Returned;
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> Returned {
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<Returned> {
         match self {
-            Decl::ConstDecl(const_decl) => const_decl.generate(output, info),
-            Decl::VarDecl(var_decl) => var_decl.generate(output, info),
+            Decl::ConstDecl(const_decl) => const_decl.generate(program, info)?,
+            Decl::VarDecl(var_decl) => var_decl.generate(program, info)?,
         }
-        Returned::No
+        Ok(Returned::No)
     }
 }
 
 ///为ConstDecl实现GenerateIR trait
 impl GenerateIR for ConstDecl {
     type GenerateResult = ();
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) {
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<()> {
         match self {
             ConstDecl::ConstDeclS(btype, const_def_s) => {
+                info.decl_type = *btype;
                 for const_def in const_def_s {
-                    const_def.generate(output, info);
+                    const_def.generate(program, info)?;
                 }
             }
         }
+        Ok(())
     }
 }
 
 ///为ConstDef实现GenerateIR trait
 impl GenerateIR for ConstDef {
     type GenerateResult = ();
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) {
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<()> {
         if self.dims.is_empty() {
             //如果dims为空，则为常量定义
-            match self.const_init_val.eval(info) {
-                Some(val) => {
-                    info.insert_symbol(self.ident.clone(), Const(val));
-                }
-                None => panic!("detected Var in ConstDef when evaluating"),
-            }
+            let what = format!("常量`{}`的初值", self.ident);
+            let val = eval_const(&self.const_init_val, self.span, info, &what)?;
+            let val = val.convert(info.decl_type);
+            info.insert_symbol(self.ident.clone(), Const(val), self.span)?;
         } else {
             //如果dims不为空，则为常量数组定义，数组名用@开头
 
-            //生成维度声明并加入符号表
-            let real_dims = self.gen_def_dim(output, info);
-
-            //填充初始化内容表
-            let mut result: Vec<i32> = vec![];
-            self.const_init_val
-                .global_array_init(output, info, &real_dims, &mut result);
+            //计算维度并加入符号表
+            let real_dims = self.gen_def_dim(info)?;
+
+            //初始化内容表在计算维度时已经展平存入了符号表
+            let result = match info.search_symbol(&self.ident).map(|x| x.content) {
+                Some(Array(ArrayInfoBase {
+                    const_init: Some(init),
+                    ..
+                })) => init,
+                _ => {
+                    return Err(CompileError::internal(format!(
+                        "常量数组`{}`没有初值",
+                        self.ident
+                    )))
+                }
+            };
+            if result.is_empty() {
+                return Err(CompileError::internal(
+                    "数组初始化结果为空，可能由数组初值为{}引起".to_string(),
+                ));
+            }
 
             //为全局生成初始化内容，为局部生成初始化指令
-            match info.is_global_symbol(&self.ident) {
+            let name = info.get_name(&self.ident)?;
+            match info.is_global_symbol(&self.ident)? {
                 true => {
-                    if result.len() == 0 {
-                        panic!("可能由数组初值为{{}}引起");
-                    } else {
-                        write!(output, ", ").unwrap();
-                        gen_global_array_ir(output, &real_dims, &result, 0);
-                    }
-
-                    writeln!(output, "").unwrap(); //换行
+                    let init = gen_global_array_ir(program, &real_dims, &result, 0);
+                    info.global_alloc(program, name, init);
                 }
                 false => {
                     //局部常量数组初始化
-                    write!(output, "\n").unwrap(); //换行
-
-                    if result.len() == 0 {
-                        panic!("可能由数组初值为{{}}引起");
-                    } else {
-                        gen_local_const_array_ir(
-                            output,
-                            info,
-                            &real_dims,
-                            &result,
-                            0,
-                            "@".to_string() + &info.get_name(&self.ident),
-                        );
-                    }
+                    let alloc = info.alloc(program, name, array_type(&real_dims));
+                    gen_local_const_array_ir(program, info, &real_dims, &result, 0, alloc);
                 }
             }
         }
+        Ok(())
     }
 }
 
 ///为VarDecl实现GenerateIR trait
 impl GenerateIR for VarDecl {
     type GenerateResult = ();
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) {
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<()> {
         match self {
             VarDecl::VarDeclS(btype, var_def_s) => {
+                info.decl_type = *btype;
                 for var_def in var_def_s {
-                    var_def.generate(output, info);
+                    var_def.generate(program, info)?;
                 }
             }
         }
+        Ok(())
     }
 }
 
 ///为VarDef实现GenerateIR trait
 impl GenerateIR for VarDef {
     type GenerateResult = ();
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) {
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<()> {
         if self.dims.is_empty() {
             //如果是变量
-            match &self.init_val {
-                None => {
-                    //没有初值
-                    if self.dims.is_empty() {
-                        //纯变量，非数组
-                        info.insert_symbol(self.ident.clone(), Var(VarInfoBase::new()));
-                        match info.is_global_symbol(&self.ident) {
-                            true => writeln!(
-                                output,
-                                "global @{} = alloc i32, zeroinit",
-                                info.get_name(&self.ident)
-                            )
-                            .unwrap(),
-                            false => {
-                                writeln!(output, "  @{} = alloc i32", info.get_name(&self.ident))
-                                    .unwrap()
-                            }
-                        }
-                    }
+            if let Some(InitVal::InitValS(_)) = &self.init_val {
+                return Err(CompileError::semantic(
+                    self.span,
+                    format!("变量`{}`不是数组，不能用初始化列表赋初值", self.ident),
+                ));
+            }
+            //纯变量，非数组
+            let var_info = VarInfoBase::new(info.decl_type);
+            info.insert_symbol(self.ident.clone(), Var(var_info), self.span)?;
+            let name = info.get_name(&self.ident)?;
+            match (&self.init_val, info.is_global_symbol(&self.ident)?) {
+                (None, true) => {
+                    //没有初值的全局变量初始化为0
+                    let init = program.new_value().zero_init(Type::get_i32());
+                    info.global_alloc(program, name, init);
                 }
-                Some(init_val) => {
-                    //纯变量，非数组
-                    info.insert_symbol(self.ident.clone(), Var(VarInfoBase::new()));
-                    match info.is_global_symbol(&self.ident) {
-                        true => writeln!(
-                            output,
-                            "global @{} = alloc i32, {}",
-                            info.get_name(&self.ident),
-                            init_val.eval(info).unwrap()
-                        )
-                        .unwrap(),
-                        false => {
-                            writeln!(output, "  @{} = alloc i32", info.get_name(&self.ident))
-                                .unwrap();
-
-                            let init_val_reult = init_val.generate(output, info);
-
-                            writeln!(
-                                output,
-                                "  store {}, @{}",
-                                init_val_reult.unwrap(),
-                                info.get_name(&self.ident)
-                            )
-                            .unwrap();
-                        }
-                    }
+                (None, false) => {
+                    info.alloc(program, name, Type::get_i32());
+                }
+                (Some(init_val), true) => {
+                    let what = format!("全局变量`{}`的初值", self.ident);
+                    let val = eval_const(init_val, self.span, info, &what)?;
+                    let word = val.convert(info.decl_type).to_word();
+                    let init = program.new_value().integer(word);
+                    info.global_alloc(program, name, init);
+                }
+                (Some(init_val), false) => {
+                    let alloc = info.alloc(program, name, Type::get_i32());
+
+                    let init_val_reult = init_val.generate(program, info)?;
+                    let init_val_reult = convert(init_val_reult, info.decl_type, program, info)?
+                        .into_value(program, info);
+
+                    info.inst(program, |builder| builder.store(init_val_reult, alloc));
                 }
             }
         } else {
             //数组
 
-            //生成维度声明并加入符号表
-            let real_dims = self.gen_def_dim(output, info);
+            //计算维度并加入符号表
+            let real_dims = self.gen_def_dim(info)?;
+            let name = info.get_name(&self.ident)?;
 
             match &self.init_val {
                 None => {
                     //没有初值
                     //未初始化变量数组声明
-                    match info.is_global_symbol(&self.ident) {
+                    match info.is_global_symbol(&self.ident)? {
                         true => {
                             //全局未初始化自动初始化为0
-                            writeln!(output, ", zeroinit").unwrap();
+                            let init = program.new_value().zero_init(array_type(&real_dims));
+                            info.global_alloc(program, name, init);
                         }
                         false => {
                             //局部变量数组未初始化不用管!
@@ -972,62 +1352,72 @@ impl GenerateIR for VarDef {
                             SysY规范：未显式初始化的局部变量, 其值是不确定的;
                             而未显式初始化的全局变量, 其 (元素) 值均被初始化为 0.
                             */
+                            info.alloc(program, name, array_type(&real_dims));
                         }
                     }
                 }
                 Some(init_val) => {
                     //有初值的变量数组初始化
 
-                    match info.is_global_symbol(&self.ident) {
+                    match info.is_global_symbol(&self.ident)? {
                         true => {
                             //全局有初值变量数组
                             let mut result: Vec<i32> = vec![];
-                            init_val.global_array_init(output, info, &real_dims, &mut result);
-                            if result.len() == 0 {
+                            let btype = info.decl_type;
+                            init_val.global_array_init(
+                                info,
+                                &real_dims,
+                                btype,
+                                self.span,
+                                &mut result,
+                            )?;
+                            if result.is_empty() {
                                 //初始值是{}，初始化为0
-                                panic!("可能由数组初值为{{}}引起");
-                            } else {
-                                write!(output, ", ").unwrap();
-                                gen_global_array_ir(output, &real_dims, &result, 0);
+                                return Err(CompileError::internal(
+                                    "数组初始化结果为空，可能由数组初值为{}引起".to_string(),
+                                ));
                             }
-
-                            writeln!(output, "").unwrap(); //换行
+                            let init = gen_global_array_ir(program, &real_dims, &result, 0);
+                            info.global_alloc(program, name, init);
                         }
                         false => {
                             //局部有初值变量数组
-
-                            write!(output, "\n").unwrap(); //换行
-
-                            let mut result: Vec<String> = vec![];
-                            init_val.local_array_init(output, info, &real_dims, &mut result);
-                            if result.len() == 0 {
-                                panic!("可能由数组初值为{{}}引起");
-                            } else {
-                                //TODO
-                                gen_local_var_array_ir_string_ver(
-                                    output,
-                                    info,
-                                    &real_dims,
-                                    &result,
-                                    0,
-                                    "@".to_string() + &info.get_name(&self.ident),
-                                );
+                            let alloc = info.alloc(program, name, array_type(&real_dims));
+
+                            let mut result: Vec<ExpResult> = vec![];
+                            let btype = info.decl_type;
+                            init_val.local_array_init(
+                                program,
+                                info,
+                                &real_dims,
+                                btype,
+                                self.span,
+                                &mut result,
+                            )?;
+                            if result.is_empty() {
+                                return Err(CompileError::internal(
+                                    "数组初始化结果为空，可能由数组初值为{}引起".to_string(),
+                                ));
                             }
+                            gen_local_var_array_ir(program, info, &real_dims, &result, 0, alloc);
                         }
                     }
                 }
             }
         }
+        Ok(())
     }
 }
 
 ///为InitVal实现GenerateIR trait
 impl GenerateIR for InitVal {
     type GenerateResult = ExpResult;
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> ExpResult {
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<ExpResult> {
         match self {
-            InitVal::Exp(exp) => exp.generate(output, info),
-            _ => unreachable!(), //TODO:好像不会到这个分支
+            InitVal::Exp(exp) => exp.generate(program, info),
+            InitVal::InitValS(_) => Err(CompileError::internal(
+                "初始化列表不能作为表达式求值".to_string(),
+            )),
         }
     }
 }
@@ -1036,153 +1426,105 @@ impl GenerateIR for InitVal {
 ///作用是取出LVal对应的变量的值，存入返回值中
 ///或者是将数组对应位置的指针值放在返回值中
 pub enum LvalResult {
-    PointerArray(i32), //部分解引用的数组 *[i32][[[]]]
-    Pointer(i32),      //完全解引用的数组 *i32
-    Value(i32),
+    PointerArray(Value),   //部分解引用的数组 *[i32][[[]]]
+    Pointer(Value, BType), //完全解引用的数组 *i32，附带元素类型
+    Value(Value, BType),
 }
 impl GenerateIR for LVal {
     type GenerateResult = LvalResult;
-    fn generate(&self, output: &mut dyn Write, info: &mut GenerateIrInfo) -> LvalResult {
-        let x = info.search_symbol(&self.ident).unwrap();
+    fn generate(&self, program: &mut Program, info: &mut GenerateIrInfo) -> Result<LvalResult> {
+        let x = match info.search_symbol(&self.ident) {
+            Some(x) => x,
+            None => {
+                return Err(CompileError::semantic(
+                    self.span,
+                    format!("使用了未定义的符号`{}`", self.ident),
+                ))
+            }
+        };
         match x.content {
-            Var(_) => {
+            Var(var_info) => {
                 //LVal是变量
-                let eval_result = self.eval(info);
-                if eval_result.is_some() {
-                    info.now_id += 1;
-                    writeln!(
-                        output,
-                        "  %{} = add {}, 0",
-                        info.now_id,
-                        eval_result.unwrap()
-                    )
-                    .unwrap();
-                    return LvalResult::Value(info.now_id);
+                if let Some(val) = self.eval(info)? {
+                    let word = info.integer(program, val.to_word());
+                    return Ok(LvalResult::Value(word, val.btype()));
                 } //如果可以编译期间计算，直接返回计算结果
 
-                info.now_id += 1;
-                writeln!(
-                    output,
-                    "  %{} = load @{}",
-                    info.now_id,
-                    info.get_name(&self.ident)
-                )
-                .unwrap();
-                LvalResult::Value(info.now_id)
+                let alloc = info.get_alloc(&self.ident)?;
+                let val = info.inst(program, |builder| builder.load(alloc));
+                Ok(LvalResult::Value(val, var_info.btype))
             }
             Const(val) => {
                 //LVal是常量
-                info.now_id += 1;
-                writeln!(output, "  %{} = add {}, 0", info.now_id, val).unwrap();
-                LvalResult::Value(info.now_id)
+                let word = info.integer(program, val.to_word());
+                Ok(LvalResult::Value(word, val.btype()))
             }
             Array(array_info) => {
                 //LVal是数组
                 //将数组的指针存入返回值中（可能部分解引用）
-                let mut last_base_string = "@".to_string() + &info.get_name(&self.ident);
+                let mut last_base = info.get_alloc(&self.ident)?;
                 for dim in &self.dims {
-                    let dim_id = dim.generate(output, info);
-                    info.now_id += 1;
-                    writeln!(
-                        output,
-                        "  %{} = getelemptr {}, {}",
-                        info.now_id,
-                        last_base_string,
-                        dim_id.unwrap()
-                    )
-                    .unwrap();
-                    last_base_string = "%".to_owned() + info.now_id.to_string().as_str();
+                    let dim_id = dim.generate(program, info)?.into_value(program, info);
+                    last_base =
+                        info.inst(program, |builder| builder.get_elem_ptr(last_base, dim_id));
                 }
                 if array_info.dims.len() > self.dims.len() {
                     //部分解引用
-                    info.now_id += 1;
-                    writeln!(
-                        output,
-                        "  %{} = getelemptr {}, 0",
-                        info.now_id, last_base_string
-                    )
-                    .unwrap();
-                    return LvalResult::PointerArray(info.now_id);
+                    let zero = info.integer(program, 0);
+                    let ptr = info.inst(program, |builder| builder.get_elem_ptr(last_base, zero));
+                    Ok(LvalResult::PointerArray(ptr))
                 } else {
                     //完全解引用
-                    return LvalResult::Pointer(info.now_id);
+                    Ok(LvalResult::Pointer(last_base, array_info.btype))
                 }
             }
             ArrayPointer(array_info) => {
                 //LVal是数组指针
 
-                info.now_id += 1; //先把数组指针读出来：从**[]读到*[]
-                writeln!(
-                    output,
-                    "  %{} = load @{}",
-                    info.now_id,
-                    info.get_name(&self.ident)
-                )
-                .unwrap();
-
+                //先把数组指针读出来：从**[]读到*[]
+                let alloc = info.get_alloc(&self.ident)?;
                 //这个值代表的是就是数组指针的值
-                let mut last_base_string = "%".to_string() + &info.now_id.to_string();
+                let mut last_base = info.inst(program, |builder| builder.load(alloc));
 
                 if self.dims.is_empty() {
                     //直接访问数组指针？
-                    return LvalResult::PointerArray(info.now_id);
+                    return Ok(LvalResult::PointerArray(last_base));
                 }
 
                 for (i, dim) in self.dims.iter().enumerate() {
                     //然后用getelemptr解决剩下的维度
-                    let dim_id = dim.generate(output, info);
-                    info.now_id += 1;
-                    if i != 0 {
-                        writeln!(
-                            output,
-                            "  %{} = getelemptr {}, {}",
-                            info.now_id,
-                            last_base_string,
-                            dim_id.unwrap()
-                        )
-                        .unwrap();
+                    let dim_id = dim.generate(program, info)?.into_value(program, info);
+                    last_base = if i != 0 {
+                        info.inst(program, |builder| builder.get_elem_ptr(last_base, dim_id))
                     } else {
                         //首先用getptr解决第一维
-                        writeln!(
-                            output,
-                            "  %{} = getptr {}, {}",
-                            info.now_id,
-                            last_base_string,
-                            dim_id.unwrap()
-                        )
-                        .unwrap();
-                    }
-
-                    last_base_string = "%".to_owned() + info.now_id.to_string().as_str();
+                        info.inst(program, |builder| builder.get_ptr(last_base, dim_id))
+                    };
                 }
 
                 if array_info.dims.len() + 1 > self.dims.len() {
                     //部分解引用
-                    info.now_id += 1;
-                    writeln!(
-                        output,
-                        "  %{} = getelemptr {}, 0",
-                        info.now_id, last_base_string
-                    )
-                    .unwrap();
-                    return LvalResult::PointerArray(info.now_id);
+                    let zero = info.integer(program, 0);
+                    let ptr = info.inst(program, |builder| builder.get_elem_ptr(last_base, zero));
+                    Ok(LvalResult::PointerArray(ptr))
                 } else {
                     //完全解引用
-                    return LvalResult::Pointer(info.now_id);
+                    Ok(LvalResult::Pointer(last_base, array_info.btype))
                 }
             }
-            Func(_) => {
-                panic!("尝试查询函数的值");
-            }
+            Func(_) => Err(CompileError::semantic(
+                self.span,
+                format!("函数`{}`不能作为值使用", self.ident),
+            )),
         }
     }
 }
 
-///为全局数组生成代码
-fn gen_global_array_ir(output: &mut dyn Write, dims: &[i32], result: &Vec<i32>, now_pos: i32) {
+///为全局数组生成初值
+fn gen_global_array_ir(program: &mut Program, dims: &[i32], result: &[i32], now_pos: i32) -> Value {
     if dims.is_empty() {
         //到达叶子
-        write!(output, "{}", result[now_pos as usize]).unwrap();
+        program.new_value().integer(result[now_pos as usize])
     } else {
         //未到达叶子
         //计算增量，是dims[1..]的乘积
@@ -1190,74 +1532,33 @@ fn gen_global_array_ir(output: &mut dyn Write, dims: &[i32], result: &Vec<i32>,
         for dim in &dims[1..] {
             delta *= dim;
         }
-        write!(output, "{{").unwrap();
 
+        let mut elems = vec![];
         for i in 0..dims[0] {
-            if i != 0 {
-                write!(output, ", ").unwrap();
-            }
-            gen_global_array_ir(output, &dims[1..], result, now_pos + delta * i);
-        }
-
-        write!(output, "}}").unwrap();
-    }
-}
-
-///为局部变量数组生成代码
-fn gen_local_var_array_ir(
-    output: &mut dyn Write,
-    info: &mut GenerateIrInfo,
-    dims: &[i32],
-    result: &Vec<i32>,
-    now_pos: i32,
-    ptr: String,
-) {
-    if dims.is_empty() {
-        //到达叶子
-        if result[now_pos as usize] == 0 {
-            writeln!(output, "  store 0, {}", ptr).unwrap();
-        } else {
-            writeln!(output, "  store %{}, {}", result[now_pos as usize], ptr).unwrap();
-        }
-    } else {
-        //未到达叶子
-        //计算增量，是dims[1..]的乘积
-        let mut delta: i32 = 1;
-        for dim in &dims[1..] {
-            delta *= dim;
-        }
-
-        for i in 0..dims[0] {
-            info.now_id += 1;
-            writeln!(output, "  %{} = getelemptr {}, {}", info.now_id, ptr, i).unwrap();
-            gen_local_var_array_ir(
-                output,
-                info,
+            elems.push(gen_global_array_ir(
+                program,
                 &dims[1..],
                 result,
                 now_pos + delta * i,
-                "%".to_owned() + info.now_id.to_string().as_str(),
-            );
+            ));
         }
+        program.new_value().aggregate(elems)
     }
 }
 
 ///为局部变量数组生成代码
-fn gen_local_var_array_ir_string_ver(
-    output: &mut dyn Write,
+fn gen_local_var_array_ir(
+    program: &mut Program,
     info: &mut GenerateIrInfo,
     dims: &[i32],
-    result: &Vec<String>,
+    result: &[ExpResult],
     now_pos: i32,
-    ptr: String,
+    ptr: Value,
 ) {
     if dims.is_empty() {
         //到达叶子
-        if result[now_pos as usize] == "0".to_owned() {
-            writeln!(output, "  store 0, {}", ptr).unwrap();
-        } else {
-            writeln!(output, "  store {}, {}", result[now_pos as usize], ptr).unwrap();
-        }
+        let val = result[now_pos as usize].clone().into_value(program, info);
+        info.inst(program, |builder| builder.store(val, ptr));
     } else {
         //未到达叶子
         //计算增量，是dims[1..]的乘积
@@ -1267,15 +1568,15 @@ fn gen_local_var_array_ir_string_ver(
         }
 
         for i in 0..dims[0] {
-            info.now_id += 1;
-            writeln!(output, "  %{} = getelemptr {}, {}", info.now_id, ptr, i).unwrap();
-            gen_local_var_array_ir_string_ver(
-                output,
+            let index = info.integer(program, i);
+            let elem_ptr = info.inst(program, |builder| builder.get_elem_ptr(ptr, index));
+            gen_local_var_array_ir(
+                program,
                 info,
                 &dims[1..],
                 result,
                 now_pos + delta * i,
-                "%".to_owned() + info.now_id.to_string().as_str(),
+                elem_ptr,
             );
         }
     }
@@ -1283,16 +1584,17 @@ fn gen_local_var_array_ir_string_ver(
 
 ///为局部常量数组生成代码
 fn gen_local_const_array_ir(
-    output: &mut dyn Write,
+    program: &mut Program,
     info: &mut GenerateIrInfo,
     dims: &[i32],
-    result: &Vec<i32>,
+    result: &[i32],
     now_pos: i32,
-    ptr: String,
+    ptr: Value,
 ) {
     if dims.is_empty() {
         //到达叶子
-        writeln!(output, "  store {}, {}", result[now_pos as usize], ptr).unwrap();
+        let val = info.integer(program, result[now_pos as usize]);
+        info.inst(program, |builder| builder.store(val, ptr));
     } else {
         //未到达叶子
         //计算增量，是dims[1..]的乘积
@@ -1302,15 +1604,15 @@ fn gen_local_const_array_ir(
         }
 
         for i in 0..dims[0] {
-            info.now_id += 1;
-            writeln!(output, "  %{} = getelemptr {}, {}", info.now_id, ptr, i).unwrap();
+            let index = info.integer(program, i);
+            let elem_ptr = info.inst(program, |builder| builder.get_elem_ptr(ptr, index));
             gen_local_const_array_ir(
-                output,
+                program,
                 info,
                 &dims[1..],
                 result,
                 now_pos + delta * i,
-                "%".to_owned() + info.now_id.to_string().as_str(),
+                elem_ptr,
             );
         }
     }
@@ -0,0 +1,285 @@
+//! 基于支配树的全局值编号
+//!
+//! LVal::generate每次访问数组都重新计算整条地址，这里沿支配树先序遍历，
+//! 相同的二元运算、getelemptr和getptr只保留支配树上最先算出的一个。
+//! load只沿着没有汇合的路径复用，中间遇到可能写到同一地址的store或者有副作用的调用就作废，
+//! store之后读同一地址直接使用存进去的值
+use crate::ds_for_cfg::Cfg;
+use crate::ds_for_opt::{remove_insts, replace_uses};
+use koopa::ir::{BasicBlock, BinaryOp, Function, FunctionData, Value, ValueKind};
+use std::collections::{HashMap, HashSet};
+
+///操作数，整数常量按值比较
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Operand {
+    Const(i32),
+    Value(Value),
+}
+
+///可以复用结果的计算
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Expr {
+    Binary(BinaryOp, Operand, Operand),
+    GetElemPtr(Operand, Operand),
+    GetPtr(Operand, Operand),
+}
+
+///支配树上的先序遍历，离开时撤销子树中加入的表达式
+enum Visit {
+    Enter(BasicBlock),
+    Exit(usize),
+}
+
+///对一个函数做值编号，返回是否有改动
+pub fn gvn(func: &mut FunctionData, pure: &HashSet<Function>) -> bool {
+    let Some(entry) = func.layout().entry_bb() else {
+        return false;
+    };
+    let cfg = Cfg::new(func);
+    let children = cfg.dom_children();
+    let mut numbering = Numbering {
+        func,
+        pure,
+        exprs: HashMap::new(),
+        added: vec![],
+        replaced: HashMap::new(),
+    };
+    //每个基本块出口处可以复用的load，地址到值
+    let mut end_loads: HashMap<BasicBlock, HashMap<Value, Value>> = HashMap::new();
+    let mut stack = vec![Visit::Enter(entry)];
+    while let Some(visit) = stack.pop() {
+        match visit {
+            Visit::Enter(bb) => {
+                //只有一个前驱且就是直接支配者时，入口处的内存和前驱出口处一样
+                let idom = cfg.idom[&bb];
+                let loads = match cfg.preds[&bb][..] {
+                    [pred] if pred == idom => end_loads[&idom].clone(),
+                    _ => HashMap::new(),
+                };
+                stack.push(Visit::Exit(numbering.added.len()));
+                let loads = numbering.visit_bb(bb, loads);
+                end_loads.insert(bb, loads);
+                for &child in children.get(&bb).into_iter().flatten().rev() {
+                    stack.push(Visit::Enter(child));
+                }
+            }
+            Visit::Exit(len) => {
+                for expr in numbering.added.drain(len..) {
+                    numbering.exprs.remove(&expr);
+                }
+            }
+        }
+    }
+
+    let replaced = numbering.replaced;
+    let dead: Vec<Value> = replaced.keys().copied().collect();
+    replace_uses(func, &replaced);
+    remove_insts(func, &dead);
+    !dead.is_empty()
+}
+
+struct Numbering<'a> {
+    func: &'a FunctionData,
+    pure: &'a HashSet<Function>,
+    exprs: HashMap<Expr, Value>,     //当前基本块的支配者中算出的表达式
+    added: Vec<Expr>,                //按加入顺序记录，离开子树时撤销
+    replaced: HashMap<Value, Value>, //重复的指令换成的值
+}
+
+impl Numbering<'_> {
+    ///重复的指令已经换成的值
+    fn resolve(&self, mut value: Value) -> Value {
+        while let Some(&next) = self.replaced.get(&value) {
+            value = next;
+        }
+        value
+    }
+
+    fn operand(&self, value: Value) -> Operand {
+        let value = self.resolve(value);
+        if !value.is_global() {
+            if let ValueKind::Integer(int) = self.func.dfg().value(value).kind() {
+                return Operand::Const(int.value());
+            }
+        }
+        Operand::Value(value)
+    }
+
+    ///给基本块中的指令编号，loads是入口处可以复用的load，返回出口处的
+    fn visit_bb(
+        &mut self,
+        bb: BasicBlock,
+        mut loads: HashMap<Value, Value>,
+    ) -> HashMap<Value, Value> {
+        let func = self.func;
+        for &inst in func.layout().bbs().node(&bb).unwrap().insts().keys() {
+            let expr = match func.dfg().value(inst).kind() {
+                ValueKind::Binary(binary) => {
+                    let (lhs, rhs) = (self.operand(binary.lhs()), self.operand(binary.rhs()));
+                    //交换律成立的运算，交换操作数后也算相同
+                    if is_commutative(binary.op()) {
+                        if let Some(&value) = self.exprs.get(&Expr::Binary(binary.op(), rhs, lhs)) {
+                            self.replaced.insert(inst, value);
+                            continue;
+                        }
+                    }
+                    Expr::Binary(binary.op(), lhs, rhs)
+                }
+                ValueKind::GetElemPtr(get_elem_ptr) => Expr::GetElemPtr(
+                    self.operand(get_elem_ptr.src()),
+                    self.operand(get_elem_ptr.index()),
+                ),
+                ValueKind::GetPtr(get_ptr) => {
+                    Expr::GetPtr(self.operand(get_ptr.src()), self.operand(get_ptr.index()))
+                }
+                ValueKind::Load(load) => {
+                    let addr = self.resolve(load.src());
+                    match loads.get(&addr) {
+                        Some(&value) => {
+                            self.replaced.insert(inst, value);
+                        }
+                        None => {
+                            loads.insert(addr, inst);
+                        }
+                    }
+                    continue;
+                }
+                ValueKind::Store(store) => {
+                    let addr = self.resolve(store.dest());
+                    let access = self.access(addr);
+                    loads.retain(|&other, _| !may_alias(&access, &self.access(other)));
+                    loads.insert(addr, self.resolve(store.value()));
+                    continue;
+                }
+                ValueKind::Call(call) => {
+                    if !self.pure.contains(&call.callee()) {
+                        loads.clear();
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            match self.exprs.get(&expr) {
+                Some(&value) => {
+                    self.replaced.insert(inst, value);
+                }
+                None => {
+                    self.exprs.insert(expr, inst);
+                    self.added.push(expr);
+                }
+            }
+        }
+        loads
+    }
+
+    ///地址从哪个alloc出发，经过哪些getelemptr/getptr得到
+    fn access(&self, mut addr: Value) -> Access {
+        let mut path = vec![];
+        let root = loop {
+            if addr.is_global() {
+                break Some(addr);
+            }
+            let (is_elem, src, index) = match self.func.dfg().value(addr).kind() {
+                ValueKind::Alloc(_) => break Some(addr),
+                ValueKind::GetElemPtr(get_elem_ptr) => {
+                    (true, get_elem_ptr.src(), get_elem_ptr.index())
+                }
+                ValueKind::GetPtr(get_ptr) => (false, get_ptr.src(), get_ptr.index()),
+                //通过参数或者load得到的指针，不知道指向哪里
+                _ => break None,
+            };
+            path.push((is_elem, self.operand(index)));
+            addr = self.resolve(src);
+        };
+        path.reverse();
+        Access { root, path }
+    }
+}
+
+///地址的来源，root为None时可能指向任何地方
+struct Access {
+    root: Option<Value>,
+    path: Vec<(bool, Operand)>, //(是不是getelemptr, 下标)
+}
+
+///两个地址是否可能相同：来自不同的alloc时不同；来自同一个alloc时，
+///找到第一个不同的步骤，之前的步骤完全相同、这一步是同一种运算且下标是不同的常量，
+///之后都是getelemptr(只在这一步选出的子数组内部移动)时也不同。
+///getptr可以越过子数组的边界，比如先取第0行再后移一行和先取第1行是同一个地址
+fn may_alias(a: &Access, b: &Access) -> bool {
+    match (a.root, b.root) {
+        (Some(x), Some(y)) if x != y => false,
+        (Some(_), Some(_)) => {
+            let Some(k) = a.path.iter().zip(&b.path).position(|(p, q)| p != q) else {
+                return true;
+            };
+            let disjoint = match (a.path[k], b.path[k]) {
+                ((p, Operand::Const(i)), (q, Operand::Const(j))) => p == q && i != j,
+                _ => false,
+            };
+            let inside = |path: &[(bool, Operand)]| path[k + 1..].iter().all(|step| step.0);
+            !(disjoint && inside(&a.path) && inside(&b.path))
+        }
+        _ => true,
+    }
+}
+
+fn is_commutative(op: BinaryOp) -> bool {
+    matches!(
+        op,
+        BinaryOp::Add
+            | BinaryOp::Mul
+            | BinaryOp::And
+            | BinaryOp::Or
+            | BinaryOp::Xor
+            | BinaryOp::Eq
+            | BinaryOp::NotEq
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koopa::ir::builder_traits::*;
+    use koopa::ir::Type;
+
+    ///从root出发，每一步的下标都是常量
+    fn access(root: Value, path: &[(bool, i32)]) -> Access {
+        let path = path
+            .iter()
+            .map(|&(is_elem, index)| (is_elem, Operand::Const(index)))
+            .collect();
+        Access {
+            root: Some(root),
+            path,
+        }
+    }
+
+    #[test]
+    fn constant_indices_separate_subarrays() {
+        let mut func = FunctionData::new("@f".to_string(), vec![], Type::get_unit());
+        let ty = Type::get_array(Type::get_array(Type::get_i32(), 2), 2);
+        let root = func.dfg_mut().new_value().alloc(ty);
+        let a01 = access(root, &[(true, 0), (true, 1)]);
+        let a10 = access(root, &[(true, 1), (true, 0)]);
+        let a11 = access(root, &[(true, 1), (true, 1)]);
+        assert!(!may_alias(&a01, &a11));
+        assert!(!may_alias(&a10, &a11));
+        assert!(may_alias(&a11, &a11));
+    }
+
+    #[test]
+    fn getptr_may_cross_subarrays() {
+        let mut func = FunctionData::new("@f".to_string(), vec![], Type::get_unit());
+        let ty = Type::get_array(Type::get_array(Type::get_i32(), 2), 2);
+        let root = func.dfg_mut().new_value().alloc(ty);
+        //先取第0行再后移一行，和先取第1行是同一个地址
+        let a = access(root, &[(true, 0), (false, 1)]);
+        let b = access(root, &[(true, 1), (false, 0)]);
+        assert!(may_alias(&a, &b));
+        //相同的前缀之后getptr的下标不同，不会是同一个地址
+        let a = access(root, &[(true, 0), (false, 1)]);
+        let b = access(root, &[(true, 0), (false, 0)]);
+        assert!(!may_alias(&a, &b));
+    }
+}
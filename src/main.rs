@@ -3,95 +3,241 @@
 mod debug_macros;
 
 pub mod ast;
+pub mod calc_exp;
+pub mod diagnostics;
 pub mod ds_for_ir;
+pub mod error;
+mod preprocess;
 pub mod symbol_table;
 
+mod dce;
 mod ds_for_asm;
+mod ds_for_cfg;
+mod ds_for_opt;
 #[cfg(feature = "generate-asm")]
 mod gen_asm;
+mod gvn;
+mod mem2reg;
+mod optimize;
+mod sccp;
+mod simplify_cfg;
+mod verify_ir;
 #[cfg(feature = "generate-asm")]
 use gen_asm::GenerateAsm;
 
 #[cfg(feature = "generate-ir")]
 mod array_solve;
 #[cfg(feature = "generate-ir")]
-pub mod calc_exp;
+mod ds_for_check;
 #[cfg(feature = "generate-ir")]
 mod gen_ir;
 #[cfg(feature = "generate-ir")]
+mod runtime;
+#[cfg(feature = "generate-ir")]
+mod semantic_check;
+#[cfg(feature = "generate-ir")]
 use gen_ir::GenerateIR;
 
+use diagnostics::{Diagnostic, SourceFile};
+use error::{CompileError, Result};
 use lalrpop_util::lalrpop_mod;
+use preprocess::Preprocessor;
 use std::env::args;
-use std::fs::read_to_string;
 use std::fs::File;
-use std::io::Result;
-use std::io::Write;
+use std::process::exit;
+use symbol_table::FuncInfoBase;
 
 // 引用 lalrpop 生成的解析器
 // 因为我们刚刚创建了 sysy.lalrpop, 所以模块名是 sysy
-lalrpop_mod!(sysy);
+lalrpop_mod!(
+    #[allow(clippy::all)]
+    sysy
+);
 
-fn main() -> Result<()> {
+fn main() {
     // 解析命令行参数
     let mut args = args();
     args.next();
-    let mode = args.next().unwrap();
-    let input = args.next().unwrap();
-    args.next();
-    let output = args.next().unwrap();
+    let options = match Options::parse(args.collect()) {
+        Some(options) => options,
+        None => {
+            eprintln!(
+                "usage: compiler (-koopa | -riscv | -perf) <input> -o <output> [-Werror] [-I <dir>] [--runtime-header <file>] [--verify]"
+            );
+            exit(2);
+        }
+    };
 
-    // 读取输入文件
-    let input = read_to_string(input)?;
+    // 读取输入文件并预处理
+    let preprocessor = Preprocessor::new(options.include_paths.clone());
+    let source = match preprocessor.run_file(&options.input) {
+        Ok(source) => source,
+        Err(err) => {
+            eprint!("{}", err);
+            exit(1);
+        }
+    };
 
+    #[cfg(feature = "generate-ir")]
+    let runtime = load_runtime(&options);
+    #[cfg(not(feature = "generate-ir"))]
+    let runtime = vec![];
+    if let Err(err) = compile(&options, &source, &runtime) {
+        eprint!("{}", err.render(&source));
+        exit(1);
+    }
+}
+
+///命令行参数
+struct Options {
+    mode: String,
+    input: String,
+    output: String,
+    werror: bool,                   //-Werror，把警告当作错误
+    runtime_header: Option<String>, //--runtime-header，运行时库的头文件
+    include_paths: Vec<String>,     //-I，查找#include文件的目录
+    verify: bool,                   //--verify，生成IR后检查IR是否合法
+}
+
+impl Options {
+    ///解析命令行参数，格式不对时返回None
+    fn parse(args: Vec<String>) -> Option<Self> {
+        let mut werror = false;
+        let mut verify = false;
+        let mut runtime_header = None;
+        let mut include_paths = vec![];
+        let mut positional = vec![];
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-Werror" => werror = true,
+                "--verify" => verify = true,
+                "--runtime-header" => runtime_header = Some(args.next()?),
+                "-I" => include_paths.push(args.next()?),
+                _ if arg.len() > 2 && arg.starts_with("-I") => {
+                    include_paths.push(arg[2..].to_string())
+                }
+                _ => positional.push(arg),
+            }
+        }
+        match positional.as_slice() {
+            [mode, input, _, output] if matches!(mode.as_str(), "-koopa" | "-riscv" | "-perf") => {
+                Some(Options {
+                    mode: mode.clone(),
+                    input: input.clone(),
+                    output: output.clone(),
+                    werror,
+                    runtime_header,
+                    include_paths,
+                    verify,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "generate-ir")]
+///读取运行时库的头文件并取出其中的函数，没有指定时使用内置的默认头文件
+///头文件有错误时按头文件的位置报告并退出
+fn load_runtime(options: &Options) -> Vec<(String, FuncInfoBase)> {
+    let preprocessor = Preprocessor::new(options.include_paths.clone());
+    let header = match &options.runtime_header {
+        Some(path) => preprocessor.run_file(path),
+        None => preprocessor.run_source("sylib.h", runtime::DEFAULT_HEADER),
+    };
+    let header = match header {
+        Ok(header) => header,
+        Err(err) => {
+            eprint!("{}", err);
+            exit(1);
+        }
+    };
+    match parse(&header).and_then(|ast| runtime::lib_funcs(&ast)) {
+        Ok(funcs) => funcs,
+        Err(err) => {
+            eprint!("{}", err.render(&header));
+            exit(1);
+        }
+    }
+}
+
+///解析源文件得到AST，报告所有语法错误
+fn parse(source: &SourceFile) -> Result<ast::CompUnit> {
     // 调用 lalrpop 生成的 parser 解析输入文件
-    let ast = sysy::CompUnitParser::new().parse(&input).unwrap();
+    // 可以恢复的语法错误收集在errors中，全部报告出来
+    let mut errors = vec![];
+    let result = sysy::CompUnitParser::new().parse(&mut errors, &source.content);
+    let mut diags: Vec<Diagnostic> = errors
+        .iter()
+        .map(|recovery| Diagnostic::from_parse_error(&recovery.error, source))
+        .collect();
+    match result {
+        Ok(ast) if diags.is_empty() => Ok(ast),
+        Ok(_) => Err(CompileError::Syntax(diags)),
+        Err(err) => {
+            diags.push(Diagnostic::from_parse_error(&err, source));
+            Err(CompileError::Syntax(diags))
+        }
+    }
+}
+
+///解析源文件并按mode输出IR或汇编
+fn compile(
+    options: &Options,
+    source: &SourceFile,
+    runtime: &[(String, FuncInfoBase)],
+) -> Result<()> {
+    let mode = options.mode.as_str();
+    let ast = parse(source)?;
 
     // 输出解析得到的 AST
     //let my_koppa_ir = format!("{}", ast);
 
-    let mut output_file = File::create(output)?;
+    // 生成IR之前先做语义检查，一次性报告所有错误
+    // 没有错误时警告输出到stderr，编译继续
+    #[cfg(feature = "generate-ir")]
+    for warning in semantic_check::check_program(&ast, options.werror, runtime)? {
+        eprint!("{}", warning.render(source));
+    }
+
+    let mut output_file = File::create(&options.output)?;
     koopa::ir::types::Type::set_ptr_size(4); //TODO 设置指针大小
 
-    match mode.as_str() {
-        "-koopa" => {
-            let mut info = ds_for_ir::GenerateIrInfo::new();
+    #[cfg(feature = "print-AST")]
+    println!("{:#?}", ast);
 
-            #[cfg(feature = "print-AST")]
-            println!("{:#?}", ast);
+    // 在内存中生成IR，之后按mode输出文本形式的IR或者汇编
+    let mut info = ds_for_ir::GenerateIrInfo::new();
+    info.runtime = runtime.to_vec();
+    info.line_table = source.line_table();
+    let mut program = koopa::ir::Program::new();
+    #[cfg(feature = "generate-ir")]
+    ast.generate(&mut program, &mut info)?;
+    // debug构建总是检查IR，release构建只在--verify时检查
+    // 优化前后各检查一次，出错时能分清是生成IR还是优化遍的问题
+    let verify = options.verify || cfg!(debug_assertions);
+    if verify {
+        verify_ir::verify_program(&program)?;
+    }
+    optimize::optimize(&mut program);
+    if verify {
+        verify_ir::verify_program(&program)?;
+    }
 
-            #[cfg(feature = "generate-ir")]
-            {
-                ast.generate(&mut output_file, &mut info);
-            }
-        }
-        "-riscv" => {
-            #[cfg(feature = "generate-asm")]
-            {
-                let mut info = ds_for_ir::GenerateIrInfo::new();
-                let mut tmp_ir = Vec::new();
-                ast.generate(&mut tmp_ir, &mut info);
-                let my_koppa_ir = String::from_utf8(tmp_ir).unwrap();
-                println!("{}", my_koppa_ir);
-                let driver = koopa::front::Driver::from(my_koppa_ir);
-                let program = driver.generate_program().unwrap();
-                program.generate(&mut output_file, &program);
-            }
+    match mode {
+        "-koopa" => {
+            koopa::back::KoopaGenerator::new(output_file).generate_on(&program)?;
         }
-        "-perf" => {
+        "-riscv" | "-perf" => {
             #[cfg(feature = "generate-asm")]
             {
-                let mut info = ds_for_ir::GenerateIrInfo::new();
-                let mut tmp_ir = Vec::new();
-                ast.generate(&mut tmp_ir, &mut info);
-                let my_koppa_ir = String::from_utf8(tmp_ir).unwrap();
-                let driver = koopa::front::Driver::from(my_koppa_ir);
-                let program = driver.generate_program().unwrap();
-                program.generate(&mut output_file, &program);
+                let abi = info.float_abi_table();
+                program.generate(&mut output_file, &program, &abi)?;
             }
         }
         _ => {
-            panic!("Unknown mode: {}", mode);
+            return Err(CompileError::internal(format!("Unknown mode: {}", mode)));
         }
     }
     Ok(())
@@ -0,0 +1,204 @@
+//! 把只通过load/store访问的局部变量提升为SSA值
+//!
+//! 标量局部变量、函数参数的备份和&&/||的临时结果都放在alloc出的内存里。
+//! 这里在支配边界上放置基本块参数代替phi(只放在变量活跃的地方)，再按逆后序沿支配树重命名，
+//! load换成当时的值，store和alloc删掉，跳转时把变量的值作为实参传给基本块参数
+use crate::ds_for_cfg::{successors, terminator, Cfg};
+use crate::ds_for_opt::{add_bb_params, insts, remove_insts, replace_uses, rewrite_edges};
+use koopa::ir::builder_traits::*;
+use koopa::ir::{BasicBlock, FunctionData, Type, Value, ValueKind};
+use std::collections::{HashMap, HashSet};
+
+///每个基本块新加的参数，(被提升的alloc, 对应的参数)
+type BlockParams = HashMap<BasicBlock, Vec<(Value, Value)>>;
+
+///提升一个函数中所有能提升的alloc，返回是否有改动
+pub fn mem2reg(func: &mut FunctionData) -> bool {
+    let cfg = Cfg::new(func);
+    let allocs = promotable_allocs(func, &cfg);
+    if allocs.is_empty() {
+        return false;
+    }
+    let params = place_params(func, &cfg, &allocs);
+    rename(func, &cfg, &allocs, &params);
+    true
+}
+
+///能提升的alloc：类型是*i32，只在可达的基本块中被load，或者作为store的目标
+fn promotable_allocs(func: &FunctionData, cfg: &Cfg) -> Vec<Value> {
+    let i32_ptr = Type::get_pointer(Type::get_i32());
+    let mut allocs = vec![];
+    let mut rejected = HashSet::new();
+    for (bb, inst) in insts(func) {
+        let data = func.dfg().value(inst);
+        match data.kind() {
+            ValueKind::Alloc(_) if *data.ty() == i32_ptr => allocs.push(inst),
+            ValueKind::Load(load) => {
+                if !cfg.is_reachable(bb) {
+                    rejected.insert(load.src());
+                }
+            }
+            ValueKind::Store(store) => {
+                //地址被存到内存里就可能通过别的途径访问
+                rejected.insert(store.value());
+                if !cfg.is_reachable(bb) {
+                    rejected.insert(store.dest());
+                }
+            }
+            kind => rejected.extend(kind.value_uses()),
+        }
+    }
+    allocs.retain(|alloc| !rejected.contains(alloc));
+    allocs
+}
+
+///按迭代支配边界给基本块加参数，只加在变量在块入口活跃的基本块上
+fn place_params(func: &mut FunctionData, cfg: &Cfg, allocs: &[Value]) -> BlockParams {
+    let frontiers = cfg.dominance_frontiers();
+    //每个alloc被store的基本块，和在store之前被load的基本块
+    let mut def_bbs: HashMap<Value, HashSet<BasicBlock>> = HashMap::new();
+    let mut use_bbs: HashMap<Value, HashSet<BasicBlock>> = HashMap::new();
+    for &bb in &cfg.rpo {
+        let mut stored = HashSet::new();
+        for &inst in func.layout().bbs().node(&bb).unwrap().insts().keys() {
+            match func.dfg().value(inst).kind() {
+                ValueKind::Load(load) if !stored.contains(&load.src()) => {
+                    use_bbs.entry(load.src()).or_default().insert(bb);
+                }
+                ValueKind::Store(store) => {
+                    stored.insert(store.dest());
+                    def_bbs.entry(store.dest()).or_default().insert(bb);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut placed: HashMap<BasicBlock, Vec<Value>> = HashMap::new();
+    let empty = HashSet::new();
+    for &alloc in allocs {
+        let defs = def_bbs.get(&alloc).unwrap_or(&empty);
+        //从使用处沿前驱往回找，直到遇到store，经过的基本块入口处变量活跃
+        let mut live_in = HashSet::new();
+        let mut worklist: Vec<BasicBlock> = use_bbs
+            .get(&alloc)
+            .unwrap_or(&empty)
+            .iter()
+            .copied()
+            .collect();
+        while let Some(bb) = worklist.pop() {
+            if !live_in.insert(bb) {
+                continue;
+            }
+            for &pred in &cfg.preds[&bb] {
+                if !defs.contains(&pred) && !live_in.contains(&pred) {
+                    worklist.push(pred);
+                }
+            }
+        }
+
+        //迭代支配边界，新加的参数也算一次定义
+        let mut has_param = HashSet::new();
+        let mut worklist: Vec<BasicBlock> = defs.iter().copied().collect();
+        while let Some(bb) = worklist.pop() {
+            for &frontier in frontiers.get(&bb).into_iter().flatten() {
+                if live_in.contains(&frontier) && has_param.insert(frontier) {
+                    placed.entry(frontier).or_default().push(alloc);
+                    worklist.push(frontier);
+                }
+            }
+        }
+    }
+
+    let mut params = BlockParams::new();
+    for (bb, bb_allocs) in placed {
+        let tys = vec![Type::get_i32(); bb_allocs.len()];
+        let new_params = add_bb_params(func, bb, tys);
+        params.insert(bb, bb_allocs.into_iter().zip(new_params).collect());
+    }
+    params
+}
+
+///按逆后序重命名，每个基本块入口处变量的值来自直接支配者的出口或者基本块参数
+fn rename(func: &mut FunctionData, cfg: &Cfg, allocs: &[Value], params: &BlockParams) {
+    let allocs: HashSet<Value> = allocs.iter().copied().collect();
+    let mut end_values: HashMap<BasicBlock, HashMap<Value, Value>> = HashMap::new();
+    let mut replaced: HashMap<Value, Value> = HashMap::new(); //load换成的值
+    let mut dead: Vec<Value> = allocs.iter().copied().collect();
+    //没有store就被load时的值
+    let mut undef = None;
+    for &bb in &cfg.rpo {
+        let mut values = match cfg.idom[&bb] {
+            idom if idom == bb => HashMap::new(),
+            idom => end_values[&idom].clone(),
+        };
+        for &(alloc, param) in params.get(&bb).into_iter().flatten() {
+            values.insert(alloc, param);
+        }
+        let bb_insts: Vec<Value> = func
+            .layout()
+            .bbs()
+            .node(&bb)
+            .unwrap()
+            .insts()
+            .keys()
+            .copied()
+            .collect();
+        for inst in bb_insts {
+            match func.dfg().value(inst).kind().clone() {
+                ValueKind::Load(load) if allocs.contains(&load.src()) => {
+                    let value = match values.get(&load.src()) {
+                        Some(&value) => value,
+                        None => *undef.get_or_insert_with(|| {
+                            func.dfg_mut().new_value().undef(Type::get_i32())
+                        }),
+                    };
+                    replaced.insert(inst, value);
+                    dead.push(inst);
+                }
+                ValueKind::Store(store) if allocs.contains(&store.dest()) => {
+                    let mut value = store.value();
+                    while let Some(&next) = replaced.get(&value) {
+                        value = next;
+                    }
+                    values.insert(store.dest(), value);
+                    dead.push(inst);
+                }
+                _ => {}
+            }
+        }
+        end_values.insert(bb, values);
+    }
+
+    //跳到有新参数的基本块时，把出口处变量的值作为实参
+    for &bb in &cfg.rpo {
+        let mut extra_args: HashMap<BasicBlock, Vec<Value>> = HashMap::new();
+        for succ in successors(func, bb) {
+            let Some(succ_params) = params.get(&succ) else {
+                continue;
+            };
+            let args = succ_params
+                .iter()
+                .map(|(alloc, _)| match end_values[&bb].get(alloc) {
+                    Some(&value) => value,
+                    None => *undef
+                        .get_or_insert_with(|| func.dfg_mut().new_value().undef(Type::get_i32())),
+                })
+                .collect();
+            extra_args.insert(succ, args);
+        }
+        if extra_args.is_empty() {
+            continue;
+        }
+        let inst = terminator(func, bb).unwrap();
+        rewrite_edges(func, inst, |target, mut args| {
+            if let Some(extra) = extra_args.get(&target) {
+                args.extend(extra.iter().copied());
+            }
+            (target, args)
+        });
+    }
+
+    replace_uses(func, &replaced);
+    remove_insts(func, &dead);
+}
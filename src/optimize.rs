@@ -0,0 +1,257 @@
+//! 对内存中的IR做的优化
+//!
+//! 每个优化遍以一个函数为单位，生成IR之后、输出IR或汇编之前按顺序执行
+use crate::dce::{dce, pure_funcs};
+use crate::ds_for_cfg::terminator;
+use crate::ds_for_opt::rewrite_edges;
+use crate::gvn::gvn;
+use crate::mem2reg::mem2reg;
+use crate::sccp::sccp;
+use crate::simplify_cfg::simplify_cfg;
+use koopa::ir::builder_traits::*;
+use koopa::ir::{BasicBlock, BinaryOp, FunctionData, Program, Value, ValueKind};
+
+///依次对每个有定义的函数执行各个优化遍
+pub fn optimize(program: &mut Program) {
+    let pure = pure_funcs(program);
+    let funcs: Vec<_> = program.func_layout().to_vec();
+    for func in funcs {
+        let func_data = program.func_mut(func);
+        //跳过声明
+        if func_data.layout().entry_bb().is_none() {
+            continue;
+        }
+        simplify_cfg(func_data);
+        if mem2reg(func_data) {
+            simplify_cfg(func_data);
+        }
+        if sccp(func_data) {
+            simplify_cfg(func_data);
+        }
+        gvn(func_data, &pure);
+        if dce(func_data, &pure) {
+            simplify_cfg(func_data);
+        }
+        copy_block_args(func_data);
+    }
+}
+
+///跳转的实参是目标基本块中排在前面的参数时(比如循环中交换两个变量)，先用add x, 0复制一份再传入
+///koopa的解释器按顺序给基本块参数赋值，不复制的话这个实参会读到已经被覆盖的值
+fn copy_block_args(func: &mut FunctionData) {
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+    for bb in bbs {
+        let Some(inst) = terminator(func, bb) else {
+            continue;
+        };
+        let edges: Vec<(BasicBlock, Vec<Value>)> = match func.dfg().value(inst).kind() {
+            ValueKind::Branch(branch) => vec![
+                (branch.true_bb(), branch.true_args().to_vec()),
+                (branch.false_bb(), branch.false_args().to_vec()),
+            ],
+            ValueKind::Jump(jump) => vec![(jump.target(), jump.args().to_vec())],
+            _ => continue,
+        };
+        let mut changed = false;
+        let mut new_edges = vec![];
+        for (target, mut args) in edges {
+            let params = func.dfg().bb(target).params().to_vec();
+            for (i, arg) in args.iter_mut().enumerate() {
+                if !params[..i].contains(arg) {
+                    continue;
+                }
+                let zero = func.dfg_mut().new_value().integer(0);
+                let copy = func.dfg_mut().new_value().binary(BinaryOp::Add, *arg, zero);
+                func.layout_mut()
+                    .bb_mut(bb)
+                    .insts_mut()
+                    .cursor_mut(inst)
+                    .insert_key_before(copy)
+                    .unwrap();
+                *arg = copy;
+                changed = true;
+            }
+            new_edges.push((target, args));
+        }
+        if changed {
+            let mut new_edges = new_edges.into_iter();
+            rewrite_edges(func, inst, |_, _| new_edges.next().unwrap());
+        }
+    }
+}
+
+#[cfg(all(test, feature = "generate-ir"))]
+mod tests {
+    use super::*;
+    use crate::ds_for_asm::find_jump_tables;
+    use crate::ds_for_ir::GenerateIrInfo;
+    use crate::ds_for_opt::insts;
+    use crate::gen_ir::GenerateIR;
+    use crate::verify_ir::verify_program;
+    use koopa::ir::Type;
+    use std::collections::HashMap;
+
+    ///生成IR并执行所有优化遍，优化后的IR必须合法
+    fn optimized(source: &str) -> Program {
+        Type::set_ptr_size(4);
+        let mut errors = vec![];
+        let ast = crate::sysy::CompUnitParser::new()
+            .parse(&mut errors, source)
+            .unwrap();
+        let mut program = Program::new();
+        ast.generate(&mut program, &mut GenerateIrInfo::new())
+            .unwrap();
+        optimize(&mut program);
+        verify_program(&program).unwrap();
+        program
+    }
+
+    fn main_func(program: &Program) -> &FunctionData {
+        program
+            .funcs()
+            .values()
+            .find(|func| func.name() == "@main")
+            .unwrap()
+    }
+
+    ///main返回的常量，返回的不是常量时为None
+    fn main_ret(program: &Program) -> Option<i32> {
+        let main = main_func(program);
+        insts(main)
+            .into_iter()
+            .find_map(|(_, inst)| match main.dfg().value(inst).kind() {
+                ValueKind::Return(ret) => match main.dfg().value(ret.value()?).kind() {
+                    ValueKind::Integer(int) => Some(int.value()),
+                    _ => None,
+                },
+                _ => None,
+            })
+    }
+
+    ///main中满足条件的指令个数
+    fn count_insts(program: &Program, pred: impl Fn(&ValueKind) -> bool) -> usize {
+        let main = main_func(program);
+        insts(main)
+            .into_iter()
+            .filter(|&(_, inst)| pred(main.dfg().value(inst).kind()))
+            .count()
+    }
+
+    ///main中op运算的个数
+    fn count_binary(program: &Program, op: BinaryOp) -> usize {
+        count_insts(
+            program,
+            |kind| matches!(kind, ValueKind::Binary(binary) if binary.op() == op),
+        )
+    }
+
+    ///所有函数中基本块参数的个数
+    fn bb_params(program: &Program) -> usize {
+        program
+            .funcs()
+            .values()
+            .flat_map(|func| {
+                let bbs = func.layout().bbs().keys();
+                bbs.map(|&bb| func.dfg().bb(bb).params().len())
+            })
+            .sum()
+    }
+
+    #[test]
+    fn unused_loop_value_is_removed() {
+        let program = optimized(
+            "int main() { int s = 0; int i = 0; while (i < 10) { s = s + i; i = i + 1; } return i; }",
+        );
+        assert_eq!(bb_params(&program), 1);
+    }
+
+    #[test]
+    fn unused_loop_values_using_each_other_are_removed() {
+        let program = optimized(
+            "int main() {
+                int a = 0; int b = 1; int i = 0;
+                while (i < 10) { int t = a; a = b; b = t + b; i = i + 1; }
+                return i;
+            }",
+        );
+        assert_eq!(bb_params(&program), 1);
+    }
+
+    #[test]
+    fn constants_fold_like_the_frontend() {
+        let program =
+            optimized("int main() { int m = -2147483647 - 1; int n = -1; return m / n + m % n; }");
+        assert_eq!(main_ret(&program), Some(i32::MIN));
+    }
+
+    #[test]
+    fn division_by_zero_is_not_folded() {
+        let program = optimized("int main() { int z = 0; int x = 5; return x / z + x % z; }");
+        assert_eq!(main_ret(&program), None);
+        assert_eq!(count_binary(&program, BinaryOp::Div), 1);
+        assert_eq!(count_binary(&program, BinaryOp::Mod), 1);
+    }
+
+    #[test]
+    fn repeated_addresses_and_loads_are_reused() {
+        let program = optimized(
+            "int g[4][4]; int n;
+            int main() { g[n][n] = 3; return g[n][n] + g[n][n]; }",
+        );
+        let geps = count_insts(&program, |kind| matches!(kind, ValueKind::GetElemPtr(_)));
+        let loads = count_insts(&program, |kind| matches!(kind, ValueKind::Load(_)));
+        //n只读一次，g[n][n]的地址只算一次，读g[n][n]直接使用存入的3
+        assert_eq!(geps, 2);
+        assert_eq!(loads, 1);
+    }
+
+    ///main中可以换成跳转表的比较链个数，链中的比较换成跳转表后不再生成，不能有别的使用
+    fn jump_tables(program: &Program) -> usize {
+        let main = main_func(program);
+        let (tables, merged) = find_jump_tables(main);
+        let mut uses: HashMap<Value, usize> = HashMap::new();
+        for (_, inst) in insts(main) {
+            for value in main.dfg().value(inst).kind().value_uses() {
+                *uses.entry(value).or_default() += 1;
+            }
+        }
+        for bb in tables.keys().chain(&merged) {
+            let node = main.layout().bbs().node(bb).unwrap();
+            let cmp = *node.insts().front_key().unwrap();
+            assert_eq!(uses[&cmp], 1);
+        }
+        tables.len()
+    }
+
+    #[test]
+    fn dense_switch_becomes_jump_table() {
+        let program = optimized(
+            "int n; int g;
+            int main() {
+                switch (n) {
+                    case 0: g = 10; break; case 1: g = 11; break; case 2: g = 12; break;
+                    case 3: g = 13; break; case 4: g = 14; break; case 5: g = 15; break;
+                }
+                return g;
+            }",
+        );
+        assert_eq!(jump_tables(&program), 1);
+    }
+
+    #[test]
+    fn compare_used_elsewhere_stays_out_of_jump_table() {
+        //default中的n == 2被GVN换成了链中case 2的比较结果
+        let program = optimized(
+            "int n; int g;
+            int main() {
+                switch (n) {
+                    case 0: g = 10; break; case 1: g = 11; break; case 2: g = 12; break;
+                    case 3: g = 13; break; case 4: g = 14; break; case 5: g = 15; break;
+                    default: g = n == 2;
+                }
+                return g;
+            }",
+        );
+        jump_tables(&program);
+    }
+}
@@ -0,0 +1,459 @@
+//! 预处理
+//!
+//! 在语法分析之前处理`#include "..."`、`#define`(包括带参数的宏)、`#undef`、
+//! `#ifdef/#ifndef/#else/#endif`和`__LINE__`
+//!
+//! 注释先被去掉，以`\`结尾的行和下一行拼接。输出的每一行都记录了它来自哪个文件的哪一行，
+//! 诊断信息据此指回原始文件。预处理的错误没有对应的输出位置，直接在信息中写明`文件:行`
+use crate::diagnostics::{Diagnostic, Level, LineOrigin, SourceFile};
+use crate::error::{CompileError, Result};
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::Path;
+
+///#include的最大嵌套深度，超过时认为头文件互相包含
+const MAX_INCLUDE_DEPTH: usize = 64;
+
+///宏定义，params为None时是不带参数的宏
+#[derive(Debug)]
+struct Macro {
+    params: Option<Vec<String>>,
+    body: String,
+}
+
+///#ifdef等条件编译块的状态
+#[derive(Debug)]
+struct Conditional {
+    parent_active: bool, //外层是否在编译
+    active: bool,        //当前分支是否在编译
+    seen_else: bool,     //是否已经遇到#else
+    line: usize,         //开始的行号，缺少#endif时报告
+}
+
+///预处理器，宏定义在所有文件间共享
+pub struct Preprocessor {
+    include_paths: Vec<String>,
+    macros: HashMap<String, Macro>,
+    files: Vec<String>,
+    content: String,
+    origins: Vec<LineOrigin>,
+}
+
+impl Preprocessor {
+    pub fn new(include_paths: Vec<String>) -> Self {
+        Preprocessor {
+            include_paths,
+            macros: HashMap::new(),
+            files: vec![],
+            content: String::new(),
+            origins: vec![],
+        }
+    }
+
+    ///预处理path指向的文件
+    pub fn run_file(self, path: &str) -> Result<SourceFile> {
+        let text = read_to_string(path)?;
+        self.run_source(path, &text)
+    }
+
+    ///预处理内容为text的文件，name用于诊断信息和查找#include的相对路径
+    pub fn run_source(mut self, name: &str, text: &str) -> Result<SourceFile> {
+        self.process(name, text, 0)?;
+        Ok(SourceFile::with_origins(
+            name.to_string(),
+            self.content,
+            self.files,
+            self.origins,
+        ))
+    }
+
+    ///处理一个文件，depth为#include的嵌套深度
+    fn process(&mut self, name: &str, text: &str, depth: usize) -> Result<()> {
+        let file = self.files.len();
+        self.files.push(name.to_string());
+        let mut conditionals: Vec<Conditional> = vec![];
+        for (line, text) in logical_lines(&strip_comments(text)) {
+            let fail = |message: String| error(name, line, message);
+            let active = conditionals.last().is_none_or(|cond| cond.active);
+            let Some(directive) = text.trim_start().strip_prefix('#') else {
+                if active {
+                    let expanded = self.expand(&text, line, &mut vec![]).map_err(fail)?;
+                    self.content += &expanded;
+                    self.content.push('\n');
+                    self.origins.push(LineOrigin { file, line });
+                }
+                continue;
+            };
+            let directive = directive.trim();
+            let (keyword, rest) = split_ident(directive);
+            match keyword {
+                "ifdef" | "ifndef" => {
+                    let (ident, _) = split_ident(rest.trim_start());
+                    if ident.is_empty() {
+                        return Err(fail(format!("#{}后缺少宏名", keyword)));
+                    }
+                    let defined = self.macros.contains_key(ident);
+                    conditionals.push(Conditional {
+                        parent_active: active,
+                        active: active && defined == (keyword == "ifdef"),
+                        seen_else: false,
+                        line,
+                    });
+                }
+                "else" => match conditionals.last_mut() {
+                    Some(cond) if !cond.seen_else => {
+                        cond.seen_else = true;
+                        cond.active = cond.parent_active && !cond.active;
+                    }
+                    Some(_) => return Err(fail("多余的#else".to_string())),
+                    None => return Err(fail("#else没有对应的#ifdef".to_string())),
+                },
+                "endif" => {
+                    if conditionals.pop().is_none() {
+                        return Err(fail("#endif没有对应的#ifdef".to_string()));
+                    }
+                }
+                //不编译的部分只需要跟踪条件编译的嵌套
+                _ if !active => {}
+                "" => {} //只有#的空指令
+                "define" => {
+                    let (ident, macro_def) = parse_define(rest).map_err(fail)?;
+                    self.macros.insert(ident, macro_def);
+                }
+                "undef" => {
+                    let (ident, _) = split_ident(rest.trim_start());
+                    self.macros.remove(ident);
+                }
+                "include" => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(fail("#include嵌套过深".to_string()));
+                    }
+                    let path = self.find_include(name, rest.trim()).map_err(fail)?;
+                    let included = read_to_string(&path)
+                        .map_err(|err| fail(format!("无法读取头文件`{}`: {}", path, err)))?;
+                    self.process(&path, &included, depth + 1)?;
+                }
+                _ => return Err(fail(format!("未知的预处理指令`#{}`", keyword))),
+            }
+        }
+        match conditionals.last() {
+            Some(cond) => Err(error(name, cond.line, "缺少#endif".to_string())),
+            None => Ok(()),
+        }
+    }
+
+    ///找到#include的文件，引号形式先在当前文件所在的目录中找，再依次在-I指定的目录中找
+    fn find_include(&self, current: &str, spec: &str) -> std::result::Result<String, String> {
+        let (name, local) = if let Some(name) = spec.strip_prefix('"') {
+            (name.strip_suffix('"'), true)
+        } else if let Some(name) = spec.strip_prefix('<') {
+            (name.strip_suffix('>'), false)
+        } else {
+            (None, false)
+        };
+        let Some(name) = name.filter(|name| !name.is_empty()) else {
+            return Err("#include后应为\"文件名\"或<文件名>".to_string());
+        };
+        let current_dir = Path::new(current).parent().unwrap_or(Path::new(""));
+        let local_dir = local.then_some(current_dir);
+        let include_dirs = self.include_paths.iter().map(Path::new);
+        for dir in local_dir.into_iter().chain(include_dirs) {
+            let path = dir.join(name);
+            if path.is_file() {
+                return Ok(path.to_string_lossy().into_owned());
+            }
+        }
+        Err(format!("找不到头文件`{}`", name))
+    }
+
+    ///展开一行中的宏，disabled为正在展开的宏，展开结果中再出现时不再展开
+    fn expand(
+        &self,
+        text: &str,
+        line: usize,
+        disabled: &mut Vec<String>,
+    ) -> std::result::Result<String, String> {
+        let bytes = text.as_bytes();
+        let mut result = String::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            let c = bytes[i];
+            if c == b'"' {
+                let end = string_end(bytes, i);
+                result += &text[i..end];
+                i = end;
+            } else if c.is_ascii_digit()
+                || (c == b'.' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit))
+            {
+                let end = number_end(bytes, i);
+                result += &text[i..end];
+                i = end;
+            } else if is_ident_start(c) {
+                let (ident, _) = split_ident(&text[i..]);
+                i += ident.len();
+                if ident == "__LINE__" {
+                    result += &line.to_string();
+                    continue;
+                }
+                let macro_def = match self.macros.get(ident) {
+                    Some(macro_def) if !disabled.iter().any(|name| name == ident) => macro_def,
+                    _ => {
+                        result += ident;
+                        continue;
+                    }
+                };
+                let body = match &macro_def.params {
+                    None => macro_def.body.clone(),
+                    Some(params) => {
+                        //带参数的宏后面没有括号时不展开
+                        let open = i + (text[i..].len() - text[i..].trim_start().len());
+                        if bytes.get(open) != Some(&b'(') {
+                            result += ident;
+                            continue;
+                        }
+                        let (mut args, end) = split_args(text, open)
+                            .ok_or_else(|| format!("宏`{}`的实参缺少`)`", ident))?;
+                        i = end;
+                        if params.is_empty() && args.len() == 1 && args[0].trim().is_empty() {
+                            args.clear();
+                        }
+                        if args.len() != params.len() {
+                            return Err(format!(
+                                "宏`{}`需要{}个参数，实际传入了{}个",
+                                ident,
+                                params.len(),
+                                args.len()
+                            ));
+                        }
+                        //实参先展开再代入
+                        let mut expanded_args = HashMap::new();
+                        for (param, arg) in params.iter().zip(args) {
+                            let arg = self.expand(arg.trim(), line, disabled)?;
+                            expanded_args.insert(param.as_str(), arg);
+                        }
+                        substitute(&macro_def.body, &expanded_args)
+                    }
+                };
+                disabled.push(ident.to_string());
+                let expanded = self.expand(&body, line, disabled);
+                disabled.pop();
+                result += &expanded?;
+            } else {
+                let ch = text[i..].chars().next().unwrap();
+                result.push(ch);
+                i += ch.len_utf8();
+            }
+        }
+        Ok(result)
+    }
+}
+
+///构造一条预处理错误
+fn error(file: &str, line: usize, message: String) -> CompileError {
+    CompileError::Syntax(vec![Diagnostic {
+        level: Level::Error,
+        message: format!("{}:{}: {}", file, line, message),
+        span: None,
+    }])
+}
+
+///把注释换成空格，块注释中的换行保留，使行号不变
+fn strip_comments(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut result = String::new();
+    let mut start = 0; //还没有复制到result中的部分的开头
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'"' {
+            i = string_end(bytes, i);
+        } else if bytes[i..].starts_with(b"//") {
+            result += &text[start..i];
+            while i < bytes.len() && bytes[i] != b'\n' {
+                i += 1;
+            }
+            start = i;
+        } else if bytes[i..].starts_with(b"/*") {
+            result += &text[start..i];
+            result.push(' ');
+            let end = text[i + 2..]
+                .find("*/")
+                .map_or(bytes.len(), |pos| i + 2 + pos + 2);
+            result.extend(text[i..end].chars().filter(|&c| c == '\n'));
+            i = end;
+            start = i;
+        } else {
+            i += 1;
+        }
+    }
+    result += &text[start..];
+    result
+}
+
+///按行切分，以`\`结尾的行和下一行拼接，返回每个逻辑行开始的行号(从1开始)和内容
+fn logical_lines(text: &str) -> Vec<(usize, String)> {
+    let mut lines = vec![];
+    let mut pending: Option<(usize, String)> = None;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        let (start, mut joined) = pending.take().unwrap_or((i + 1, String::new()));
+        match line.strip_suffix('\\') {
+            Some(head) => {
+                joined += head;
+                pending = Some((start, joined));
+            }
+            None => {
+                joined += line;
+                lines.push((start, joined));
+            }
+        }
+    }
+    lines.extend(pending);
+    lines
+}
+
+///解析#define之后的部分，返回宏名和宏定义
+fn parse_define(rest: &str) -> std::result::Result<(String, Macro), String> {
+    let (ident, after) = split_ident(rest.trim_start());
+    if ident.is_empty() {
+        return Err("#define后缺少宏名".to_string());
+    }
+    //宏名后紧跟左括号时是带参数的宏
+    let Some(param_list) = after.strip_prefix('(') else {
+        let body = after.trim().to_string();
+        return Ok((ident.to_string(), Macro { params: None, body }));
+    };
+    let Some((param_list, body)) = param_list.split_once(')') else {
+        return Err(format!("宏`{}`的形参列表缺少`)`", ident));
+    };
+    let mut params: Vec<String> = vec![];
+    if !param_list.trim().is_empty() {
+        for param in param_list.split(',') {
+            let param = param.trim();
+            let (name, rest) = split_ident(param);
+            if name.is_empty() || !rest.is_empty() {
+                return Err(format!("宏`{}`的形参`{}`不是标识符", ident, param));
+            }
+            if params.iter().any(|p| p == name) {
+                return Err(format!("宏`{}`有重复的形参`{}`", ident, name));
+            }
+            params.push(name.to_string());
+        }
+    }
+    let macro_def = Macro {
+        params: Some(params),
+        body: body.trim().to_string(),
+    };
+    Ok((ident.to_string(), macro_def))
+}
+
+///把宏体中的形参替换成实参，字符串中的不替换
+fn substitute(body: &str, args: &HashMap<&str, String>) -> String {
+    let bytes = body.as_bytes();
+    let mut result = String::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'"' {
+            let end = string_end(bytes, i);
+            result += &body[i..end];
+            i = end;
+        } else if bytes[i].is_ascii_digit() {
+            let end = number_end(bytes, i);
+            result += &body[i..end];
+            i = end;
+        } else if is_ident_start(bytes[i]) {
+            let (ident, _) = split_ident(&body[i..]);
+            result += args.get(ident).map_or(ident, String::as_str);
+            i += ident.len();
+        } else {
+            let ch = body[i..].chars().next().unwrap();
+            result.push(ch);
+            i += ch.len_utf8();
+        }
+    }
+    result
+}
+
+///从open处的左括号开始，按顶层的逗号切分宏的实参，返回实参和右括号之后的位置
+fn split_args(text: &str, open: usize) -> Option<(Vec<&str>, usize)> {
+    let bytes = text.as_bytes();
+    let mut args = vec![];
+    let mut depth = 0;
+    let mut start = open + 1;
+    let mut i = open;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                i = string_end(bytes, i);
+                continue;
+            }
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    args.push(&text[start..i]);
+                    return Some((args, i + 1));
+                }
+            }
+            b',' if depth == 1 => {
+                args.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+///从start处的引号开始，返回字符串字面量结束之后的位置，没有结束时到末尾
+fn string_end(bytes: &[u8], start: usize) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return i + 1,
+            b'\n' => return i,
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+///数字字面量结束之后的位置，数字中的字母(如0x1f、1e5)不是标识符
+fn number_end(bytes: &[u8], start: usize) -> usize {
+    let hex = bytes[start..].starts_with(b"0x") || bytes[start..].starts_with(b"0X");
+    let mut i = start;
+    while i < bytes.len() {
+        let c = bytes[i];
+        //指数的正负号，十六进制浮点数的指数是p
+        let exponent_sign = matches!(c, b'+' | b'-')
+            && match bytes[i - 1] {
+                b'e' | b'E' => !hex,
+                b'p' | b'P' => hex,
+                _ => false,
+            };
+        if c.is_ascii_alphanumeric() || c == b'_' || c == b'.' || exponent_sign {
+            i += 1;
+        } else {
+            break;
+        }
+    }
+    i
+}
+
+fn is_ident_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_'
+}
+
+///切出text开头的标识符，没有时标识符为空串
+fn split_ident(text: &str) -> (&str, &str) {
+    let bytes = text.as_bytes();
+    if bytes.first().is_none_or(|&c| !is_ident_start(c)) {
+        return ("", text);
+    }
+    let end = bytes
+        .iter()
+        .position(|&c| !c.is_ascii_alphanumeric() && c != b'_')
+        .unwrap_or(bytes.len());
+    text.split_at(end)
+}
@@ -0,0 +1,63 @@
+//! 运行时库的函数声明
+//!
+//! 库函数的签名来自用SysY写的头文件，头文件中只能有函数原型和常量声明，
+//! 常量可以用作数组形参的维度。没有用--runtime-header指定时使用内置的sylib.h
+use crate::array_solve::eval_param_kind;
+use crate::ast::*;
+use crate::ds_for_ir::GenerateIrInfo;
+use crate::error::{CompileError, Result};
+use crate::gen_ir::GenerateIR;
+use crate::symbol_table::FuncInfoBase;
+use crate::symbol_table::ParamKind;
+use koopa::ir::Program;
+use std::collections::HashSet;
+
+///内置的默认头文件，对应SysY标准运行时库
+pub const DEFAULT_HEADER: &str = include_str!("sylib.h");
+
+///从头文件的AST中取出所有库函数，按声明的顺序
+pub fn lib_funcs(ast: &CompUnit) -> Result<Vec<(String, FuncInfoBase)>> {
+    let mut info = GenerateIrInfo::new();
+    let mut program = Program::new(); //常量数组的alloc生成到这里，之后丢弃
+    let mut names = HashSet::new();
+    let mut funcs = vec![];
+    for item in &ast.item {
+        match item {
+            CompItem::Decl(decl @ Decl::ConstDecl(_)) => {
+                decl.generate(&mut program, &mut info)?;
+            }
+            CompItem::Decl(Decl::VarDecl(VarDecl::VarDeclS(_, var_defs))) => {
+                let span = var_defs.first().map(|def| def.span).unwrap_or_default();
+                return Err(CompileError::semantic(
+                    span,
+                    "运行时头文件中不能定义变量".to_string(),
+                ));
+            }
+            CompItem::FuncDef(func_def) => {
+                if func_def.block.is_some() {
+                    return Err(CompileError::semantic(
+                        func_def.span,
+                        format!("运行时头文件中的函数`{}`不能有函数体", func_def.ident),
+                    ));
+                }
+                //putf的可变实参无法用原型表示，由编译器内置
+                if func_def.ident == "putf" || !names.insert(func_def.ident.clone()) {
+                    return Err(CompileError::semantic(
+                        func_def.span,
+                        format!("重复声明了库函数`{}`", func_def.ident),
+                    ));
+                }
+                let params = func_def
+                    .func_fparams
+                    .iter()
+                    .map(|param| eval_param_kind(param, &mut info))
+                    .collect::<Result<Vec<ParamKind>>>()?;
+                funcs.push((
+                    func_def.ident.clone(),
+                    FuncInfoBase::new_lib(func_def.func_type, params),
+                ));
+            }
+        }
+    }
+    Ok(funcs)
+}
@@ -0,0 +1,219 @@
+//! 稀疏条件常量传播
+//!
+//! 在SSA形式上同时求值和基本块的可达性：只有可能执行的基本块中的指令参与计算，
+//! 条件为常量的br只有一侧可能执行。结束后值为常量的指令换成整数，条件为常量的br换成jump，
+//! 再也跳不到的基本块留给simplify_cfg删除
+use crate::calc_exp::{fold_div, fold_rem};
+use crate::ds_for_cfg::terminator;
+use crate::ds_for_opt::{insts, remove_insts, replace_uses};
+use koopa::ir::builder_traits::*;
+use koopa::ir::{BasicBlock, BinaryOp, FunctionData, Value, ValueKind};
+use std::collections::{HashMap, HashSet};
+
+///值在格上的位置
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Lattice {
+    Top,        //还没有算出值，或者是undef
+    Const(i32), //常量
+    Bottom,     //不是常量
+}
+
+impl Lattice {
+    fn meet(self, other: Lattice) -> Lattice {
+        match (self, other) {
+            (Lattice::Top, x) | (x, Lattice::Top) => x,
+            (Lattice::Const(a), Lattice::Const(b)) if a == b => Lattice::Const(a),
+            _ => Lattice::Bottom,
+        }
+    }
+}
+
+///常量传播，返回是否有改动
+pub fn sccp(func: &mut FunctionData) -> bool {
+    let Some(entry) = func.layout().entry_bb() else {
+        return false;
+    };
+    let mut solver = Solver::new(func);
+    solver.run(entry);
+    let Solver { values, .. } = solver;
+
+    //值为常量的指令和基本块参数，使用处换成整数
+    let mut consts = HashMap::new();
+    let mut dead = vec![];
+    for (value, lattice) in values {
+        if let Lattice::Const(c) = lattice {
+            consts.insert(value, func.dfg_mut().new_value().integer(c));
+            if func.layout().parent_bb(value).is_some() {
+                dead.push(value);
+            }
+        }
+    }
+    replace_uses(func, &consts);
+    remove_insts(func, &dead);
+
+    //条件为常量的br换成jump
+    let mut changed = !consts.is_empty();
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+    for bb in bbs {
+        let Some(inst) = terminator(func, bb) else {
+            continue;
+        };
+        let ValueKind::Branch(branch) = func.dfg().value(inst).kind() else {
+            continue;
+        };
+        let ValueKind::Integer(cond) = func.dfg().value(branch.cond()).kind() else {
+            continue;
+        };
+        let (target, args) = if cond.value() != 0 {
+            (branch.true_bb(), branch.true_args().to_vec())
+        } else {
+            (branch.false_bb(), branch.false_args().to_vec())
+        };
+        func.dfg_mut()
+            .replace_value_with(inst)
+            .jump_with_args(target, args);
+        changed = true;
+    }
+    changed
+}
+
+///求每个值在格上的位置
+struct Solver<'a> {
+    func: &'a FunctionData,
+    uses: HashMap<Value, Vec<Value>>, //使用每个值的指令
+    values: HashMap<Value, Lattice>,  //指令和基本块参数的值，没有记录的是Top
+    executable: HashSet<BasicBlock>,  //可能执行的基本块
+    bb_work: Vec<BasicBlock>,         //刚变成可能执行的基本块
+    value_work: Vec<Value>,           //值刚发生变化的指令和基本块参数
+}
+
+impl<'a> Solver<'a> {
+    fn new(func: &'a FunctionData) -> Self {
+        let mut uses: HashMap<Value, Vec<Value>> = HashMap::new();
+        for (_, inst) in insts(func) {
+            for value in func.dfg().value(inst).kind().value_uses() {
+                uses.entry(value).or_default().push(inst);
+            }
+        }
+        Solver {
+            func,
+            uses,
+            values: HashMap::new(),
+            executable: HashSet::new(),
+            bb_work: vec![],
+            value_work: vec![],
+        }
+    }
+
+    fn run(&mut self, entry: BasicBlock) {
+        self.executable.insert(entry);
+        self.bb_work.push(entry);
+        loop {
+            if let Some(bb) = self.bb_work.pop() {
+                let node = self.func.layout().bbs().node(&bb).unwrap();
+                for &inst in node.insts().keys() {
+                    self.visit(inst);
+                }
+            } else if let Some(value) = self.value_work.pop() {
+                for inst in self.uses.get(&value).cloned().unwrap_or_default() {
+                    self.visit(inst);
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    ///操作数的值
+    fn value(&self, value: Value) -> Lattice {
+        if value.is_global() {
+            return Lattice::Bottom;
+        }
+        match self.func.dfg().value(value).kind() {
+            ValueKind::Integer(int) => Lattice::Const(int.value()),
+            ValueKind::Undef(_) => Lattice::Top,
+            ValueKind::FuncArgRef(_) => Lattice::Bottom,
+            _ => *self.values.get(&value).unwrap_or(&Lattice::Top),
+        }
+    }
+
+    ///值只会沿格往下走，发生变化时重新计算使用它的指令
+    fn update(&mut self, value: Value, lattice: Lattice) {
+        let old = self.value(value);
+        let new = old.meet(lattice);
+        if new != old {
+            self.values.insert(value, new);
+            self.value_work.push(value);
+        }
+    }
+
+    ///沿着一条可能执行的边跳到target，实参并入target的参数
+    fn flow(&mut self, target: BasicBlock, args: &[Value]) {
+        let params = self.func.dfg().bb(target).params();
+        for (&param, &arg) in params.iter().zip(args) {
+            self.update(param, self.value(arg));
+        }
+        if self.executable.insert(target) {
+            self.bb_work.push(target);
+        }
+    }
+
+    fn visit(&mut self, inst: Value) {
+        let bb = self.func.layout().parent_bb(inst).unwrap();
+        if !self.executable.contains(&bb) {
+            return;
+        }
+        match self.func.dfg().value(inst).kind() {
+            ValueKind::Binary(binary) => {
+                let lattice = match (self.value(binary.lhs()), self.value(binary.rhs())) {
+                    (Lattice::Bottom, _) | (_, Lattice::Bottom) => Lattice::Bottom,
+                    (Lattice::Const(lhs), Lattice::Const(rhs)) => {
+                        match fold_binary(binary.op(), lhs, rhs) {
+                            Some(c) => Lattice::Const(c),
+                            None => Lattice::Bottom,
+                        }
+                    }
+                    _ => Lattice::Top,
+                };
+                self.update(inst, lattice);
+            }
+            ValueKind::Branch(branch) => match self.value(branch.cond()) {
+                Lattice::Top => {}
+                Lattice::Const(c) if c != 0 => self.flow(branch.true_bb(), branch.true_args()),
+                Lattice::Const(_) => self.flow(branch.false_bb(), branch.false_args()),
+                Lattice::Bottom => {
+                    self.flow(branch.true_bb(), branch.true_args());
+                    self.flow(branch.false_bb(), branch.false_args());
+                }
+            },
+            ValueKind::Jump(jump) => self.flow(jump.target(), jump.args()),
+            ValueKind::Store(_) | ValueKind::Return(_) => {}
+            //load、call和地址计算的结果都不当作常量
+            _ => self.update(inst, Lattice::Bottom),
+        }
+    }
+}
+
+///计算两个常量的二元运算，和RISC-V指令的结果一致，除以0时不计算
+///除法和取余与前端的常量折叠使用同样的规则
+fn fold_binary(op: BinaryOp, lhs: i32, rhs: i32) -> Option<i32> {
+    Some(match op {
+        BinaryOp::NotEq => (lhs != rhs) as i32,
+        BinaryOp::Eq => (lhs == rhs) as i32,
+        BinaryOp::Gt => (lhs > rhs) as i32,
+        BinaryOp::Lt => (lhs < rhs) as i32,
+        BinaryOp::Ge => (lhs >= rhs) as i32,
+        BinaryOp::Le => (lhs <= rhs) as i32,
+        BinaryOp::Add => lhs.wrapping_add(rhs),
+        BinaryOp::Sub => lhs.wrapping_sub(rhs),
+        BinaryOp::Mul => lhs.wrapping_mul(rhs),
+        BinaryOp::Div => return fold_div(lhs, rhs),
+        BinaryOp::Mod => return fold_rem(lhs, rhs),
+        BinaryOp::And => lhs & rhs,
+        BinaryOp::Or => lhs | rhs,
+        BinaryOp::Xor => lhs ^ rhs,
+        BinaryOp::Shl => lhs.wrapping_shl(rhs as u32),
+        BinaryOp::Shr => (lhs as u32).wrapping_shr(rhs as u32) as i32,
+        BinaryOp::Sar => lhs.wrapping_shr(rhs as u32),
+    })
+}
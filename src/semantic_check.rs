@@ -0,0 +1,903 @@
+//! 语义检查
+//!
+//! 在生成IR之前遍历整棵AST，检查函数调用的参数个数和种类、void值的使用、
+//! 对常量和数组整体的赋值、break/continue的位置等，一次性报告所有错误
+//!
+//! 同时做控制流分析：int函数可能不经return走到末尾、return/break/continue
+//! 之后的语句不可达时给出警告，-Werror时警告按错误处理
+use crate::array_solve::eval_dim;
+use crate::array_solve::eval_param_kind;
+use crate::array_solve::GlobalArrayInit;
+use crate::ast::*;
+use crate::calc_exp::eval_case;
+use crate::calc_exp::eval_const;
+use crate::calc_exp::literal_value;
+use crate::calc_exp::Eval;
+use crate::diagnostics::{Diagnostic, Level};
+use crate::ds_for_check::param_kind_name;
+use crate::ds_for_check::CheckInfo;
+use crate::ds_for_check::ExpType;
+use crate::ds_for_check::Flow;
+use crate::error::{CompileError, Result};
+use crate::gen_ir::declare_funcs;
+use crate::symbol_table::ArrayInfoBase;
+use crate::symbol_table::ConstValue;
+use crate::symbol_table::FuncInfoBase;
+use crate::symbol_table::ParamKind;
+use crate::symbol_table::SymbolInfo::Array;
+use crate::symbol_table::SymbolInfo::ArrayPointer;
+use crate::symbol_table::SymbolInfo::Const;
+use crate::symbol_table::SymbolInfo::Func;
+use crate::symbol_table::SymbolInfo::Var;
+use crate::symbol_table::VarInfoBase;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+///用于语义检查的trait，发现的语义错误记录在info中，不中断检查
+pub trait Check {
+    type CheckResult;
+    fn check(&self, info: &mut CheckInfo) -> Result<Self::CheckResult>;
+}
+
+///检查整个程序，有错误时一次性返回所有错误和警告，否则返回警告
+///
+///werror为真时警告都当作错误
+pub fn check_program(
+    ast: &CompUnit,
+    werror: bool,
+    runtime: &[(String, FuncInfoBase)],
+) -> Result<Vec<Diagnostic>> {
+    let mut info = CheckInfo::new();
+    info.env.runtime = runtime.to_vec();
+    ast.check(&mut info)?;
+    let mut warnings = info.warnings;
+    if werror {
+        for warning in &mut warnings {
+            warning.level = Level::Error;
+        }
+    }
+    if info.errors.is_empty() && (!werror || warnings.is_empty()) {
+        return Ok(warnings);
+    }
+    //按在源文件中出现的位置排序输出
+    let mut diags = info.errors;
+    diags.extend(warnings);
+    diags.sort_by_key(|diag| diag.span.map(|span| span.start));
+    Err(CompileError::Semantic(diags))
+}
+
+///声明语句在源文件中的位置，取第一个定义的位置
+fn decl_span(decl: &Decl) -> Option<Span> {
+    match decl {
+        Decl::ConstDecl(ConstDecl::ConstDeclS(_, const_def_s)) => {
+            const_def_s.first().map(|def| def.span)
+        }
+        Decl::VarDecl(VarDecl::VarDeclS(_, var_def_s)) => var_def_s.first().map(|def| def.span),
+    }
+}
+
+///计算数组各维度，出错时记录错误并把该维度当作1
+fn check_dims(dims: &[ConstExp], info: &mut CheckInfo) -> Result<Vec<i32>> {
+    let mut real_dims: Vec<i32> = vec![];
+    for dim in dims {
+        match eval_dim(dim, &mut info.env) {
+            Ok(val) => real_dims.push(val),
+            Err(err) => {
+                info.record(err)?;
+                real_dims.push(1);
+            }
+        }
+    }
+    Ok(real_dims)
+}
+
+impl Check for CompUnit {
+    type CheckResult = ();
+    fn check(&self, info: &mut CheckInfo) -> Result<()> {
+        info.env.insert_lib_funcs();
+        declare_funcs(self, &mut info.env);
+        for item in &self.item {
+            match item {
+                CompItem::FuncDef(func_def) => func_def.check(info)?,
+                CompItem::Decl(decl) => decl.check(info)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Check for FuncDef {
+    type CheckResult = ();
+    fn check(&self, info: &mut CheckInfo) -> Result<()> {
+        //和生成IR时一样，形参和函数体最外层的声明在同一层作用域，函数体中不能重新定义形参
+        info.env.push_block();
+        let mut params = vec![];
+        for func_fparam in &self.func_fparams {
+            let kind = match eval_param_kind(func_fparam, &mut info.env) {
+                Ok(kind) => kind,
+                Err(err) => {
+                    info.record(err)?;
+                    match (&func_fparam.dims, func_fparam.btype) {
+                        (None, BType::Int) => ParamKind::Int,
+                        (None, BType::Float) => ParamKind::Float,
+                        (Some(_), btype) => ParamKind::Array(btype, vec![]),
+                    }
+                }
+            };
+            params.push(kind);
+        }
+        info.declare_func(
+            self.ident.clone(),
+            FuncInfoBase::new(self.func_type, params.clone()),
+            self.span,
+            self.block.is_some(),
+        )?;
+        let Some(block) = &self.block else {
+            //原型声明只检查形参
+            info.env.pop_block();
+            return Ok(());
+        };
+        for (func_fparam, kind) in self.func_fparams.iter().zip(params) {
+            //用户函数的形参只有int、float和数组指针，Str只出现在putf中
+            let symbol = match kind {
+                ParamKind::Array(btype, dims) => ArrayPointer(ArrayInfoBase {
+                    btype,
+                    dims,
+                    const_init: None,
+                }),
+                _ => Var(VarInfoBase::new(func_fparam.btype)),
+            };
+            info.insert_symbol(func_fparam.ident.clone(), symbol, func_fparam.span)?;
+        }
+
+        info.now_func = self.ident.clone();
+        info.now_func_type = self.func_type;
+        let flow = check_items(&block.items, info)?;
+        //main走到末尾时返回0，不需要警告
+        if let (Flow::Next, Some(btype)) = (flow, self.func_type.ret_btype()) {
+            if self.ident != "main" {
+                let message = format!(
+                    "{}函数`{}`可能在没有返回值的情况下结束",
+                    btype.name(),
+                    self.ident
+                );
+                info.warning(self.span, message);
+            }
+        }
+        info.env.pop_block();
+        Ok(())
+    }
+}
+
+impl Check for Block {
+    type CheckResult = Flow;
+    fn check(&self, info: &mut CheckInfo) -> Result<Flow> {
+        info.env.push_block();
+        let flow = check_items(&self.items, info)?;
+        info.env.pop_block();
+        Ok(flow)
+    }
+}
+
+///依次检查块中的语句，返回最后的控制流
+fn check_items(items: &[BlockItem], info: &mut CheckInfo) -> Result<Flow> {
+    let mut flow = Flow::Next;
+    let mut warned = false; //每个块只对第一条不可达的语句警告
+    for item in items {
+        let span = match item {
+            BlockItem::Decl(decl) => decl_span(decl),
+            BlockItem::Stmt(stmt) => Some(stmt.span),
+        };
+        if flow == Flow::Jump && !warned {
+            if let Some(span) = span {
+                info.warning(span, "不可达的语句".to_string());
+                warned = true;
+            }
+        }
+        match item {
+            BlockItem::Decl(decl) => decl.check(info)?,
+            BlockItem::Stmt(stmt) => {
+                if stmt.check(info)? == Flow::Jump {
+                    flow = Flow::Jump;
+                }
+            }
+        }
+    }
+    Ok(flow)
+}
+
+impl Check for Stmt {
+    type CheckResult = Flow;
+    fn check(&self, info: &mut CheckInfo) -> Result<Flow> {
+        let mut flow = Flow::Next;
+        match &self.kind {
+            StmtKind::Assign(lval, exp) => {
+                let exp_type = exp.check(info)?;
+                info.expect_number(exp_type);
+                check_assign_target(lval, info)?;
+            }
+            StmtKind::OpAssign(lval, op, exp) => {
+                let exp_type = exp.check(info)?;
+                let lval_type = check_assign_target(lval, info)?;
+                if *op == ArithOp::Mod {
+                    info.expect_int(lval_type, "取模运算的操作数");
+                    info.expect_int(exp_type, "取模运算的操作数");
+                } else {
+                    info.expect_number(exp_type);
+                }
+            }
+            StmtKind::Incr(lval, _) => {
+                check_assign_target(lval, info)?;
+            }
+            StmtKind::Exp(exp) => {
+                if let Some(exp) = exp {
+                    exp.check(info)?;
+                }
+            }
+            StmtKind::Block(block) => flow = block.check(info)?,
+            StmtKind::RetExp(exp) => {
+                flow = Flow::Jump;
+                match (exp, info.now_func_type.ret_btype()) {
+                    (Some(exp), Some(_)) => {
+                        let exp_type = exp.check(info)?;
+                        info.expect_number(exp_type);
+                    }
+                    (Some(exp), None) => {
+                        exp.check(info)?;
+                        let message = format!("void函数`{}`不能返回值", info.now_func);
+                        info.error(exp.span(), message);
+                    }
+                    (None, Some(btype)) => {
+                        let message =
+                            format!("{}函数`{}`必须返回一个值", btype.name(), info.now_func);
+                        info.error(self.span, message);
+                    }
+                    (None, None) => {}
+                }
+            }
+            StmtKind::If(exp, then_stmt, else_stmt) => {
+                let exp_type = exp.check(info)?;
+                info.expect_number(exp_type);
+                let then_flow = then_stmt.check(info)?;
+                if let Some(else_stmt) = else_stmt {
+                    flow = then_flow.join(else_stmt.check(info)?);
+                }
+            }
+            StmtKind::While(exp, stmt) => {
+                let exp_type = exp.check(info)?;
+                info.expect_number(exp_type);
+                info.env.push_while();
+                info.loop_breaks.push(false);
+                info.loop_continues.push(false);
+                stmt.check(info)?;
+                info.env.pop_while();
+                info.loop_continues.pop();
+                let has_break = info.loop_breaks.pop().unwrap_or(true);
+                //条件恒为真且没有break的循环不会结束
+                let endless = matches!(exp.eval(&mut info.env), Ok(Some(val)) if val.is_true());
+                if endless && !has_break {
+                    flow = Flow::Jump;
+                }
+            }
+            StmtKind::For(init, exp, step, stmt) => {
+                //初始化中声明的变量只在循环内可见
+                info.env.push_block();
+                for item in init {
+                    match item {
+                        BlockItem::Decl(decl) => decl.check(info)?,
+                        BlockItem::Stmt(stmt) => {
+                            stmt.check(info)?;
+                        }
+                    }
+                }
+                if let Some(exp) = exp {
+                    let exp_type = exp.check(info)?;
+                    info.expect_number(exp_type);
+                }
+                info.env.push_while();
+                info.loop_breaks.push(false);
+                info.loop_continues.push(false);
+                stmt.check(info)?;
+                for step in step {
+                    step.check(info)?;
+                }
+                info.env.pop_while();
+                info.loop_continues.pop();
+                let has_break = info.loop_breaks.pop().unwrap_or(true);
+                //省略条件或条件恒为真且没有break的循环不会结束
+                let endless = match exp {
+                    Some(exp) => matches!(exp.eval(&mut info.env), Ok(Some(val)) if val.is_true()),
+                    None => true,
+                };
+                if endless && !has_break {
+                    flow = Flow::Jump;
+                }
+                info.env.pop_block();
+            }
+            StmtKind::DoWhile(stmt, exp) => {
+                info.env.push_while();
+                info.loop_breaks.push(false);
+                info.loop_continues.push(false);
+                let body_flow = stmt.check(info)?;
+                info.env.pop_while();
+                let has_continue = info.loop_continues.pop().unwrap_or(true);
+                let has_break = info.loop_breaks.pop().unwrap_or(true);
+                //循环体正常结束或者continue时才会求条件，否则条件不可达，循环只能经break离开
+                let reach_cond = body_flow == Flow::Next || has_continue;
+                if !reach_cond {
+                    info.warning(exp.span(), "不可达的语句".to_string());
+                }
+                let exp_type = exp.check(info)?;
+                info.expect_number(exp_type);
+                let endless = matches!(exp.eval(&mut info.env), Ok(Some(val)) if val.is_true());
+                if (endless || !reach_cond) && !has_break {
+                    flow = Flow::Jump;
+                }
+            }
+            StmtKind::Switch(exp, arms) => {
+                let exp_type = exp.check(info)?;
+                info.expect_int(exp_type, "switch的条件");
+                info.env.push_switch();
+                info.loop_breaks.push(false);
+                let mut values = HashSet::new();
+                let mut has_default = false;
+                let mut arm_flow = Flow::Next;
+                for arm in arms {
+                    match &arm.label {
+                        CaseLabel::Case(label) => match eval_case(label, &mut info.env) {
+                            Ok(val) => {
+                                if !values.insert(val) {
+                                    info.error(label.span(), format!("重复的case值{}", val));
+                                }
+                            }
+                            Err(err) => info.record(err)?,
+                        },
+                        CaseLabel::Default(span) => {
+                            if has_default {
+                                info.error(*span, "switch中有多个default".to_string());
+                            }
+                            has_default = true;
+                        }
+                    }
+                    //每个分支单独一个作用域，没有break时顺序执行到下一个分支
+                    info.env.push_block();
+                    arm_flow = check_items(&arm.items, info)?;
+                    info.env.pop_block();
+                }
+                info.env.pop_switch();
+                let has_break = info.loop_breaks.pop().unwrap_or(true);
+                //有default时一定会进入某个分支，只能经break或最后一个分支的末尾离开
+                if has_default && !has_break && arm_flow == Flow::Jump {
+                    flow = Flow::Jump;
+                }
+            }
+            //不在循环中的break/continue已经报错，之后的语句不再当作不可达
+            StmtKind::Break => match info.loop_breaks.last_mut() {
+                Some(has_break) => {
+                    *has_break = true;
+                    flow = Flow::Jump;
+                }
+                None => info.error(self.span, "break语句不在循环或switch中".to_string()),
+            },
+            StmtKind::Continue => {
+                if info.env.continue_label().is_some() {
+                    if let Some(has_continue) = info.loop_continues.last_mut() {
+                        *has_continue = true;
+                    }
+                    flow = Flow::Jump;
+                } else {
+                    info.error(self.span, "continue语句不在循环中".to_string());
+                }
+            }
+        }
+        Ok(flow)
+    }
+}
+
+///检查赋值语句的左值是否可以赋值，返回左值的类型(出错时当作int)
+fn check_assign_target(lval: &LVal, info: &mut CheckInfo) -> Result<ExpType> {
+    match info.env.search_symbol(&lval.ident).map(|x| x.content) {
+        Some(Const(_)) => {
+            info.error(lval.span, format!("不能给常量`{}`赋值", lval.ident));
+        }
+        Some(Array(array_info)) if array_info.const_init.is_some() => {
+            info.error(lval.span, format!("不能给常量数组`{}`赋值", lval.ident));
+        }
+        _ => match lval.check(info)? {
+            ExpType::Array(..) => {
+                info.error(lval.span, format!("不能给数组`{}`整体赋值", lval.ident));
+            }
+            lval_type => return Ok(lval_type),
+        },
+    }
+    Ok(ExpType::Int)
+}
+
+impl Check for Decl {
+    type CheckResult = ();
+    fn check(&self, info: &mut CheckInfo) -> Result<()> {
+        match self {
+            Decl::ConstDecl(ConstDecl::ConstDeclS(btype, const_def_s)) => {
+                info.env.decl_type = *btype;
+                for const_def in const_def_s {
+                    const_def.check(info)?;
+                }
+            }
+            Decl::VarDecl(VarDecl::VarDeclS(btype, var_def_s)) => {
+                info.env.decl_type = *btype;
+                for var_def in var_def_s {
+                    var_def.check(info)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Check for ConstDef {
+    type CheckResult = ();
+    fn check(&self, info: &mut CheckInfo) -> Result<()> {
+        if self.dims.is_empty() {
+            //常量，出错时当作0继续检查
+            let what = format!("常量`{}`的初值", self.ident);
+            let val = match eval_const(&self.const_init_val, self.span, &mut info.env, &what) {
+                Ok(val) => val.convert(info.env.decl_type),
+                Err(err) => {
+                    info.record(err)?;
+                    ConstValue::Int(0).convert(info.env.decl_type)
+                }
+            };
+            info.insert_symbol(self.ident.clone(), Const(val), self.span)?;
+        } else {
+            //常量数组，初值必须都是常量表达式，出错时当作全0继续检查
+            let real_dims = check_dims(&self.dims, info)?;
+            let btype = info.env.decl_type;
+            let mut init: Vec<i32> = vec![];
+            if let Err(err) = self.const_init_val.global_array_init(
+                &mut info.env,
+                &real_dims,
+                btype,
+                self.span,
+                &mut init,
+            ) {
+                info.record(err)?;
+                init = vec![0; real_dims.iter().product::<i32>() as usize];
+            }
+            info.insert_symbol(
+                self.ident.clone(),
+                Array(ArrayInfoBase {
+                    btype,
+                    dims: real_dims,
+                    const_init: Some(Rc::new(init)),
+                }),
+                self.span,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Check for VarDef {
+    type CheckResult = ();
+    fn check(&self, info: &mut CheckInfo) -> Result<()> {
+        let is_global = info.env.tables.len() == 1;
+        if self.dims.is_empty() {
+            //和生成IR时一样，先插入符号表再检查初值
+            let var_info = VarInfoBase::new(info.env.decl_type);
+            info.insert_symbol(self.ident.clone(), Var(var_info), self.span)?;
+            match &self.init_val {
+                None => {}
+                Some(InitVal::InitValS(_)) => {
+                    let message = format!("变量`{}`不是数组，不能用初始化列表赋初值", self.ident);
+                    info.error(self.span, message);
+                }
+                Some(InitVal::Exp(exp)) if is_global => {
+                    let what = format!("全局变量`{}`的初值", self.ident);
+                    if let Err(err) = eval_const(exp, self.span, &mut info.env, &what) {
+                        info.record(err)?;
+                    }
+                }
+                Some(InitVal::Exp(exp)) => {
+                    let exp_type = exp.check(info)?;
+                    info.expect_number(exp_type);
+                }
+            }
+        } else {
+            let real_dims = check_dims(&self.dims, info)?;
+            let btype = info.env.decl_type;
+            info.insert_symbol(
+                self.ident.clone(),
+                Array(ArrayInfoBase {
+                    btype,
+                    dims: real_dims.clone(),
+                    const_init: None,
+                }),
+                self.span,
+            )?;
+            match &self.init_val {
+                None => {}
+                Some(init_val) if is_global => {
+                    //全局数组的初值必须都是常量表达式
+                    if let Err(err) = init_val.global_array_init(
+                        &mut info.env,
+                        &real_dims,
+                        btype,
+                        self.span,
+                        &mut vec![],
+                    ) {
+                        info.record(err)?;
+                    }
+                }
+                Some(init_val) => init_val.check(info)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+///二元算术运算结果的类型，有一边是float时结果为float
+fn arith_type(lhs: ExpType, rhs: ExpType) -> ExpType {
+    match (lhs, rhs) {
+        (ExpType::Float(l), ExpType::Float(r)) => ExpType::Float(Span::new(l.start, r.end)),
+        (ExpType::Float(span), _) | (_, ExpType::Float(span)) => ExpType::Float(span),
+        _ => ExpType::Int,
+    }
+}
+
+///检查局部数组初值中的每个表达式
+impl Check for InitVal {
+    type CheckResult = ();
+    fn check(&self, info: &mut CheckInfo) -> Result<()> {
+        match self {
+            InitVal::Exp(exp) => {
+                let exp_type = exp.check(info)?;
+                info.expect_number(exp_type);
+            }
+            InitVal::InitValS(vals) => {
+                for val in vals {
+                    val.check(info)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Check for Exp {
+    type CheckResult = ExpType;
+    fn check(&self, info: &mut CheckInfo) -> Result<ExpType> {
+        match self {
+            Exp::LOrExp(lor_exp, _) => lor_exp.check(info),
+            Exp::Cond(cond, then_exp, else_exp, _) => {
+                let cond_type = cond.check(info)?;
+                info.expect_number(cond_type);
+                let then_type = then_exp.check(info)?;
+                let then_type = info.expect_number(then_type);
+                let else_type = else_exp.check(info)?;
+                let else_type = info.expect_number(else_type);
+                Ok(arith_type(then_type, else_type))
+            }
+        }
+    }
+}
+
+impl Check for LOrExp {
+    type CheckResult = ExpType;
+    fn check(&self, info: &mut CheckInfo) -> Result<ExpType> {
+        match self {
+            LOrExp::LAndExp(exp) => exp.check(info),
+            LOrExp::BinaryExp(lhs, rhs) => {
+                let lhs_type = lhs.check(info)?;
+                info.expect_number(lhs_type);
+                let rhs_type = rhs.check(info)?;
+                info.expect_number(rhs_type);
+                Ok(ExpType::Int)
+            }
+        }
+    }
+}
+
+impl Check for LAndExp {
+    type CheckResult = ExpType;
+    fn check(&self, info: &mut CheckInfo) -> Result<ExpType> {
+        match self {
+            LAndExp::BitOrExp(exp) => exp.check(info),
+            LAndExp::BinaryExp(lhs, rhs) => {
+                let lhs_type = lhs.check(info)?;
+                info.expect_number(lhs_type);
+                let rhs_type = rhs.check(info)?;
+                info.expect_number(rhs_type);
+                Ok(ExpType::Int)
+            }
+        }
+    }
+}
+
+impl Check for BitOrExp {
+    type CheckResult = ExpType;
+    fn check(&self, info: &mut CheckInfo) -> Result<ExpType> {
+        match self {
+            BitOrExp::BitXorExp(exp) => exp.check(info),
+            BitOrExp::BinaryExp(lhs, rhs) => {
+                let lhs_type = lhs.check(info)?;
+                info.expect_int(lhs_type, "位运算的操作数");
+                let rhs_type = rhs.check(info)?;
+                info.expect_int(rhs_type, "位运算的操作数");
+                Ok(ExpType::Int)
+            }
+        }
+    }
+}
+
+impl Check for BitXorExp {
+    type CheckResult = ExpType;
+    fn check(&self, info: &mut CheckInfo) -> Result<ExpType> {
+        match self {
+            BitXorExp::BitAndExp(exp) => exp.check(info),
+            BitXorExp::BinaryExp(lhs, rhs) => {
+                let lhs_type = lhs.check(info)?;
+                info.expect_int(lhs_type, "位运算的操作数");
+                let rhs_type = rhs.check(info)?;
+                info.expect_int(rhs_type, "位运算的操作数");
+                Ok(ExpType::Int)
+            }
+        }
+    }
+}
+
+impl Check for BitAndExp {
+    type CheckResult = ExpType;
+    fn check(&self, info: &mut CheckInfo) -> Result<ExpType> {
+        match self {
+            BitAndExp::EqExp(exp) => exp.check(info),
+            BitAndExp::BinaryExp(lhs, rhs) => {
+                let lhs_type = lhs.check(info)?;
+                info.expect_int(lhs_type, "位运算的操作数");
+                let rhs_type = rhs.check(info)?;
+                info.expect_int(rhs_type, "位运算的操作数");
+                Ok(ExpType::Int)
+            }
+        }
+    }
+}
+
+impl Check for EqExp {
+    type CheckResult = ExpType;
+    fn check(&self, info: &mut CheckInfo) -> Result<ExpType> {
+        match self {
+            EqExp::RelExp(exp) => exp.check(info),
+            EqExp::BinaryExp(lhs, _, rhs) => {
+                let lhs_type = lhs.check(info)?;
+                info.expect_number(lhs_type);
+                let rhs_type = rhs.check(info)?;
+                info.expect_number(rhs_type);
+                Ok(ExpType::Int)
+            }
+        }
+    }
+}
+
+impl Check for RelExp {
+    type CheckResult = ExpType;
+    fn check(&self, info: &mut CheckInfo) -> Result<ExpType> {
+        match self {
+            RelExp::ShiftExp(exp) => exp.check(info),
+            RelExp::BinaryExp(lhs, _, rhs) => {
+                let lhs_type = lhs.check(info)?;
+                info.expect_number(lhs_type);
+                let rhs_type = rhs.check(info)?;
+                info.expect_number(rhs_type);
+                Ok(ExpType::Int)
+            }
+        }
+    }
+}
+
+impl Check for ShiftExp {
+    type CheckResult = ExpType;
+    fn check(&self, info: &mut CheckInfo) -> Result<ExpType> {
+        match self {
+            ShiftExp::AddExp(exp) => exp.check(info),
+            ShiftExp::BinaryExp(lhs, _, rhs) => {
+                let lhs_type = lhs.check(info)?;
+                info.expect_int(lhs_type, "位运算的操作数");
+                let rhs_type = rhs.check(info)?;
+                info.expect_int(rhs_type, "位运算的操作数");
+                Ok(ExpType::Int)
+            }
+        }
+    }
+}
+
+impl Check for AddExp {
+    type CheckResult = ExpType;
+    fn check(&self, info: &mut CheckInfo) -> Result<ExpType> {
+        match self {
+            AddExp::MulExp(exp) => exp.check(info),
+            AddExp::BinaryExp(lhs, _, rhs) => {
+                let lhs_type = lhs.check(info)?;
+                let lhs_type = info.expect_number(lhs_type);
+                let rhs_type = rhs.check(info)?;
+                let rhs_type = info.expect_number(rhs_type);
+                Ok(arith_type(lhs_type, rhs_type))
+            }
+        }
+    }
+}
+
+impl Check for MulExp {
+    type CheckResult = ExpType;
+    fn check(&self, info: &mut CheckInfo) -> Result<ExpType> {
+        match self {
+            MulExp::UnaryExp(exp) => exp.check(info),
+            MulExp::BinaryExp(lhs, BinaryMulOp::Mod, rhs) => {
+                let lhs_type = lhs.check(info)?;
+                info.expect_int(lhs_type, "取模运算的操作数");
+                let rhs_type = rhs.check(info)?;
+                info.expect_int(rhs_type, "取模运算的操作数");
+                Ok(ExpType::Int)
+            }
+            MulExp::BinaryExp(lhs, _, rhs) => {
+                let lhs_type = lhs.check(info)?;
+                let lhs_type = info.expect_number(lhs_type);
+                let rhs_type = rhs.check(info)?;
+                let rhs_type = info.expect_number(rhs_type);
+                Ok(arith_type(lhs_type, rhs_type))
+            }
+        }
+    }
+}
+
+impl Check for UnaryExp {
+    type CheckResult = ExpType;
+    fn check(&self, info: &mut CheckInfo) -> Result<ExpType> {
+        match self {
+            UnaryExp::PrimaryExp(primary_exp) => primary_exp.check(info),
+            UnaryExp::BinaryOp(UnaryOp::Not, exp) => {
+                let exp_type = exp.check(info)?;
+                info.expect_number(exp_type);
+                Ok(ExpType::Int)
+            }
+            UnaryExp::BinaryOp(UnaryOp::BitNot, exp) => {
+                let exp_type = exp.check(info)?;
+                info.expect_int(exp_type, "位运算的操作数");
+                Ok(ExpType::Int)
+            }
+            UnaryExp::BinaryOp(_, exp) => {
+                let exp_type = exp.check(info)?;
+                Ok(info.expect_number(exp_type))
+            }
+            UnaryExp::Call(ident, exps, span) => {
+                let mut arg_types = vec![];
+                for exp in exps {
+                    arg_types.push((exp.check(info)?, exp.span()));
+                }
+                let func_info = match info.env.search_symbol(ident).map(|x| x.content) {
+                    Some(Func(func_info)) => func_info,
+                    Some(_) => {
+                        info.error(*span, format!("尝试调用非函数`{}`", ident));
+                        return Ok(ExpType::Int);
+                    }
+                    None => {
+                        info.error(*span, format!("调用了未定义的函数`{}`", ident));
+                        return Ok(ExpType::Int);
+                    }
+                };
+                if func_info.variadic && func_info.params.len() > arg_types.len() {
+                    let message = format!(
+                        "函数`{}`至少需要{}个参数，实际传入了{}个",
+                        ident,
+                        func_info.params.len(),
+                        arg_types.len()
+                    );
+                    info.error(*span, message);
+                } else if !func_info.variadic && func_info.params.len() != arg_types.len() {
+                    let message = format!(
+                        "函数`{}`需要{}个参数，实际传入了{}个",
+                        ident,
+                        func_info.params.len(),
+                        arg_types.len()
+                    );
+                    info.error(*span, message);
+                } else {
+                    for (i, ((arg_type, arg_span), param)) in
+                        arg_types.iter().zip(&func_info.params).enumerate()
+                    {
+                        if !arg_type.matches(param) {
+                            let message = format!(
+                                "函数`{}`的第{}个参数需要{}，实际传入了{}",
+                                ident,
+                                i + 1,
+                                param_kind_name(param),
+                                arg_type.name()
+                            );
+                            info.error(*arg_span, message);
+                        }
+                    }
+                    //可变参数只能是int或float
+                    for (arg_type, _) in arg_types.into_iter().skip(func_info.params.len()) {
+                        info.expect_number(arg_type);
+                    }
+                }
+                match func_info.ret_type.ret_btype() {
+                    Some(btype) => Ok(ExpType::from_btype(btype, *span)),
+                    None => Ok(ExpType::Void(*span)),
+                }
+            }
+        }
+    }
+}
+
+impl Check for PrimaryExp {
+    type CheckResult = ExpType;
+    fn check(&self, info: &mut CheckInfo) -> Result<ExpType> {
+        match self {
+            PrimaryExp::Bexp(exp) => exp.check(info),
+            PrimaryExp::LVal(lval) => lval.check(info),
+            PrimaryExp::Number(num, span) => {
+                if let Err(err) = literal_value(*num, *span) {
+                    info.record(err)?;
+                }
+                Ok(ExpType::Int)
+            }
+            PrimaryExp::FloatNumber(_, span) => Ok(ExpType::Float(*span)),
+            PrimaryExp::Str(_, span) => Ok(ExpType::Str(*span)),
+        }
+    }
+}
+
+///LVal的类型由符号种类和下标个数共同决定
+impl Check for LVal {
+    type CheckResult = ExpType;
+    fn check(&self, info: &mut CheckInfo) -> Result<ExpType> {
+        for dim in &self.dims {
+            let dim_type = dim.check(info)?;
+            info.expect_int(dim_type, "数组下标");
+        }
+        let index_count = self.dims.len();
+        let symbol = match info.env.search_symbol(&self.ident) {
+            Some(x) => x.content,
+            None => {
+                info.error(self.span, format!("使用了未定义的符号`{}`", self.ident));
+                return Ok(ExpType::Int);
+            }
+        };
+        //elem_dims为除第一维外的维度，max_index为最多能取的下标个数
+        let (btype, elem_dims, max_index) = match symbol {
+            Var(var_info) => (var_info.btype, vec![], 0),
+            Const(val) => (val.btype(), vec![], 0),
+            Func(_) => {
+                info.error(self.span, format!("函数`{}`不能作为值使用", self.ident));
+                return Ok(ExpType::Int);
+            }
+            //数组[2,3]的指针形式为*[i32, 3]
+            Array(array_info) => {
+                let max_index = array_info.dims.len();
+                (array_info.btype, array_info.dims[1..].to_vec(), max_index)
+            }
+            //数组指针*[i32, 3]本身就是指针形式
+            ArrayPointer(array_info) => {
+                let max_index = array_info.dims.len() + 1;
+                (array_info.btype, array_info.dims, max_index)
+            }
+        };
+        if index_count > max_index {
+            if max_index == 0 {
+                info.error(self.span, format!("`{}`不是数组", self.ident));
+            } else {
+                info.error(self.span, format!("数组`{}`的下标过多", self.ident));
+            }
+            Ok(ExpType::from_btype(btype, self.span))
+        } else if index_count == max_index {
+            Ok(ExpType::from_btype(btype, self.span))
+        } else if index_count == 0 {
+            Ok(ExpType::Array(btype, elem_dims, self.span))
+        } else {
+            Ok(ExpType::Array(
+                btype,
+                elem_dims[index_count..].to_vec(),
+                self.span,
+            ))
+        }
+    }
+}
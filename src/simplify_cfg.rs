@@ -0,0 +1,140 @@
+//! 化简控制流图
+//!
+//! 生成IR时if的两个分支都返回后仍然有%if_end，break/continue之后也会留下没有前驱的基本块，
+//! 这里删除不可达的基本块，把两个目标相同的br换成jump，跳过只有一条jump的基本块，
+//! 再把只有一个前驱的基本块合并到前驱里，反复进行直到不再变化
+use crate::ds_for_cfg::{terminator, Cfg};
+use crate::ds_for_opt::{remove_bbs, remove_insts, replace_uses, rewrite_edges};
+use koopa::ir::builder_traits::*;
+use koopa::ir::{BasicBlock, FunctionData, ValueKind};
+use std::collections::HashMap;
+
+///化简一个函数的控制流图，返回是否有改动
+pub fn simplify_cfg(func: &mut FunctionData) -> bool {
+    let mut changed = false;
+    loop {
+        let mut round = remove_unreachable(func);
+        round |= fold_branches(func);
+        round |= fold_jumps(func);
+        round |= merge_chains(func);
+        if !round {
+            return changed;
+        }
+        changed = true;
+    }
+}
+
+///删除从入口不可达的基本块
+fn remove_unreachable(func: &mut FunctionData) -> bool {
+    let cfg = Cfg::new(func);
+    let dead: Vec<BasicBlock> = func
+        .layout()
+        .bbs()
+        .keys()
+        .copied()
+        .filter(|&bb| !cfg.is_reachable(bb))
+        .collect();
+    remove_bbs(func, &dead);
+    !dead.is_empty()
+}
+
+///两个目标和参数都相同的br换成jump
+fn fold_branches(func: &mut FunctionData) -> bool {
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+    let mut changed = false;
+    for bb in bbs {
+        let Some(inst) = terminator(func, bb) else {
+            continue;
+        };
+        if let ValueKind::Branch(branch) = func.dfg().value(inst).kind() {
+            if branch.true_bb() == branch.false_bb() && branch.true_args() == branch.false_args() {
+                let (target, args) = (branch.true_bb(), branch.true_args().to_vec());
+                func.dfg_mut()
+                    .replace_value_with(inst)
+                    .jump_with_args(target, args);
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+///基本块只有一条jump时，让跳到它的指令直接跳到jump的目标，然后删掉它
+fn fold_jumps(func: &mut FunctionData) -> bool {
+    let entry = func.layout().entry_bb();
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+    let mut changed = false;
+    for bb in bbs {
+        if Some(bb) == entry || !func.dfg().bb(bb).params().is_empty() {
+            continue;
+        }
+        let insts = func.layout().bbs().node(&bb).unwrap().insts();
+        if insts.len() != 1 {
+            continue;
+        }
+        let (target, args) = match func.dfg().value(*insts.front_key().unwrap()).kind() {
+            ValueKind::Jump(jump) if jump.target() != bb => (jump.target(), jump.args().to_vec()),
+            _ => continue,
+        };
+        let preds: Vec<_> = func.dfg().bb(bb).used_by().iter().copied().collect();
+        for pred in preds {
+            rewrite_edges(func, pred, |to, to_args| {
+                if to == bb {
+                    (target, args.clone())
+                } else {
+                    (to, to_args)
+                }
+            });
+        }
+        remove_bbs(func, &[bb]);
+        changed = true;
+    }
+    changed
+}
+
+///前驱以jump结尾、自己只有这一个前驱的基本块，合并到前驱的末尾
+fn merge_chains(func: &mut FunctionData) -> bool {
+    let entry = func.layout().entry_bb();
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+    let mut changed = false;
+    for bb in bbs {
+        //bb可能已经被合并到别的基本块里
+        if func.layout().bbs().node(&bb).is_none() {
+            continue;
+        }
+        while let Some(jump) = terminator(func, bb) {
+            let (next, args) = match func.dfg().value(jump).kind() {
+                ValueKind::Jump(j) => (j.target(), j.args().to_vec()),
+                _ => break,
+            };
+            let next_data = func.dfg().bb(next);
+            if next == bb || Some(next) == entry || next_data.used_by().len() != 1 {
+                break;
+            }
+            //基本块参数直接换成传入的实参
+            let params: HashMap<_, _> = next_data.params().iter().copied().zip(args).collect();
+            remove_insts(func, &[jump]);
+            replace_uses(func, &params);
+            let insts: Vec<_> = func
+                .layout()
+                .bbs()
+                .node(&next)
+                .unwrap()
+                .insts()
+                .keys()
+                .copied()
+                .collect();
+            for inst in insts {
+                func.layout_mut().bb_mut(next).insts_mut().remove(&inst);
+                func.layout_mut()
+                    .bb_mut(bb)
+                    .insts_mut()
+                    .push_key_back(inst)
+                    .unwrap();
+            }
+            remove_bbs(func, &[next]);
+            changed = true;
+        }
+    }
+    changed
+}
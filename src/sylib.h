@@ -0,0 +1,16 @@
+// SysY运行时库的函数原型
+int getint();
+int getch();
+float getfloat();
+int getarray(int a[]);
+int getfarray(float a[]);
+void putint(int a);
+void putch(int a);
+void putfloat(float a);
+void putarray(int n, int a[]);
+void putfarray(int n, float a[]);
+// starttime()/stoptime()在调用处改写成下面两个函数，传入所在的行号
+void starttime();
+void stoptime();
+void _sysy_starttime(int lineno);
+void _sysy_stoptime(int lineno);
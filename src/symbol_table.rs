@@ -1,44 +1,135 @@
 //! 符号表
 
 use std::collections::HashMap;
+use std::rc::Rc;
 
+use crate::ast::BType;
 use crate::ast::FuncType;
 
-#[derive(Debug, Clone)] //移除了copy
-pub struct VarInfoBase {}
+///编译期常量的值
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConstValue {
+    Int(i32),
+    Float(f32),
+}
+
+impl ConstValue {
+    pub fn btype(self) -> BType {
+        match self {
+            ConstValue::Int(_) => BType::Int,
+            ConstValue::Float(_) => BType::Float,
+        }
+    }
+    ///按C的规则转换成int，浮点数向0截断
+    pub fn as_int(self) -> i32 {
+        match self {
+            ConstValue::Int(v) => v,
+            ConstValue::Float(v) => v as i32,
+        }
+    }
+    pub fn as_float(self) -> f32 {
+        match self {
+            ConstValue::Int(v) => v as f32,
+            ConstValue::Float(v) => v,
+        }
+    }
+    ///隐式转换成btype类型
+    pub fn convert(self, btype: BType) -> ConstValue {
+        match btype {
+            BType::Int => ConstValue::Int(self.as_int()),
+            BType::Float => ConstValue::Float(self.as_float()),
+        }
+    }
+    ///作为条件时的真假
+    pub fn is_true(self) -> bool {
+        match self {
+            ConstValue::Int(v) => v != 0,
+            ConstValue::Float(v) => v != 0.0,
+        }
+    }
+    ///在IR中的表示，浮点数用它的位模式
+    pub fn to_word(self) -> i32 {
+        match self {
+            ConstValue::Int(v) => v,
+            ConstValue::Float(v) => v.to_bits() as i32,
+        }
+    }
+    ///从IR中的表示还原
+    pub fn from_word(word: i32, btype: BType) -> ConstValue {
+        match btype {
+            BType::Int => ConstValue::Int(word),
+            BType::Float => ConstValue::Float(f32::from_bits(word as u32)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)] //移除了copy
+pub struct VarInfoBase {
+    pub btype: BType,
+}
+///函数形参的种类
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamKind {
+    Int,
+    Float,
+    Array(BType, Vec<i32>), //数组指针，vec为除第一维外的维度，vec:[] => *i32
+    Str,                    //字符串字面量，只有putf的格式串是这种形参
+}
 #[derive(Debug, Clone)]
 pub struct FuncInfoBase {
     pub ret_type: FuncType,
+    pub params: Vec<ParamKind>,
+    pub is_lib: bool,   //是否为SysY库函数
+    pub variadic: bool, //params之后还能接任意个int/float实参，只有putf是这样
 }
 impl FuncInfoBase {
-    pub fn new(ret_type: FuncType) -> Self {
-        FuncInfoBase { ret_type }
+    pub fn new(ret_type: FuncType, params: Vec<ParamKind>) -> Self {
+        FuncInfoBase {
+            ret_type,
+            params,
+            is_lib: false,
+            variadic: false,
+        }
+    }
+    pub fn new_lib(ret_type: FuncType, params: Vec<ParamKind>) -> Self {
+        FuncInfoBase {
+            ret_type,
+            params,
+            is_lib: true,
+            variadic: false,
+        }
     }
 }
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ArrayInfoBase {
+    pub btype: BType, //元素类型
     pub dims: Vec<i32>,
+    pub const_init: Option<Rc<Vec<i32>>>, //常量数组展平后在IR中的初值，非常量数组为None
 }
 #[derive(Debug, Clone)]
 pub enum SymbolInfo {
-    Const(i32),
+    Const(ConstValue),
     Var(VarInfoBase),
     Func(FuncInfoBase),
     Array(ArrayInfoBase),        //vec:[2,3] => [[i32, 3], 2]
     ArrayPointer(ArrayInfoBase), //vec:[2,3] => *[[i32, 3], 2] vec:[] => *i32
 }
 impl VarInfoBase {
-    pub fn new() -> Self {
-        VarInfoBase {}
+    pub fn new(btype: BType) -> Self {
+        VarInfoBase { btype }
     }
 }
 impl ArrayInfoBase {
     pub fn new() -> Self {
-        ArrayInfoBase { dims: Vec::new() }
+        ArrayInfoBase {
+            btype: BType::Int,
+            dims: Vec::new(),
+            const_init: None,
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct SymbolTable {
     table: HashMap<String, SymbolInfo>,
 }
@@ -57,4 +148,8 @@ impl SymbolTable {
     pub fn get(&self, key: &str) -> Option<&SymbolInfo> {
         self.table.get(key)
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &SymbolInfo)> {
+        self.table.iter()
+    }
 }
@@ -0,0 +1,303 @@
+//! 检查内存中的IR是否合法
+//!
+//! 生成IR或优化遍出bug时，在这里报告出错的函数和基本块，而不是等到生成汇编时才暴露出来。
+//! 检查的内容：每个基本块恰好以一条结束指令结尾，store/load/getelemptr等指令的类型匹配，
+//! 以及每个操作数的定义支配它的使用
+use crate::ds_for_cfg::{terminator, Cfg};
+use crate::error::{CompileError, Result};
+use koopa::ir::{BasicBlock, FunctionData, Program, Type, TypeKind, Value, ValueKind};
+use std::collections::HashMap;
+
+///检查整个程序，把发现的所有问题作为一个内部错误报告
+pub fn verify_program(program: &Program) -> Result<()> {
+    let mut errors = vec![];
+    for &func in program.func_layout() {
+        let func_data = program.func(func);
+        //跳过声明
+        if func_data.layout().entry_bb().is_none() {
+            continue;
+        }
+        FuncVerifier::new(program, func_data, &mut errors).run();
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(CompileError::internal(format!(
+            "IR验证失败:\n{}",
+            errors.join("\n")
+        )))
+    }
+}
+
+///检查一个函数时用到的信息
+struct FuncVerifier<'a> {
+    program: &'a Program,
+    func: &'a FunctionData,
+    cfg: Cfg,
+    position: HashMap<Value, (BasicBlock, usize)>, //指令所在的基本块和在块中的下标
+    block_params: HashMap<Value, BasicBlock>,      //基本块参数属于哪个基本块
+    errors: &'a mut Vec<String>,
+}
+
+impl<'a> FuncVerifier<'a> {
+    fn new(program: &'a Program, func: &'a FunctionData, errors: &'a mut Vec<String>) -> Self {
+        let mut position = HashMap::new();
+        let mut block_params = HashMap::new();
+        for (&bb, node) in func.layout().bbs() {
+            for (i, &inst) in node.insts().keys().enumerate() {
+                position.insert(inst, (bb, i));
+            }
+            for &param in func.dfg().bb(bb).params() {
+                block_params.insert(param, bb);
+            }
+        }
+        FuncVerifier {
+            program,
+            func,
+            cfg: Cfg::new(func),
+            position,
+            block_params,
+            errors,
+        }
+    }
+
+    ///记录一个错误，注明函数和基本块
+    fn error(&mut self, bb: BasicBlock, message: String) {
+        let bb_name = self.func.dfg().bb(bb).name().clone();
+        self.errors.push(format!(
+            "函数{}的基本块{}: {}",
+            self.func.name(),
+            bb_name.as_deref().unwrap_or("(未命名)"),
+            message
+        ));
+    }
+
+    fn run(&mut self) {
+        for (&bb, node) in self.func.layout().bbs() {
+            let insts: Vec<Value> = node.insts().keys().copied().collect();
+            self.check_terminator(bb, &insts);
+            for (i, &inst) in insts.iter().enumerate() {
+                self.check_types(bb, inst);
+                if self.cfg.is_reachable(bb) {
+                    self.check_dominance(bb, i, inst);
+                }
+            }
+        }
+    }
+
+    ///基本块必须以恰好一条br/jump/ret结尾
+    fn check_terminator(&mut self, bb: BasicBlock, insts: &[Value]) {
+        if terminator(self.func, bb).is_none() {
+            self.error(bb, "没有以br/jump/ret结尾".to_string());
+        }
+        let Some((_, body)) = insts.split_last() else {
+            return;
+        };
+        for &inst in body {
+            if let ValueKind::Branch(_) | ValueKind::Jump(_) | ValueKind::Return(_) =
+                self.func.dfg().value(inst).kind()
+            {
+                self.error(bb, "br/jump/ret之后还有指令".to_string());
+                return;
+            }
+        }
+    }
+
+    ///操作数的类型，操作数已经被删除时返回None
+    fn value_ty(&self, value: Value) -> Option<Type> {
+        if value.is_global() {
+            return Some(self.program.borrow_value(value).ty().clone());
+        }
+        let data = self.func.dfg().values().get(&value)?;
+        Some(data.ty().clone())
+    }
+
+    ///检查指令的操作数类型
+    fn check_types(&mut self, bb: BasicBlock, inst: Value) {
+        let data = self.func.dfg().value(inst);
+        let mut tys = vec![];
+        for value in data.kind().value_uses() {
+            match self.value_ty(value) {
+                Some(ty) => tys.push(ty),
+                None => {
+                    self.error(bb, format!("{}使用了已经删除的值", inst_name(data.kind())));
+                    return;
+                }
+            }
+        }
+        let i32_ty = Type::get_i32();
+        let message = match data.kind() {
+            ValueKind::Store(_) => match tys[1].kind() {
+                TypeKind::Pointer(base) if *base == tys[0] => None,
+                _ => Some(format!("store的值类型{}和目标类型{}不匹配", tys[0], tys[1])),
+            },
+            ValueKind::Load(_) => match tys[0].kind() {
+                TypeKind::Pointer(_) => None,
+                _ => Some(format!("load的源类型{}不是指针", tys[0])),
+            },
+            ValueKind::GetElemPtr(_) => match tys[0].kind() {
+                TypeKind::Pointer(base) if matches!(base.kind(), TypeKind::Array(..)) => {
+                    (tys[1] != i32_ty).then(|| format!("getelemptr的下标类型{}不是i32", tys[1]))
+                }
+                _ => Some(format!("getelemptr的源类型{}不是数组指针", tys[0])),
+            },
+            ValueKind::GetPtr(_) => match tys[0].kind() {
+                TypeKind::Pointer(_) => {
+                    (tys[1] != i32_ty).then(|| format!("getptr的下标类型{}不是i32", tys[1]))
+                }
+                _ => Some(format!("getptr的源类型{}不是指针", tys[0])),
+            },
+            ValueKind::Binary(binary) => (tys[0] != i32_ty || tys[1] != i32_ty).then(|| {
+                format!(
+                    "{:?}运算的操作数类型{}, {}不是i32",
+                    binary.op(),
+                    tys[0],
+                    tys[1]
+                )
+            }),
+            ValueKind::Branch(branch) => {
+                if tys[0] != i32_ty {
+                    Some(format!("br的条件类型{}不是i32", tys[0]))
+                } else {
+                    self.check_bb_args(bb, branch.true_bb(), branch.true_args());
+                    self.check_bb_args(bb, branch.false_bb(), branch.false_args());
+                    None
+                }
+            }
+            ValueKind::Jump(jump) => {
+                self.check_bb_args(bb, jump.target(), jump.args());
+                None
+            }
+            ValueKind::Call(call) => {
+                let callee = self.program.func(call.callee());
+                match callee.ty().kind() {
+                    TypeKind::Function(params, _) if *params == tys => None,
+                    _ => Some(format!(
+                        "调用{}的实参类型({})和形参类型{}不匹配",
+                        callee.name(),
+                        join_types(&tys),
+                        callee.ty()
+                    )),
+                }
+            }
+            ValueKind::Return(_) => {
+                let ret = match self.func.ty().kind() {
+                    TypeKind::Function(_, ret) => ret.clone(),
+                    _ => unreachable!(),
+                };
+                match tys.first() {
+                    Some(ty) if *ty != ret => {
+                        Some(format!("ret的值类型{}和返回类型{}不匹配", ty, ret))
+                    }
+                    None if !ret.is_unit() => Some(format!("返回类型为{}的函数ret没有值", ret)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        if let Some(message) = message {
+            self.error(bb, message);
+        }
+    }
+
+    ///跳转到target时传递的参数要和target的参数一一对应
+    fn check_bb_args(&mut self, bb: BasicBlock, target: BasicBlock, args: &[Value]) {
+        if self.func.layout().bbs().node(&target).is_none() {
+            self.error(bb, "跳转到了不在函数中的基本块".to_string());
+            return;
+        }
+        let target_data = self.func.dfg().bb(target);
+        let params: Vec<Type> = target_data
+            .params()
+            .iter()
+            .map(|&param| self.func.dfg().value(param).ty().clone())
+            .collect();
+        let args: Vec<Type> = args.iter().filter_map(|&arg| self.value_ty(arg)).collect();
+        if params != args {
+            let target_name = target_data.name().clone().unwrap_or_default();
+            self.error(
+                bb,
+                format!(
+                    "跳转到{}时传递的参数类型({})和基本块参数类型({})不匹配",
+                    target_name,
+                    join_types(&args),
+                    join_types(&params)
+                ),
+            );
+        }
+    }
+
+    ///操作数的定义必须支配使用它的指令
+    fn check_dominance(&mut self, bb: BasicBlock, index: usize, inst: Value) {
+        let data = self.func.dfg().value(inst);
+        for value in data.kind().value_uses() {
+            if value.is_global() {
+                continue;
+            }
+            let Some(value_data) = self.func.dfg().values().get(&value) else {
+                continue; //已经在类型检查中报告
+            };
+            let dominated = match value_data.kind() {
+                ValueKind::Integer(_)
+                | ValueKind::ZeroInit(_)
+                | ValueKind::Undef(_)
+                | ValueKind::Aggregate(_)
+                | ValueKind::FuncArgRef(_) => true,
+                ValueKind::BlockArgRef(_) => match self.block_params.get(&value) {
+                    Some(&def_bb) => {
+                        self.cfg.is_reachable(def_bb) && self.cfg.dominates(def_bb, bb)
+                    }
+                    None => false,
+                },
+                _ => match self.position.get(&value) {
+                    Some(&(def_bb, def_index)) if def_bb == bb => def_index < index,
+                    Some(&(def_bb, _)) => {
+                        self.cfg.is_reachable(def_bb) && self.cfg.dominates(def_bb, bb)
+                    }
+                    None => false,
+                },
+            };
+            if !dominated {
+                let message = format!(
+                    "{}的操作数{}的定义不支配这次使用",
+                    inst_name(data.kind()),
+                    value_name(self.func, value)
+                );
+                self.error(bb, message);
+            }
+        }
+    }
+}
+
+///指令的名字，用于错误信息
+fn inst_name(kind: &ValueKind) -> &'static str {
+    match kind {
+        ValueKind::Alloc(_) => "alloc",
+        ValueKind::Load(_) => "load",
+        ValueKind::Store(_) => "store",
+        ValueKind::GetPtr(_) => "getptr",
+        ValueKind::GetElemPtr(_) => "getelemptr",
+        ValueKind::Binary(_) => "二元运算",
+        ValueKind::Branch(_) => "br",
+        ValueKind::Jump(_) => "jump",
+        ValueKind::Call(_) => "call",
+        ValueKind::Return(_) => "ret",
+        _ => "值",
+    }
+}
+
+///操作数的名字，没有名字的只能给出种类
+fn value_name(func: &FunctionData, value: Value) -> String {
+    let data = func.dfg().value(value);
+    match data.name() {
+        Some(name) => name.clone(),
+        None => format!("({})", inst_name(data.kind())),
+    }
+}
+
+fn join_types(tys: &[Type]) -> String {
+    tys.iter()
+        .map(|ty| ty.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}